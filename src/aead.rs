@@ -0,0 +1,547 @@
+//! Authenticated encryption on top of `ring::aead`.
+//!
+//! Single messages are sealed into a self-describing envelope:
+//!
+//! ```notrust
+//!   version(1) | algorithm(1) | nonce(12) | ciphertext | tag(16)
+//! ```
+//!
+//! The header is authenticated along with the caller's associated data, so an
+//! envelope can't be relabelled as another algorithm.
+//!
+//! Large inputs go through `encrypt_stream`/`decrypt_stream`, which cut the
+//! plaintext into fixed size chunks (the STREAM construction):
+//!
+//! ```notrust
+//!   header: version(1) | algorithm(1) | prefix(7) | chunk size(4, BE)
+//!   chunk i: seal(nonce = prefix || i (4, BE) || last (1), aad = header || aad)
+//! ```
+//!
+//! Only the final chunk has `last = 1`, so reordering, dropping or truncating
+//! chunks makes decryption fail.
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::Zeroizing;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use std::io::{Read, Write};
+
+const VERSION: u8 = 1;
+pub const KEY_LEN: usize = 32;
+pub const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 2 + NONCE_LEN;
+const STREAM_PREFIX_LEN: usize = 7;
+const STREAM_HEADER_LEN: usize = 2 + STREAM_PREFIX_LEN + 4;
+/// Default plaintext chunk size for streaming, 64 KiB.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+/// Largest chunk size accepted, 16 MiB. The size comes from an unauthenticated header,
+/// so it bounds what `decrypt_stream` allocates.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AeadAlgorithm {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl AeadAlgorithm {
+    pub fn code(self) -> u8 {
+        match self {
+            AeadAlgorithm::Aes256Gcm => 1,
+            AeadAlgorithm::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<AeadAlgorithm> {
+        match code {
+            1 => Some(AeadAlgorithm::Aes256Gcm),
+            2 => Some(AeadAlgorithm::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn ring_algorithm(self) -> &'static ring::aead::Algorithm {
+        match self {
+            AeadAlgorithm::Aes256Gcm => &ring::aead::AES_256_GCM,
+            AeadAlgorithm::ChaCha20Poly1305 => &ring::aead::CHACHA20_POLY1305,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AeadError {
+    InvalidKeyLength,
+    /// envelope or stream is too short or malformed
+    InvalidEnvelope,
+    UnsupportedAlgorithm(u8),
+    /// the envelope was sealed with a different algorithm than the key
+    AlgorithmMismatch,
+    /// wrong key, wrong associated data or tampered ciphertext
+    DecryptionFailed,
+    /// the counter nonce space is used up, rekey
+    NonceExhausted,
+    Random(RandomError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for AeadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AeadError::InvalidKeyLength => write!(f, "aead keys are {} bytes", KEY_LEN),
+            AeadError::InvalidEnvelope => write!(f, "malformed ciphertext envelope"),
+            AeadError::UnsupportedAlgorithm(c) => write!(f, "unsupported aead algorithm {}", c),
+            AeadError::AlgorithmMismatch => write!(f, "ciphertext uses a different algorithm"),
+            AeadError::DecryptionFailed => write!(f, "decryption failed"),
+            AeadError::NonceExhausted => write!(f, "nonce space exhausted"),
+            AeadError::Random(e) => write!(f, "{}", e),
+            AeadError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AeadError {}
+
+impl From<RandomError> for AeadError {
+    fn from(e: RandomError) -> Self {
+        AeadError::Random(e)
+    }
+}
+
+/// How `AeadKey::seal` picks nonces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NonceMode {
+    /// 96 random bits per message. Fine up to roughly 2^32 messages per key.
+    Random,
+    /// `prefix || counter` (4 + 8 bytes). Never repeats as long as the prefix is
+    /// unique to this key and sender; the counter must not be reset.
+    Counter { prefix: [u8; 4], next: u64 },
+}
+
+pub struct AeadKey {
+    algorithm: AeadAlgorithm,
+    key: LessSafeKey,
+    nonce_mode: NonceMode,
+}
+
+impl AeadKey {
+    /// A key using random nonces.
+    pub fn new(algorithm: AeadAlgorithm, key: &[u8]) -> Result<AeadKey, AeadError> {
+        AeadKey::with_nonce_mode(algorithm, key, NonceMode::Random)
+    }
+
+    pub fn with_nonce_mode(
+        algorithm: AeadAlgorithm,
+        key: &[u8],
+        nonce_mode: NonceMode,
+    ) -> Result<AeadKey, AeadError> {
+        if key.len() != KEY_LEN {
+            return Err(AeadError::InvalidKeyLength);
+        }
+        let unbound = UnboundKey::new(algorithm.ring_algorithm(), key)
+            .map_err(|_| AeadError::InvalidKeyLength)?;
+        Ok(AeadKey {
+            algorithm,
+            key: LessSafeKey::new(unbound),
+            nonce_mode,
+        })
+    }
+
+    /// Fresh random key bytes, to be stored by the caller.
+    pub fn generate_key_bytes() -> Zeroizing<[u8; KEY_LEN]> {
+        AeadKey::generate_key_bytes_with_rng(&SystemRandom::new()).unwrap()
+    }
+
+    pub fn generate_key_bytes_with_rng(
+        rng: &dyn SecureRandom,
+    ) -> Result<Zeroizing<[u8; KEY_LEN]>, RandomError> {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        rng.fill(&mut *key)?;
+        Ok(key)
+    }
+
+    pub fn algorithm(&self) -> AeadAlgorithm {
+        self.algorithm
+    }
+
+    pub fn nonce_mode(&self) -> &NonceMode {
+        &self.nonce_mode
+    }
+
+    /// Encrypt `plaintext` into an envelope, authenticating `aad` as well.
+    pub fn seal(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, AeadError> {
+        self.seal_with_rng(plaintext, aad, &SystemRandom::new())
+    }
+
+    /// `seal`, drawing random nonces from `rng`. Counter nonces don't use it.
+    pub fn seal_with_rng(
+        &mut self,
+        plaintext: &[u8],
+        aad: &[u8],
+        rng: &dyn SecureRandom,
+    ) -> Result<Vec<u8>, AeadError> {
+        let nonce = self.next_nonce(rng)?;
+        let mut envelope = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+        envelope.push(VERSION);
+        envelope.push(self.algorithm.code());
+        envelope.extend_from_slice(&nonce);
+        let full_aad = [&envelope[..], aad].concat();
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&full_aad),
+                &mut in_out,
+            )
+            .map_err(|_| AeadError::DecryptionFailed)?;
+        envelope.extend_from_slice(&in_out);
+        Ok(envelope)
+    }
+
+    /// Decrypt an envelope produced by `seal` with the same key and `aad`.
+    pub fn open(&self, envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, AeadError> {
+        if envelope.len() < HEADER_LEN + TAG_LEN || envelope[0] != VERSION {
+            return Err(AeadError::InvalidEnvelope);
+        }
+        let algorithm = AeadAlgorithm::from_code(envelope[1])
+            .ok_or(AeadError::UnsupportedAlgorithm(envelope[1]))?;
+        if algorithm != self.algorithm {
+            return Err(AeadError::AlgorithmMismatch);
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&envelope[2..HEADER_LEN])
+            .map_err(|_| AeadError::InvalidEnvelope)?;
+        let full_aad = [&envelope[..HEADER_LEN], aad].concat();
+        let mut in_out = envelope[HEADER_LEN..].to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(&full_aad), &mut in_out)
+            .map_err(|_| AeadError::DecryptionFailed)?
+            .len();
+        in_out.truncate(len);
+        Ok(in_out)
+    }
+
+    fn next_nonce(&mut self, rng: &dyn SecureRandom) -> Result<[u8; NONCE_LEN], AeadError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        match &mut self.nonce_mode {
+            NonceMode::Random => rng.fill(&mut nonce)?,
+            NonceMode::Counter { prefix, next } => {
+                if *next == u64::MAX {
+                    return Err(AeadError::NonceExhausted);
+                }
+                nonce[..4].copy_from_slice(prefix);
+                nonce[4..].copy_from_slice(&next.to_be_bytes());
+                *next += 1;
+            }
+        }
+        Ok(nonce)
+    }
+}
+
+impl std::fmt::Debug for AeadKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AeadKey")
+            .field("algorithm", &self.algorithm)
+            .field("nonce_mode", &self.nonce_mode)
+            .finish()
+    }
+}
+
+/// Encrypt everything from `reader` into `writer` in chunks of `chunk_size` bytes.
+/// Returns the number of plaintext bytes read.
+pub fn encrypt_stream<R: Read, W: Write>(
+    key: &AeadKey,
+    reader: &mut R,
+    writer: &mut W,
+    aad: &[u8],
+    chunk_size: u32,
+) -> Result<u64, AeadError> {
+    encrypt_stream_with_rng(key, reader, writer, aad, chunk_size, &SystemRandom::new())
+}
+
+/// `encrypt_stream` with the nonce prefix drawn from `rng`.
+pub fn encrypt_stream_with_rng<R: Read, W: Write>(
+    key: &AeadKey,
+    reader: &mut R,
+    writer: &mut W,
+    aad: &[u8],
+    chunk_size: u32,
+    rng: &dyn SecureRandom,
+) -> Result<u64, AeadError> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(AeadError::InvalidEnvelope);
+    }
+    let mut prefix = [0u8; STREAM_PREFIX_LEN];
+    rng.fill(&mut prefix)?;
+    let mut header = vec![VERSION, key.algorithm.code()];
+    header.extend_from_slice(&prefix);
+    header.extend_from_slice(&chunk_size.to_be_bytes());
+    writer.write_all(&header).map_err(AeadError::Io)?;
+    let full_aad = [&header[..], aad].concat();
+
+    let chunk_size = chunk_size as usize;
+    let mut current = vec![0u8; chunk_size];
+    let mut current_len = read_full(reader, &mut current)?;
+    let mut next = vec![0u8; chunk_size];
+    let mut total = 0u64;
+    let mut index = 0u32;
+    loop {
+        // a short chunk is always last, a full one is last only if nothing follows
+        let next_len = if current_len == chunk_size {
+            read_full(reader, &mut next)?
+        } else {
+            0
+        };
+        let last = next_len == 0;
+        let mut in_out = current[..current_len].to_vec();
+        key.key
+            .seal_in_place_append_tag(
+                stream_nonce(&prefix, index, last),
+                Aad::from(&full_aad),
+                &mut in_out,
+            )
+            .map_err(|_| AeadError::DecryptionFailed)?;
+        writer.write_all(&in_out).map_err(AeadError::Io)?;
+        total += current_len as u64;
+        if last {
+            break;
+        }
+        index = index.checked_add(1).ok_or(AeadError::NonceExhausted)?;
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+    writer.flush().map_err(AeadError::Io)?;
+    Ok(total)
+}
+
+/// Decrypt a stream written by `encrypt_stream`. Returns the number of plaintext bytes written.
+/// Plaintext of earlier chunks is written before later chunks are checked, so on error the
+/// output must be discarded.
+pub fn decrypt_stream<R: Read, W: Write>(
+    key: &AeadKey,
+    reader: &mut R,
+    writer: &mut W,
+    aad: &[u8],
+) -> Result<u64, AeadError> {
+    let mut header = [0u8; STREAM_HEADER_LEN];
+    if read_full(reader, &mut header)? != STREAM_HEADER_LEN || header[0] != VERSION {
+        return Err(AeadError::InvalidEnvelope);
+    }
+    let algorithm =
+        AeadAlgorithm::from_code(header[1]).ok_or(AeadError::UnsupportedAlgorithm(header[1]))?;
+    if algorithm != key.algorithm {
+        return Err(AeadError::AlgorithmMismatch);
+    }
+    let mut prefix = [0u8; STREAM_PREFIX_LEN];
+    prefix.copy_from_slice(&header[2..2 + STREAM_PREFIX_LEN]);
+    let mut size_bytes = [0u8; 4];
+    size_bytes.copy_from_slice(&header[2 + STREAM_PREFIX_LEN..]);
+    let chunk_size = u32::from_be_bytes(size_bytes);
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(AeadError::InvalidEnvelope);
+    }
+    let chunk_size = chunk_size as usize;
+    let full_aad = [&header[..], aad].concat();
+
+    let sealed_size = chunk_size + TAG_LEN;
+    let mut current = vec![0u8; sealed_size];
+    let mut current_len = read_full(reader, &mut current)?;
+    let mut next = vec![0u8; sealed_size];
+    let mut total = 0u64;
+    let mut index = 0u32;
+    loop {
+        if current_len < TAG_LEN {
+            return Err(AeadError::DecryptionFailed);
+        }
+        let next_len = if current_len == sealed_size {
+            read_full(reader, &mut next)?
+        } else {
+            0
+        };
+        let last = next_len == 0;
+        let plaintext = key
+            .key
+            .open_in_place(
+                stream_nonce(&prefix, index, last),
+                Aad::from(&full_aad),
+                &mut current[..current_len],
+            )
+            .map_err(|_| AeadError::DecryptionFailed)?;
+        writer.write_all(plaintext).map_err(AeadError::Io)?;
+        total += plaintext.len() as u64;
+        if last {
+            break;
+        }
+        index = index.checked_add(1).ok_or(AeadError::NonceExhausted)?;
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+    writer.flush().map_err(AeadError::Io)?;
+    Ok(total)
+}
+
+fn stream_nonce(prefix: &[u8; STREAM_PREFIX_LEN], index: u32, last: bool) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..STREAM_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[STREAM_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+// like read_exact, but a short read at end of input is fine
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, AeadError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(AeadError::Io(e)),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [AeadAlgorithm; 2] =
+        [AeadAlgorithm::Aes256Gcm, AeadAlgorithm::ChaCha20Poly1305];
+
+    #[test]
+    fn seal_open_round_trip() {
+        for &algorithm in ALGORITHMS.iter() {
+            let mut key = AeadKey::new(algorithm, &AeadKey::generate_key_bytes()[..]).unwrap();
+            let envelope = key.seal(b"attack at dawn", b"header").unwrap();
+            assert_eq!(envelope[1], algorithm.code());
+            assert_eq!(envelope.len(), HEADER_LEN + 14 + TAG_LEN);
+            assert_eq!(key.open(&envelope, b"header").unwrap(), b"attack at dawn");
+            // fresh nonce every time
+            assert_ne!(key.seal(b"attack at dawn", b"header").unwrap(), envelope);
+        }
+    }
+
+    #[test]
+    fn tampering_rejected() {
+        let key_bytes = AeadKey::generate_key_bytes();
+        let mut key = AeadKey::new(AeadAlgorithm::Aes256Gcm, &key_bytes[..]).unwrap();
+        let envelope = key.seal(b"payload", b"aad").unwrap();
+        assert!(key.open(&envelope, b"other aad").is_err());
+        for i in 0..envelope.len() {
+            let mut flipped = envelope.clone();
+            flipped[i] ^= 1;
+            assert!(key.open(&flipped, b"aad").is_err(), "byte {}", i);
+        }
+        assert!(key.open(&envelope[..HEADER_LEN + 3], b"aad").is_err());
+
+        let other = AeadKey::new(AeadAlgorithm::ChaCha20Poly1305, &key_bytes[..]).unwrap();
+        match other.open(&envelope, b"aad") {
+            Err(AeadError::AlgorithmMismatch) => {}
+            e => panic!("unexpected {:?}", e),
+        }
+        assert!(AeadKey::new(AeadAlgorithm::Aes256Gcm, &[0u8; 16]).is_err());
+    }
+
+    #[test]
+    fn counter_nonces() {
+        let mut key = AeadKey::with_nonce_mode(
+            AeadAlgorithm::ChaCha20Poly1305,
+            &[9u8; 32],
+            NonceMode::Counter {
+                prefix: *b"node",
+                next: 7,
+            },
+        )
+        .unwrap();
+        let envelope = key.seal(b"m", b"").unwrap();
+        assert_eq!(&envelope[2..6], b"node");
+        assert_eq!(&envelope[6..14], &7u64.to_be_bytes());
+        assert_eq!(
+            key.nonce_mode(),
+            &NonceMode::Counter {
+                prefix: *b"node",
+                next: 8
+            }
+        );
+        assert_eq!(key.open(&envelope, b"").unwrap(), b"m");
+
+        let mut exhausted = AeadKey::with_nonce_mode(
+            AeadAlgorithm::Aes256Gcm,
+            &[9u8; 32],
+            NonceMode::Counter {
+                prefix: [0; 4],
+                next: u64::MAX,
+            },
+        )
+        .unwrap();
+        assert!(exhausted.seal(b"m", b"").is_err());
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7) as u8).collect();
+        for &algorithm in ALGORITHMS.iter() {
+            let key = AeadKey::new(algorithm, &AeadKey::generate_key_bytes()[..]).unwrap();
+            // empty, shorter than a chunk, exact multiple, and ragged tail
+            for &len in [0usize, 10, 4096, 10_000].iter() {
+                let mut sealed = vec![];
+                let written =
+                    encrypt_stream(&key, &mut &data[..len], &mut sealed, b"file", 1024).unwrap();
+                assert_eq!(written, len as u64);
+                let mut opened = vec![];
+                decrypt_stream(&key, &mut &sealed[..], &mut opened, b"file").unwrap();
+                assert_eq!(opened, &data[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn stream_truncation_and_reordering_rejected() {
+        let data = vec![0x5au8; 3000];
+        let key =
+            AeadKey::new(AeadAlgorithm::Aes256Gcm, &AeadKey::generate_key_bytes()[..]).unwrap();
+        let mut sealed = vec![];
+        encrypt_stream(&key, &mut &data[..], &mut sealed, b"", 1000).unwrap();
+        let chunk = 1000 + TAG_LEN;
+        assert_eq!(sealed.len(), STREAM_HEADER_LEN + 3 * chunk);
+
+        // drop the final chunk: the new last chunk was not sealed as last
+        let truncated = &sealed[..STREAM_HEADER_LEN + 2 * chunk];
+        assert!(decrypt_stream(&key, &mut &truncated[..], &mut vec![], b"").is_err());
+
+        // swap the first two chunks
+        let mut swapped = sealed[..STREAM_HEADER_LEN].to_vec();
+        swapped
+            .extend_from_slice(&sealed[STREAM_HEADER_LEN + chunk..STREAM_HEADER_LEN + 2 * chunk]);
+        swapped.extend_from_slice(&sealed[STREAM_HEADER_LEN..STREAM_HEADER_LEN + chunk]);
+        swapped.extend_from_slice(&sealed[STREAM_HEADER_LEN + 2 * chunk..]);
+        assert!(decrypt_stream(&key, &mut &swapped[..], &mut vec![], b"").is_err());
+
+        // wrong associated data
+        assert!(decrypt_stream(&key, &mut &sealed[..], &mut vec![], b"x").is_err());
+    }
+
+    #[test]
+    fn stream_chunk_size_bounded() {
+        let key =
+            AeadKey::new(AeadAlgorithm::Aes256Gcm, &AeadKey::generate_key_bytes()[..]).unwrap();
+        assert!(matches!(
+            encrypt_stream(
+                &key,
+                &mut &b"data"[..],
+                &mut vec![],
+                b"",
+                MAX_CHUNK_SIZE + 1
+            ),
+            Err(AeadError::InvalidEnvelope)
+        ));
+
+        // a forged header claiming 4 GiB chunks is rejected before anything is allocated
+        let mut sealed = vec![];
+        encrypt_stream(&key, &mut &b"data"[..], &mut sealed, b"", 1024).unwrap();
+        sealed[2 + STREAM_PREFIX_LEN..STREAM_HEADER_LEN].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decrypt_stream(&key, &mut &sealed[..], &mut vec![], b""),
+            Err(AeadError::InvalidEnvelope)
+        ));
+    }
+}
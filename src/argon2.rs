@@ -0,0 +1,669 @@
+//! ``` notrust
+//! Argon2id memory-hard hashing, RFC 9106 (version 0x13).
+//!
+//! memory is split into p lanes of q 1 KiB blocks, each lane into 4 slices:
+//!
+//!          slice 0   slice 1   slice 2   slice 3
+//! lane 0  |B B B B | B B B B | B B B B | B B B B |
+//! lane 1  |B B B B | B B B B | B B B B | B B B B |
+//!
+//! every block is G(previous block, some earlier block), t passes over memory.
+//! Argon2id picks the earlier block independently of the data for the first
+//! half of the first pass (side-channel resistant) and data-dependently after
+//! that (GPU/ASIC tradeoff resistant).
+//! ```
+//!
+//! Lanes are filled one after another on the calling thread.
+
+use crate::hash::H256;
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{constant_time_eq, Zeroize};
+use blake2::digest::{Input, VariableOutput};
+use blake2::VarBlake2b;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+const VERSION: u32 = 0x13;
+// Argon2id type code y
+const ARGON2ID: u32 = 2;
+const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: usize = 4;
+// salt used when Argon2id is run as a PoW function, where there is no secret salt
+const POW_SALT: &[u8] = b"crypto_lib/argon2id/pow";
+
+/// Largest memory size accepted, 4 GiB.
+pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+/// Largest number of passes accepted.
+pub const MAX_TIME: u32 = 64;
+/// Largest degree of parallelism accepted.
+pub const MAX_LANES: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Argon2Params {
+    /// memory size m in KiB
+    pub memory_kib: u32,
+    /// number of passes t
+    pub iterations: u32,
+    /// degree of parallelism p
+    pub lanes: u32,
+    /// tag length T in bytes
+    pub output_len: u32,
+}
+
+impl Default for Argon2Params {
+    /// RFC 9106 second recommended option: 64 MiB, t = 3, p = 4.
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            lanes: 4,
+            output_len: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argon2Error {
+    InvalidParams(&'static str),
+    InvalidPhcString,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for Argon2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Argon2Error::InvalidParams(reason) => {
+                write!(f, "invalid Argon2 parameters: {}", reason)
+            }
+            Argon2Error::InvalidPhcString => write!(f, "invalid PHC string"),
+            Argon2Error::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Argon2Error {}
+
+impl From<RandomError> for Argon2Error {
+    fn from(e: RandomError) -> Self {
+        Argon2Error::Random(e)
+    }
+}
+
+impl Argon2Params {
+    fn validate(&self, salt: &[u8]) -> Result<(), Argon2Error> {
+        self.check_bounds()?;
+        if self.memory_kib < 8 * self.lanes {
+            return Err(Argon2Error::InvalidParams(
+                "memory must be at least 8 KiB per lane",
+            ));
+        }
+        if self.output_len < 4 {
+            return Err(Argon2Error::InvalidParams(
+                "output must be at least 4 bytes",
+            ));
+        }
+        if salt.len() < 8 {
+            return Err(Argon2Error::InvalidParams("salt must be at least 8 bytes"));
+        }
+        Ok(())
+    }
+
+    // upper bounds keep untrusted parameters from exhausting memory or time
+    fn check_bounds(&self) -> Result<(), Argon2Error> {
+        if self.lanes == 0 || self.lanes > MAX_LANES {
+            return Err(Argon2Error::InvalidParams("lanes must be in 1..=MAX_LANES"));
+        }
+        if self.iterations == 0 || self.iterations > MAX_TIME {
+            return Err(Argon2Error::InvalidParams(
+                "iterations must be in 1..=MAX_TIME",
+            ));
+        }
+        if self.memory_kib > MAX_MEMORY_KIB {
+            return Err(Argon2Error::InvalidParams(
+                "memory must be at most MAX_MEMORY_KIB",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parameters for `pow_hash`, checked once up front so hashing cannot fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PowParams(Argon2Params);
+
+impl PowParams {
+    /// `output_len` is ignored, PoW tags are always 32 bytes.
+    pub fn new(params: Argon2Params) -> Result<Self, Argon2Error> {
+        let params = Argon2Params {
+            output_len: 32,
+            ..params
+        };
+        params.validate(POW_SALT)?;
+        Ok(PowParams(params))
+    }
+
+    pub fn params(&self) -> &Argon2Params {
+        &self.0
+    }
+}
+
+type Block = [u64; BLOCK_WORDS];
+
+/// plain BLAKE2b with an `out_len` byte digest over the concatenated inputs
+fn blake2b(out_len: usize, inputs: &[&[u8]]) -> Vec<u8> {
+    let mut ctx = VarBlake2b::new(out_len).unwrap();
+    for input in inputs.iter() {
+        ctx.input(input);
+    }
+    let mut out = Vec::with_capacity(out_len);
+    ctx.variable_result(|res| out.extend_from_slice(res));
+    out
+}
+
+/// H'^T: BLAKE2b stretched to `out_len` bytes
+fn blake2b_long(out_len: usize, inputs: &[&[u8]]) -> Vec<u8> {
+    let len_prefix = (out_len as u32).to_le_bytes();
+    let mut prefixed: Vec<&[u8]> = vec![&len_prefix];
+    prefixed.extend_from_slice(inputs);
+    if out_len <= 64 {
+        return blake2b(out_len, &prefixed);
+    }
+    // r = ceil(T/32) - 2 full 64-byte hashes keeping 32 bytes each,
+    // then one last hash of the remaining length
+    let r = out_len.div_ceil(32) - 2;
+    let mut out = Vec::with_capacity(out_len);
+    let mut v = blake2b(64, &prefixed);
+    out.extend_from_slice(&v[..32]);
+    for _ in 1..r {
+        v = blake2b(64, &[&v]);
+        out.extend_from_slice(&v[..32]);
+    }
+    out.extend_from_slice(&blake2b(out_len - 32 * r, &[&v]));
+    out
+}
+
+/// fBlaMka: x + y + 2 * lo32(x) * lo32(y)
+fn blamka(x: u64, y: u64) -> u64 {
+    let product = (x & 0xffff_ffff).wrapping_mul(y & 0xffff_ffff);
+    x.wrapping_add(y).wrapping_add(product.wrapping_mul(2))
+}
+
+fn mix(v: &mut Block, a: usize, b: usize, c: usize, d: usize) {
+    v[a] = blamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = blamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = blamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = blamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// one BLAKE2 round over 16 words picked out of the block
+fn round(v: &mut Block, idx: &[usize; 16]) {
+    mix(v, idx[0], idx[4], idx[8], idx[12]);
+    mix(v, idx[1], idx[5], idx[9], idx[13]);
+    mix(v, idx[2], idx[6], idx[10], idx[14]);
+    mix(v, idx[3], idx[7], idx[11], idx[15]);
+    mix(v, idx[0], idx[5], idx[10], idx[15]);
+    mix(v, idx[1], idx[6], idx[11], idx[12]);
+    mix(v, idx[2], idx[7], idx[8], idx[13]);
+    mix(v, idx[3], idx[4], idx[9], idx[14]);
+}
+
+/// compression function G(X, Y), optionally xor-ed into the old value of `next` (passes > 0)
+fn compress(prev: &Block, reference: &Block, next: &mut Block, with_xor: bool) {
+    let mut r: Block = [0u64; BLOCK_WORDS];
+    for i in 0..BLOCK_WORDS {
+        r[i] = prev[i] ^ reference[i];
+    }
+    let mut tmp = r;
+    if with_xor {
+        for i in 0..BLOCK_WORDS {
+            tmp[i] ^= next[i];
+        }
+    }
+    // rows: 8 consecutive groups of 16 words
+    for row in 0..8 {
+        let mut idx = [0usize; 16];
+        for (j, slot) in idx.iter_mut().enumerate() {
+            *slot = 16 * row + j;
+        }
+        round(&mut r, &idx);
+    }
+    // columns: 2 adjacent words out of every row
+    for col in 0..8 {
+        let mut idx = [0usize; 16];
+        for (j, slot) in idx.iter_mut().enumerate() {
+            *slot = 2 * col + (j / 2) * 16 + (j % 2);
+        }
+        round(&mut r, &idx);
+    }
+    for i in 0..BLOCK_WORDS {
+        next[i] = tmp[i] ^ r[i];
+    }
+}
+
+fn block_from_bytes(bytes: &[u8]) -> Block {
+    let mut block: Block = [0u64; BLOCK_WORDS];
+    for (i, word) in bytes.chunks(8).enumerate() {
+        block[i] = u64::from_le_bytes(word.try_into().unwrap());
+    }
+    block
+}
+
+fn block_to_bytes(block: &Block) -> Vec<u8> {
+    block
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect()
+}
+
+struct Position {
+    pass: usize,
+    lane: usize,
+    slice: usize,
+}
+
+struct Instance {
+    memory: Vec<Block>,
+    lanes: usize,
+    lane_length: usize,
+    segment_length: usize,
+    passes: usize,
+}
+
+impl Instance {
+    /// map the 32-bit pseudo random J1 onto a block index in the reference lane
+    fn index_alpha(&self, position: &Position, index: usize, j1: u64, same_lane: bool) -> usize {
+        let segment = self.segment_length;
+        let area_size = if position.pass == 0 {
+            if position.slice == 0 {
+                index - 1
+            } else if same_lane {
+                position.slice * segment + index - 1
+            } else if index == 0 {
+                position.slice * segment - 1
+            } else {
+                position.slice * segment
+            }
+        } else if same_lane {
+            self.lane_length - segment + index - 1
+        } else if index == 0 {
+            self.lane_length - segment - 1
+        } else {
+            self.lane_length - segment
+        };
+        let area_size = area_size as u64;
+        let x = (j1 * j1) >> 32;
+        let relative = area_size - 1 - ((area_size * x) >> 32);
+        let start = if position.pass != 0 && position.slice != SYNC_POINTS - 1 {
+            (position.slice + 1) * segment
+        } else {
+            0
+        };
+        (start + relative as usize) % self.lane_length
+    }
+
+    fn fill_segment(&mut self, position: &Position) {
+        let zero: Block = [0u64; BLOCK_WORDS];
+        let data_independent = position.pass == 0 && position.slice < SYNC_POINTS / 2;
+        let mut input: Block = [0u64; BLOCK_WORDS];
+        let mut addresses: Block = [0u64; BLOCK_WORDS];
+        if data_independent {
+            input[0] = position.pass as u64;
+            input[1] = position.lane as u64;
+            input[2] = position.slice as u64;
+            input[3] = self.memory.len() as u64;
+            input[4] = self.passes as u64;
+            input[5] = ARGON2ID as u64;
+        }
+        let next_addresses = |input: &mut Block, addresses: &mut Block| {
+            input[6] += 1;
+            let mut tmp: Block = [0u64; BLOCK_WORDS];
+            compress(&zero, input, &mut tmp, false);
+            compress(&zero, &tmp, addresses, false);
+        };
+
+        // the first two blocks of each lane come from H0
+        let start_index = if position.pass == 0 && position.slice == 0 {
+            if data_independent {
+                next_addresses(&mut input, &mut addresses);
+            }
+            2
+        } else {
+            0
+        };
+
+        let lane_start = position.lane * self.lane_length;
+        let segment_start = lane_start + position.slice * self.segment_length;
+        for index in start_index..self.segment_length {
+            let curr = segment_start + index;
+            let prev = if curr == lane_start {
+                lane_start + self.lane_length - 1
+            } else {
+                curr - 1
+            };
+            let pseudo_rand = if data_independent {
+                if index % BLOCK_WORDS == 0 {
+                    next_addresses(&mut input, &mut addresses);
+                }
+                addresses[index % BLOCK_WORDS]
+            } else {
+                self.memory[prev][0]
+            };
+            let ref_lane = if position.pass == 0 && position.slice == 0 {
+                position.lane
+            } else {
+                ((pseudo_rand >> 32) % self.lanes as u64) as usize
+            };
+            let ref_index = self.index_alpha(
+                position,
+                index,
+                pseudo_rand & 0xffff_ffff,
+                ref_lane == position.lane,
+            );
+            let reference = self.memory[ref_lane * self.lane_length + ref_index];
+            let previous = self.memory[prev];
+            compress(
+                &previous,
+                &reference,
+                &mut self.memory[curr],
+                position.pass > 0,
+            );
+        }
+    }
+}
+
+/// Raw Argon2id: the tag for `password` under `salt`, optional `secret` key K and
+/// associated data X.
+pub fn argon2id(
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    associated_data: &[u8],
+    params: &Argon2Params,
+) -> Result<Vec<u8>, Argon2Error> {
+    params.validate(salt)?;
+    let lanes = params.lanes as usize;
+    // m' = 4 * p * floor(m / 4p)
+    let memory_blocks = 4 * lanes * (params.memory_kib as usize / (4 * lanes));
+    let lane_length = memory_blocks / lanes;
+
+    let mut h0_input: Vec<u8> = Vec::new();
+    for value in [
+        params.lanes,
+        params.output_len,
+        params.memory_kib,
+        params.iterations,
+        VERSION,
+        ARGON2ID,
+    ]
+    .iter()
+    {
+        h0_input.extend_from_slice(&value.to_le_bytes());
+    }
+    for field in [password, salt, secret, associated_data].iter() {
+        h0_input.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        h0_input.extend_from_slice(field);
+    }
+    let h0 = blake2b(64, &[&h0_input]);
+
+    let mut instance = Instance {
+        memory: vec![[0u64; BLOCK_WORDS]; memory_blocks],
+        lanes,
+        lane_length,
+        segment_length: lane_length / SYNC_POINTS,
+        passes: params.iterations as usize,
+    };
+    for lane in 0..lanes {
+        let lane_bytes = (lane as u32).to_le_bytes();
+        for i in 0..2u32 {
+            let block = blake2b_long(1024, &[&h0, &i.to_le_bytes(), &lane_bytes]);
+            instance.memory[lane * lane_length + i as usize] = block_from_bytes(&block);
+        }
+    }
+
+    for pass in 0..instance.passes {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                instance.fill_segment(&Position { pass, lane, slice });
+            }
+        }
+    }
+
+    // xor the last column together and stretch it into the tag
+    let mut last = instance.memory[lane_length - 1];
+    for lane in 1..lanes {
+        let block = &instance.memory[lane * lane_length + lane_length - 1];
+        for (word, other) in last.iter_mut().zip(block.iter()) {
+            *word ^= other;
+        }
+    }
+    let tag = blake2b_long(params.output_len as usize, &[&block_to_bytes(&last)]);
+    for block in instance.memory.iter_mut() {
+        block.zeroize();
+    }
+    last.zeroize();
+    Ok(tag)
+}
+
+/// Hash a password for storage, returning a PHC string like
+/// `$argon2id$v=19$m=65536,t=3,p=4$<salt>$<hash>`.
+pub fn hash_password(password: &[u8], params: &Argon2Params) -> Result<String, Argon2Error> {
+    hash_password_with_rng(password, params, &SystemRandom::new())
+}
+
+/// `hash_password` with the salt drawn from `rng`.
+pub fn hash_password_with_rng(
+    password: &[u8],
+    params: &Argon2Params,
+    rng: &dyn SecureRandom,
+) -> Result<String, Argon2Error> {
+    let mut salt = [0u8; 16];
+    rng.fill(&mut salt)?;
+    hash_password_with_salt(password, &salt, params)
+}
+
+pub fn hash_password_with_salt(
+    password: &[u8],
+    salt: &[u8],
+    params: &Argon2Params,
+) -> Result<String, Argon2Error> {
+    let tag = argon2id(password, salt, &[], &[], params)?;
+    Ok(format!(
+        "$argon2id$v={}$m={},t={},p={}${}${}",
+        VERSION,
+        params.memory_kib,
+        params.iterations,
+        params.lanes,
+        base64::encode_config(salt, base64::STANDARD_NO_PAD),
+        base64::encode_config(&tag, base64::STANDARD_NO_PAD)
+    ))
+}
+
+/// Split a PHC string into its parameters, salt and expected tag.
+pub fn parse_phc(phc: &str) -> Result<(Argon2Params, Vec<u8>, Vec<u8>), Argon2Error> {
+    let fields: Vec<&str> = phc.split('$').collect();
+    // leading '$' gives an empty first field
+    if fields.len() != 6 || !fields[0].is_empty() || fields[1] != "argon2id" {
+        return Err(Argon2Error::InvalidPhcString);
+    }
+    if fields[2] != format!("v={}", VERSION) {
+        return Err(Argon2Error::InvalidPhcString);
+    }
+    // every key exactly once, so a hash never verifies under parameters nobody wrote
+    let mut memory_kib: Option<u32> = None;
+    let mut iterations: Option<u32> = None;
+    let mut lanes: Option<u32> = None;
+    for pair in fields[3].split(',') {
+        let mut kv = pair.splitn(2, '=');
+        let key = kv.next().ok_or(Argon2Error::InvalidPhcString)?;
+        let value: u32 = kv
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or(Argon2Error::InvalidPhcString)?;
+        let slot = match key {
+            "m" => &mut memory_kib,
+            "t" => &mut iterations,
+            "p" => &mut lanes,
+            _ => return Err(Argon2Error::InvalidPhcString),
+        };
+        if slot.replace(value).is_some() {
+            return Err(Argon2Error::InvalidPhcString);
+        }
+    }
+    let mut params = match (memory_kib, iterations, lanes) {
+        (Some(memory_kib), Some(iterations), Some(lanes)) => Argon2Params {
+            memory_kib,
+            iterations,
+            lanes,
+            ..Argon2Params::default()
+        },
+        _ => return Err(Argon2Error::InvalidPhcString),
+    };
+    params.check_bounds()?;
+    let salt = base64::decode_config(fields[4], base64::STANDARD_NO_PAD)
+        .map_err(|_| Argon2Error::InvalidPhcString)?;
+    let tag = base64::decode_config(fields[5], base64::STANDARD_NO_PAD)
+        .map_err(|_| Argon2Error::InvalidPhcString)?;
+    params.output_len = tag.len() as u32;
+    Ok((params, salt, tag))
+}
+
+/// Check `password` against a stored PHC string, comparing tags in constant time.
+pub fn verify_password(password: &[u8], phc: &str) -> Result<bool, Argon2Error> {
+    let (params, salt, expected) = parse_phc(phc)?;
+    let tag = argon2id(password, &salt, &[], &[], &params)?;
+    Ok(constant_time_eq(&tag, &expected))
+}
+
+/// Argon2id as a memory-hard PoW function: a fixed public salt and a 32-byte tag.
+pub fn pow_hash(input: &[u8], params: &PowParams) -> H256 {
+    let tag = argon2id(input, POW_SALT, &[], &[], &params.0)
+        .expect("PowParams are validated on construction");
+    H256::from(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_params() -> Argon2Params {
+        Argon2Params {
+            memory_kib: 64,
+            iterations: 2,
+            lanes: 1,
+            output_len: 32,
+        }
+    }
+
+    #[test]
+    fn rfc9106_argon2id_vector() {
+        // RFC 9106 section 5.3
+        let params = Argon2Params {
+            memory_kib: 32,
+            iterations: 3,
+            lanes: 4,
+            output_len: 32,
+        };
+        let tag = argon2id(&[0x01; 32], &[0x02; 16], &[0x03; 8], &[0x04; 12], &params).unwrap();
+        assert_eq!(
+            tag,
+            hex!("0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659").to_vec()
+        );
+    }
+
+    #[test]
+    fn phc_string_known_answer() {
+        let phc = hash_password_with_salt(b"password", b"somesalt", &small_params()).unwrap();
+        assert_eq!(
+            phc,
+            "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ$FqGkmHNGCd0BRW2kBt6fPZ2pPmyGwwChL8FGUhTOSSI"
+        );
+        assert_eq!(verify_password(b"password", &phc), Ok(true));
+        assert_eq!(verify_password(b"Password", &phc), Ok(false));
+    }
+
+    #[test]
+    fn random_salt_round_trip() {
+        let params = small_params();
+        let phc1 = hash_password(b"hunter2", &params).unwrap();
+        let phc2 = hash_password(b"hunter2", &params).unwrap();
+        assert_ne!(phc1, phc2);
+        assert_eq!(verify_password(b"hunter2", &phc1), Ok(true));
+        assert_eq!(verify_password(b"hunter2", &phc2), Ok(true));
+    }
+
+    #[test]
+    fn long_output() {
+        // output longer than 64 bytes goes through the chained H' construction
+        let params = Argon2Params {
+            output_len: 100,
+            ..small_params()
+        };
+        let tag = argon2id(b"password", b"somesalt", &[], &[], &params).unwrap();
+        assert_eq!(tag.len(), 100);
+        let phc = hash_password_with_salt(b"password", b"somesalt", &params).unwrap();
+        assert_eq!(verify_password(b"password", &phc), Ok(true));
+    }
+
+    #[test]
+    fn bad_inputs_rejected() {
+        assert!(argon2id(b"pw", b"short", &[], &[], &small_params()).is_err());
+        let too_little_memory = Argon2Params {
+            memory_kib: 8,
+            lanes: 2,
+            ..small_params()
+        };
+        assert!(argon2id(b"pw", b"somesalt", &[], &[], &too_little_memory).is_err());
+        assert_eq!(
+            verify_password(b"pw", "$argon2i$v=19$m=64,t=2,p=1$c29tZXNhbHQ$AAAA"),
+            Err(Argon2Error::InvalidPhcString)
+        );
+        assert_eq!(
+            verify_password(b"pw", "$argon2id$v=19$m=64,t=2$c29tZXNhbHQ$AAAA"),
+            Err(Argon2Error::InvalidPhcString)
+        );
+        // a repeated key must not stand in for a missing one
+        for phc in &[
+            "$argon2id$v=19$m=64,m=64,m=64$c29tZXNhbHQ$AAAA",
+            "$argon2id$v=19$m=64,t=2,t=2$c29tZXNhbHQ$AAAA",
+            "$argon2id$v=19$m=64,t=2,p=1,p=1$c29tZXNhbHQ$AAAA",
+        ] {
+            assert_eq!(parse_phc(phc), Err(Argon2Error::InvalidPhcString));
+        }
+        for phc in &[
+            "$argon2id$v=19$m=4294967295,t=2,p=1$c29tZXNhbHQ$AAAA",
+            "$argon2id$v=19$m=64,t=4294967295,p=1$c29tZXNhbHQ$AAAA",
+            "$argon2id$v=19$m=65536,t=2,p=65535$c29tZXNhbHQ$AAAA",
+        ] {
+            assert!(matches!(parse_phc(phc), Err(Argon2Error::InvalidParams(_))));
+        }
+        assert!(PowParams::new(Argon2Params {
+            lanes: 0,
+            ..small_params()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn pow_hash_is_deterministic() {
+        let params = PowParams::new(small_params()).unwrap();
+        let a = pow_hash(b"block header", &params);
+        assert_eq!(a, pow_hash(b"block header", &params));
+        assert_ne!(a, pow_hash(b"block headex", &params));
+    }
+
+    #[test]
+    fn hasher_interface_matches_pow_hash() {
+        let params = PowParams::new(small_params()).unwrap();
+        let mut ctx = crate::hash::Hasher::new(&crate::hash::HasherType::Argon2id(params));
+        ctx.update(b"block ");
+        ctx.update(b"header");
+        assert_eq!(ctx.finish(), pow_hash(b"block header", &params));
+        ctx.reset();
+        ctx.update(b"other");
+        assert_eq!(ctx.finish(), pow_hash(b"other", &params));
+    }
+}
@@ -0,0 +1,351 @@
+//! Signing many messages with one Ed25519 signature over a Merkle root.
+//!
+//! ```notrust
+//!   message_hash(m) = HASH_TYPE("crypto_lib/batch/leaf/v1" || m)
+//!   leaf i    = HASH_TYPE(message_hash(m_i))
+//!   root      = MerkleTree over the leaves
+//!   signature = Ed25519("crypto_lib/batch/root/v1" || root || leaf count (8, BE))
+//!   receipt i = signature, root, leaf count, i, inclusion proof of leaf i
+//! ```
+//!
+//! Each receipt verifies on its own: check the root signature, then the proof
+//! with `merkle_tree::verify`. Signing the leaf count fixes the tree shape, so
+//! a proof of the wrong length is rejected. Leaves and the tree follow the
+//! crate wide `HASH_TYPE`, so sign and verify with the same build profile.
+use crate::hash::{Hashable, Hasher, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use crate::merkle_tree::merkle_tree::{self, MerkleTree};
+use crate::HASH_TYPE;
+use serde::{Deserialize, Serialize};
+
+const LEAF_TAG: &[u8] = b"crypto_lib/batch/leaf/v1";
+const ROOT_TAG: &[u8] = b"crypto_lib/batch/root/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchError {
+    EmptyBatch,
+    /// the root signature doesn't verify under the given key
+    InvalidSignature,
+    /// the message isn't in the signed tree at the receipt's index
+    InvalidProof,
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BatchError::EmptyBatch => write!(f, "cannot sign an empty batch"),
+            BatchError::InvalidSignature => write!(f, "invalid batch root signature"),
+            BatchError::InvalidProof => write!(f, "invalid batch inclusion proof"),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// The tagged hash of one message; callers holding only hashes sign these.
+pub fn message_hash(message: &[u8]) -> H256 {
+    let mut hasher = Hasher::new(&HASH_TYPE);
+    hasher.update(LEAF_TAG);
+    hasher.update(message);
+    hasher.finish()
+}
+
+/// The signed part shared by every receipt of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRoot {
+    pub root: H256,
+    pub leaf_count: usize,
+    pub signature: Signature,
+}
+
+impl SignedRoot {
+    fn payload(root: &H256, leaf_count: usize) -> Vec<u8> {
+        let mut payload = ROOT_TAG.to_vec();
+        payload.extend_from_slice(root.as_ref());
+        payload.extend_from_slice(&(leaf_count as u64).to_be_bytes());
+        payload
+    }
+
+    /// Check the root signature. A verifier holding many receipts of the same
+    /// batch can do this once and then only check inclusion proofs.
+    pub fn verify(&self, signer: &PublicKey) -> Result<(), BatchError> {
+        if self.leaf_count == 0 {
+            return Err(BatchError::InvalidSignature);
+        }
+        let payload = SignedRoot::payload(&self.root, self.leaf_count);
+        if signer.verify(&payload, &self.signature) {
+            Ok(())
+        } else {
+            Err(BatchError::InvalidSignature)
+        }
+    }
+}
+
+/// Proof that one message was signed as part of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub signed_root: SignedRoot,
+    pub index: usize,
+    pub proof: Vec<H256>,
+}
+
+impl Receipt {
+    /// Check that `signer` signed `message`.
+    pub fn verify(&self, signer: &PublicKey, message: &[u8]) -> Result<(), BatchError> {
+        self.verify_hash(signer, &message_hash(message))
+    }
+
+    /// `verify` for a message given by its `message_hash`.
+    pub fn verify_hash(&self, signer: &PublicKey, message_hash: &H256) -> Result<(), BatchError> {
+        self.signed_root.verify(signer)?;
+        self.verify_inclusion(message_hash)
+    }
+
+    /// Only the inclusion proof, for a `signed_root` that was already verified.
+    pub fn verify_inclusion(&self, message_hash: &H256) -> Result<(), BatchError> {
+        let leaf_count = self.signed_root.leaf_count;
+        if self.index >= leaf_count || self.proof.len() != merkle_tree::proof_len(leaf_count) {
+            return Err(BatchError::InvalidProof);
+        }
+        if merkle_tree::verify(
+            &self.signed_root.root,
+            &message_hash.hash(&HASH_TYPE),
+            &self.proof,
+            self.index,
+            leaf_count,
+        ) {
+            Ok(())
+        } else {
+            Err(BatchError::InvalidProof)
+        }
+    }
+}
+
+/// Collects messages, then signs them all at once.
+pub struct BatchSigner<'a> {
+    key: &'a KeyPair,
+    hashes: Vec<H256>,
+}
+
+impl<'a> BatchSigner<'a> {
+    pub fn new(key: &'a KeyPair) -> Self {
+        BatchSigner {
+            key,
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Queue `message`, returning the index of its receipt.
+    pub fn add(&mut self, message: &[u8]) -> usize {
+        self.add_hash(message_hash(message))
+    }
+
+    /// Queue a message by its `message_hash`.
+    pub fn add_hash(&mut self, message_hash: H256) -> usize {
+        self.hashes.push(message_hash);
+        self.hashes.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Build the tree and sign its root.
+    pub fn finish(self) -> Result<SignedBatch, BatchError> {
+        if self.hashes.is_empty() {
+            return Err(BatchError::EmptyBatch);
+        }
+        let tree = MerkleTree::new(&self.hashes);
+        let root = tree.root();
+        let signature = self
+            .key
+            .sign(&SignedRoot::payload(&root, self.hashes.len()));
+        Ok(SignedBatch {
+            signed_root: SignedRoot {
+                root,
+                leaf_count: self.hashes.len(),
+                signature,
+            },
+            tree,
+        })
+    }
+}
+
+/// A signed batch, handing out receipts.
+pub struct SignedBatch {
+    signed_root: SignedRoot,
+    tree: MerkleTree<H256>,
+}
+
+impl SignedBatch {
+    pub fn signed_root(&self) -> &SignedRoot {
+        &self.signed_root
+    }
+
+    pub fn len(&self) -> usize {
+        self.signed_root.leaf_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signed_root.leaf_count == 0
+    }
+
+    /// The receipt for the message added at `index`.
+    pub fn receipt(&self, index: usize) -> Option<Receipt> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(Receipt {
+            signed_root: self.signed_root.clone(),
+            index,
+            proof: self.tree.proof(index),
+        })
+    }
+
+    /// Receipts for every message, in the order they were added.
+    pub fn receipts(&self) -> Vec<Receipt> {
+        (0..self.len()).filter_map(|i| self.receipt(i)).collect()
+    }
+}
+
+/// Sign every message in `messages`, returning one receipt per message.
+pub fn sign_batch<M: AsRef<[u8]>>(
+    key: &KeyPair,
+    messages: &[M],
+) -> Result<Vec<Receipt>, BatchError> {
+    let mut signer = BatchSigner::new(key);
+    for message in messages.iter() {
+        signer.add(message.as_ref());
+    }
+    Ok(signer.finish()?.receipts())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| format!("receipt {}", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn every_receipt_verifies() {
+        let key = KeyPair::from_seed(&[7u8; 32]).unwrap();
+        for &count in [1usize, 2, 5, 8, 33].iter() {
+            let messages = messages(count);
+            let receipts = sign_batch(&key, &messages).unwrap();
+            assert_eq!(receipts.len(), count);
+            for (i, (message, receipt)) in messages.iter().zip(receipts.iter()).enumerate() {
+                assert_eq!(receipt.index, i);
+                assert_eq!(receipt.proof.len(), merkle_tree::proof_len(count));
+                assert_eq!(receipt.verify(&key.public_key(), message), Ok(()));
+                // the inclusion proof is a plain merkle_tree proof
+                assert!(merkle_tree::verify(
+                    &receipt.signed_root.root,
+                    &message_hash(message).hash(&HASH_TYPE),
+                    &receipt.proof,
+                    i,
+                    count,
+                ));
+            }
+            // one signature for the whole batch
+            assert!(receipts
+                .iter()
+                .all(|r| r.signed_root == receipts[0].signed_root));
+        }
+        assert_eq!(sign_batch::<&[u8]>(&key, &[]), Err(BatchError::EmptyBatch));
+    }
+
+    #[test]
+    fn tampered_receipts_rejected() {
+        let key = KeyPair::from_seed(&[7u8; 32]).unwrap();
+        let messages = messages(5);
+        let receipts = sign_batch(&key, &messages).unwrap();
+        let public = key.public_key();
+        let receipt = &receipts[2];
+
+        assert_eq!(
+            receipt.verify(&public, &messages[3]),
+            Err(BatchError::InvalidProof)
+        );
+        let other_key = KeyPair::from_seed(&[8u8; 32]).unwrap().public_key();
+        assert_eq!(
+            receipt.verify(&other_key, &messages[2]),
+            Err(BatchError::InvalidSignature)
+        );
+
+        let mut wrong_index = receipt.clone();
+        wrong_index.index = 3;
+        assert_eq!(
+            wrong_index.verify(&public, &messages[2]),
+            Err(BatchError::InvalidProof)
+        );
+        let mut wrong_proof = receipt.clone();
+        wrong_proof.proof[0] = H256::default();
+        assert_eq!(
+            wrong_proof.verify(&public, &messages[2]),
+            Err(BatchError::InvalidProof)
+        );
+        let mut short_proof = receipt.clone();
+        short_proof.proof.pop();
+        assert_eq!(
+            short_proof.verify(&public, &messages[2]),
+            Err(BatchError::InvalidProof)
+        );
+        // the leaf count is covered by the signature
+        let mut wrong_count = receipt.clone();
+        wrong_count.signed_root.leaf_count = 4;
+        assert_eq!(
+            wrong_count.verify(&public, &messages[2]),
+            Err(BatchError::InvalidSignature)
+        );
+        // a receipt from another batch doesn't vouch for this root
+        let other = sign_batch(&key, &[b"other"]).unwrap();
+        let mut mixed = receipt.clone();
+        mixed.signed_root = other[0].signed_root.clone();
+        assert!(mixed.verify(&public, &messages[2]).is_err());
+    }
+
+    #[test]
+    fn incremental_signer_and_hashes() {
+        let key = KeyPair::from_seed(&[7u8; 32]).unwrap();
+        let mut signer = BatchSigner::new(&key);
+        assert!(signer.is_empty());
+        assert_eq!(signer.add(b"first"), 0);
+        assert_eq!(signer.add_hash(message_hash(b"second")), 1);
+        assert_eq!(signer.len(), 2);
+        let batch = signer.finish().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert!(batch.receipt(2).is_none());
+        assert_eq!(batch.signed_root().verify(&key.public_key()), Ok(()));
+
+        let receipt = batch.receipt(1).unwrap();
+        assert_eq!(receipt.verify(&key.public_key(), b"second"), Ok(()));
+        assert_eq!(receipt.verify_inclusion(&message_hash(b"second")), Ok(()));
+        // same receipts as signing the list in one go
+        assert_eq!(
+            sign_batch(&key, &[&b"first"[..], b"second"]).unwrap(),
+            batch.receipts()
+        );
+        assert_eq!(
+            BatchSigner::new(&key).finish().err(),
+            Some(BatchError::EmptyBatch)
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let key = KeyPair::from_seed(&[7u8; 32]).unwrap();
+        let receipts = sign_batch(&key, &messages(3)).unwrap();
+        let bytes = bincode::serialize(&receipts[1]).unwrap();
+        let decoded: Receipt = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, receipts[1]);
+        assert_eq!(decoded.verify(&key.public_key(), b"receipt 1"), Ok(()));
+    }
+}
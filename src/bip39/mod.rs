@@ -0,0 +1,295 @@
+//! BIP-39 mnemonic phrases.
+//!
+//! ```notrust
+//!   entropy (128..256 bits) || first ENT/32 bits of SHA-256(entropy)
+//!   split into 11 bit groups -> index into the English wordlist
+//!
+//!   seed = PBKDF2-HMAC-SHA512(phrase, "mnemonic" || passphrase, 2048 rounds, 64 bytes)
+//! ```
+//!
+//! Only the English list is supported and no Unicode normalisation is done,
+//! so passphrases must already be NFKD (plain ASCII always is).
+pub mod english;
+
+use crate::hash::{Hasher, HasherType};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{Zeroize, Zeroizing};
+use ring::pbkdf2;
+use std::num::NonZeroU32;
+
+const PBKDF2_ROUNDS: u32 = 2048;
+pub const SEED_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bip39Error {
+    /// phrases have 12, 15, 18, 21 or 24 words
+    InvalidWordCount(usize),
+    /// entropy must be 16, 20, 24, 28 or 32 bytes
+    InvalidEntropyLength(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Bip39Error::InvalidWordCount(n) => write!(f, "invalid mnemonic word count {}", n),
+            Bip39Error::InvalidEntropyLength(n) => write!(f, "invalid entropy length {}", n),
+            Bip39Error::UnknownWord(w) => write!(f, "unknown mnemonic word {}", w),
+            Bip39Error::InvalidChecksum => write!(f, "invalid mnemonic checksum"),
+            Bip39Error::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Bip39Error {}
+
+impl From<RandomError> for Bip39Error {
+    fn from(e: RandomError) -> Self {
+        Bip39Error::Random(e)
+    }
+}
+
+/// A validated mnemonic, stored as its entropy.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    entropy: Vec<u8>,
+}
+
+impl Mnemonic {
+    /// A fresh random mnemonic of `word_count` words (12, 15, 18, 21 or 24).
+    pub fn generate(word_count: usize) -> Result<Mnemonic, Bip39Error> {
+        Mnemonic::generate_with_rng(word_count, &SystemRandom::new())
+    }
+
+    /// A mnemonic of `word_count` words with entropy drawn from `rng`.
+    pub fn generate_with_rng(
+        word_count: usize,
+        rng: &dyn SecureRandom,
+    ) -> Result<Mnemonic, Bip39Error> {
+        if !valid_word_count(word_count) {
+            return Err(Bip39Error::InvalidWordCount(word_count));
+        }
+        let mut entropy = Zeroizing::new(vec![0u8; word_count / 3 * 4]);
+        rng.fill(&mut entropy)?;
+        Mnemonic::from_entropy(&entropy)
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Mnemonic, Bip39Error> {
+        if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+            return Err(Bip39Error::InvalidEntropyLength(entropy.len()));
+        }
+        Ok(Mnemonic {
+            entropy: entropy.to_vec(),
+        })
+    }
+
+    /// Parse and validate a phrase: word count, wordlist membership and checksum.
+    pub fn parse(phrase: &str) -> Result<Mnemonic, Bip39Error> {
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+        if !valid_word_count(words.len()) {
+            return Err(Bip39Error::InvalidWordCount(words.len()));
+        }
+        let mut bits = Zeroizing::new(Vec::with_capacity(words.len() * 11));
+        for word in words.iter() {
+            let index = english::WORDS
+                .binary_search(word)
+                .map_err(|_| Bip39Error::UnknownWord(word.to_string()))?;
+            for i in (0..11).rev() {
+                bits.push((index >> i) & 1 == 1);
+            }
+        }
+        let checksum_len = words.len() / 3;
+        let entropy_len = (bits.len() - checksum_len) / 8;
+        let mut entropy = vec![0u8; entropy_len];
+        for (i, bit) in bits[..entropy_len * 8].iter().enumerate() {
+            if *bit {
+                entropy[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        let mnemonic = Mnemonic { entropy };
+        let expected = checksum_bits(&mnemonic.entropy);
+        if bits[entropy_len * 8..] != expected[..] {
+            return Err(Bip39Error::InvalidChecksum);
+        }
+        Ok(mnemonic)
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.entropy.len() * 3 / 4
+    }
+
+    pub fn words(&self) -> Vec<&'static str> {
+        let mut bits = Zeroizing::new(Vec::with_capacity(
+            self.entropy.len() * 8 + self.entropy.len() / 4,
+        ));
+        for byte in self.entropy.iter() {
+            for i in (0..8).rev() {
+                bits.push((byte >> i) & 1 == 1);
+            }
+        }
+        bits.extend(checksum_bits(&self.entropy));
+        bits.chunks(11)
+            .map(|chunk| {
+                let index = chunk
+                    .iter()
+                    .fold(0usize, |acc, &bit| (acc << 1) | bit as usize);
+                english::WORDS[index]
+            })
+            .collect()
+    }
+
+    /// The space separated phrase to write down.
+    pub fn phrase(&self) -> String {
+        self.words().join(" ")
+    }
+
+    /// The 64-byte BIP-39 seed, salted with the optional `passphrase` ("" for none).
+    pub fn to_seed(&self, passphrase: &str) -> Zeroizing<[u8; SEED_LEN]> {
+        let mut salt = Zeroizing::new(b"mnemonic".to_vec());
+        salt.extend_from_slice(passphrase.as_bytes());
+        let phrase = Zeroizing::new(self.phrase());
+        let mut seed = Zeroizing::new([0u8; SEED_LEN]);
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            NonZeroU32::new(PBKDF2_ROUNDS).unwrap(),
+            &salt,
+            phrase.as_bytes(),
+            &mut *seed,
+        );
+        seed
+    }
+}
+
+impl std::fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // the phrase is the key, never print it
+        write!(f, "Mnemonic({} words)", self.word_count())
+    }
+}
+
+impl Drop for Mnemonic {
+    fn drop(&mut self) {
+        self.entropy.zeroize();
+    }
+}
+
+fn valid_word_count(count: usize) -> bool {
+    matches!(count, 12 | 15 | 18 | 21 | 24)
+}
+
+fn checksum_bits(entropy: &[u8]) -> Vec<bool> {
+    let mut hasher = Hasher::new(&HasherType::RingSHA256);
+    hasher.update(entropy);
+    let digest = hasher.finish();
+    let first = digest.as_ref()[0];
+    (0..entropy.len() / 4)
+        .map(|i| (first >> (7 - i)) & 1 == 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (entropy, phrase, seed with passphrase "TREZOR") from the reference BIP-39 vectors
+    const VECTORS: [(&str, &str, &str); 6] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+            "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+        ),
+        (
+            "808080808080808080808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter always",
+            "107d7c02a5aa6f38c58083ff74f04c607c2d2c0ecc55501dadd72d025b751bc27fe913ffb796f841c49b1d33b610cf0e91d3aa239027f5e99fe4ce9e5088cd65",
+        ),
+        (
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+            "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+        ),
+        (
+            "8080808080808080808080808080808080808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+            "c0c519bd0e91a2ed54357d9d1ebef6f5af218a153624cf4f2da911a0ed8f7a09e2ef61af0aca007096df430022f7a2b6fb91661a9589097069720d015e4e982f",
+        ),
+    ];
+
+    #[test]
+    fn reference_vectors() {
+        for (entropy, phrase, seed) in VECTORS.iter() {
+            let from_entropy = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(from_entropy.phrase(), *phrase);
+            let parsed = Mnemonic::parse(phrase).unwrap();
+            assert_eq!(parsed, from_entropy);
+            assert_eq!(hex::encode(&parsed.to_seed("TREZOR")[..]), *seed);
+        }
+    }
+
+    #[test]
+    fn generated_phrases_round_trip() {
+        for &count in [12, 15, 18, 21, 24].iter() {
+            let mnemonic = Mnemonic::generate(count).unwrap();
+            assert_eq!(mnemonic.words().len(), count);
+            assert_eq!(Mnemonic::parse(&mnemonic.phrase()).unwrap(), mnemonic);
+        }
+        assert_eq!(
+            Mnemonic::generate(13).unwrap_err(),
+            Bip39Error::InvalidWordCount(13)
+        );
+        let rng = crate::rng::TestRng::new(b"bip39");
+        let first = Mnemonic::generate_with_rng(12, &rng).unwrap();
+        assert_ne!(Mnemonic::generate_with_rng(12, &rng).unwrap(), first);
+        assert_eq!(
+            Mnemonic::generate_with_rng(12, &crate::rng::TestRng::new(b"bip39")).unwrap(),
+            first
+        );
+        assert_eq!(
+            Mnemonic::from_entropy(&[0u8; 17]).unwrap_err(),
+            Bip39Error::InvalidEntropyLength(17)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_phrases() {
+        let wrong_checksum =
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert_eq!(
+            Mnemonic::parse(wrong_checksum).unwrap_err(),
+            Bip39Error::InvalidChecksum
+        );
+        let unknown =
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon aboot";
+        assert_eq!(
+            Mnemonic::parse(unknown).unwrap_err(),
+            Bip39Error::UnknownWord("aboot".to_string())
+        );
+        assert_eq!(
+            Mnemonic::parse("zoo zoo").unwrap_err(),
+            Bip39Error::InvalidWordCount(2)
+        );
+    }
+
+    #[test]
+    fn passphrase_changes_seed() {
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        assert_ne!(&mnemonic.to_seed("")[..], &mnemonic.to_seed("x")[..]);
+        assert!(!format!("{:?}", mnemonic).contains(&mnemonic.phrase()));
+    }
+}
@@ -0,0 +1,731 @@
+//! Lightweight key certificates for delegating signing authority.
+//!
+//! A certificate binds a subject name and public key to a validity window and
+//! a set of permissions, signed by an issuer key:
+//!
+//! ```notrust
+//!   tbs  = "crypto_lib/cert/v1" || version (1) || serial (8) || subject (2 + n)
+//!          || key algorithm (1) || key (2 + n) || issuer key id (32)
+//!          || not_before (8) || not_after (8) || permissions (4)
+//!   cert = tbs || signature algorithm (1) || signature (2 + n)
+//! ```
+//!
+//! Integers are big endian and times are unix seconds. Key ids are SHA-256 of
+//! the algorithm code and key bytes, independent of the build's `HASH_TYPE`.
+//!
+//! A `TrustStore` holds root keys and revoked serials. Chains run from a leaf
+//! to a certificate issued by a root; every issuer in between needs
+//! `Permissions::CERTIFY` and may only delegate permissions it holds itself.
+use crate::hash::{Hasher, HasherType, H256};
+use crate::key_pair::{Algorithm, SigningKey, TaggedSignature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const DOMAIN: &[u8] = b"crypto_lib/cert/v1";
+pub const VERSION: u8 = 1;
+/// Longest chain `build_chain` will follow, leaf included.
+pub const MAX_CHAIN_LEN: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertError {
+    InvalidEncoding(String),
+    /// not_before is after not_after
+    InvalidValidity,
+    EmptyChain,
+    ChainTooLong,
+    /// no certificate or root key matches an issuer key id
+    UnknownIssuer(H256),
+    InvalidSignature {
+        serial: u64,
+    },
+    NotYetValid {
+        serial: u64,
+    },
+    Expired {
+        serial: u64,
+    },
+    Revoked {
+        serial: u64,
+    },
+    /// the issuer of this certificate may not issue certificates
+    NotCertifier {
+        serial: u64,
+    },
+    /// the certificate holds permissions its issuer doesn't have
+    PermissionEscalation {
+        serial: u64,
+    },
+    MissingPermissions,
+}
+
+impl std::fmt::Display for CertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CertError::InvalidEncoding(e) => write!(f, "invalid certificate encoding: {}", e),
+            CertError::InvalidValidity => write!(f, "not_before is after not_after"),
+            CertError::EmptyChain => write!(f, "empty certificate chain"),
+            CertError::ChainTooLong => write!(f, "certificate chain too long"),
+            CertError::UnknownIssuer(id) => write!(f, "unknown issuer {}", id),
+            CertError::InvalidSignature { serial } => {
+                write!(f, "certificate {} has an invalid signature", serial)
+            }
+            CertError::NotYetValid { serial } => {
+                write!(f, "certificate {} is not valid yet", serial)
+            }
+            CertError::Expired { serial } => write!(f, "certificate {} has expired", serial),
+            CertError::Revoked { serial } => write!(f, "certificate {} is revoked", serial),
+            CertError::NotCertifier { serial } => {
+                write!(f, "issuer of certificate {} may not certify", serial)
+            }
+            CertError::PermissionEscalation { serial } => {
+                write!(f, "certificate {} exceeds its issuer's permissions", serial)
+            }
+            CertError::MissingPermissions => write!(f, "leaf lacks the required permissions"),
+        }
+    }
+}
+
+impl std::error::Error for CertError {}
+
+/// Usage flags carried by a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Permissions(pub u32);
+
+impl Permissions {
+    pub const NONE: Permissions = Permissions(0);
+    /// sign data, e.g. MMR checkpoints
+    pub const SIGN: Permissions = Permissions(1);
+    /// issue certificates for other keys
+    pub const CERTIFY: Permissions = Permissions(1 << 1);
+    /// authenticate to services
+    pub const AUTHENTICATE: Permissions = Permissions(1 << 2);
+    /// sign revocations
+    pub const REVOKE: Permissions = Permissions(1 << 3);
+
+    pub fn contains(self, other: Permissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Permissions {
+    type Output = Permissions;
+
+    fn bitor(self, other: Permissions) -> Permissions {
+        Permissions(self.0 | other.0)
+    }
+}
+
+/// SHA-256 key id of a public key.
+pub fn key_id(key: &VerifyingKey) -> H256 {
+    let mut hasher = Hasher::new(&HasherType::RingSHA256);
+    hasher.update(&[algorithm_code(key.algorithm)]);
+    hasher.update(&key.bytes);
+    hasher.finish()
+}
+
+/// The fields of a certificate before the issuer signs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateRequest {
+    pub serial: u64,
+    pub subject: String,
+    pub public_key: VerifyingKey,
+    pub not_before: u64,
+    pub not_after: u64,
+    pub permissions: Permissions,
+}
+
+impl CertificateRequest {
+    pub fn sign(self, issuer: &SigningKey) -> Result<Certificate, CertError> {
+        if self.not_before > self.not_after {
+            return Err(CertError::InvalidValidity);
+        }
+        if self.subject.len() > u16::MAX as usize {
+            return Err(CertError::InvalidEncoding("subject too long".to_string()));
+        }
+        let mut certificate = Certificate {
+            serial: self.serial,
+            subject: self.subject,
+            public_key: self.public_key,
+            issuer: key_id(&issuer.verifying_key()),
+            not_before: self.not_before,
+            not_after: self.not_after,
+            permissions: self.permissions,
+            signature: TaggedSignature {
+                algorithm: issuer.algorithm(),
+                bytes: vec![],
+            },
+        };
+        certificate.signature = issuer.sign(&certificate.tbs_bytes());
+        Ok(certificate)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Certificate {
+    pub serial: u64,
+    pub subject: String,
+    pub public_key: VerifyingKey,
+    /// key id of the issuing key
+    pub issuer: H256,
+    pub not_before: u64,
+    pub not_after: u64,
+    pub permissions: Permissions,
+    pub signature: TaggedSignature,
+}
+
+impl Certificate {
+    pub fn key_id(&self) -> H256 {
+        key_id(&self.public_key)
+    }
+
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.not_before <= now && now <= self.not_after
+    }
+
+    /// Whether `issuer` signed this certificate. Says nothing about validity or permissions.
+    pub fn verify_signature(&self, issuer: &VerifyingKey) -> bool {
+        key_id(issuer) == self.issuer && issuer.verify(&self.tbs_bytes(), &self.signature)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.tbs_bytes();
+        out.push(algorithm_code(self.signature.algorithm));
+        put_bytes(&mut out, &self.signature.bytes);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Certificate, CertError> {
+        let mut reader = Reader(bytes);
+        if reader.take(DOMAIN.len())? != DOMAIN {
+            return Err(invalid("bad domain"));
+        }
+        if reader.take(1)?[0] != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let serial = reader.u64()?;
+        let subject = String::from_utf8(reader.bytes()?.to_vec())
+            .map_err(|_| invalid("subject is not utf-8"))?;
+        let algorithm = algorithm_from_code(reader.take(1)?[0])?;
+        let public_key = VerifyingKey {
+            algorithm,
+            bytes: reader.bytes()?.to_vec(),
+        };
+        let issuer = H256::from(reader.take(32)?.to_vec());
+        let not_before = reader.u64()?;
+        let not_after = reader.u64()?;
+        let mut permissions = [0u8; 4];
+        permissions.copy_from_slice(reader.take(4)?);
+        let signature = TaggedSignature {
+            algorithm: algorithm_from_code(reader.take(1)?[0])?,
+            bytes: reader.bytes()?.to_vec(),
+        };
+        if !reader.0.is_empty() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(Certificate {
+            serial,
+            subject,
+            public_key,
+            issuer,
+            not_before,
+            not_after,
+            permissions: Permissions(u32::from_be_bytes(permissions)),
+            signature,
+        })
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(hex_str: &str) -> Result<Certificate, CertError> {
+        let bytes = hex::decode(hex_str).map_err(|_| invalid("bad hex"))?;
+        Certificate::from_bytes(&bytes)
+    }
+
+    // the signed part
+    fn tbs_bytes(&self) -> Vec<u8> {
+        let mut out = DOMAIN.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&self.serial.to_be_bytes());
+        put_bytes(&mut out, self.subject.as_bytes());
+        out.push(algorithm_code(self.public_key.algorithm));
+        put_bytes(&mut out, &self.public_key.bytes);
+        out.extend_from_slice(self.issuer.as_ref());
+        out.extend_from_slice(&self.not_before.to_be_bytes());
+        out.extend_from_slice(&self.not_after.to_be_bytes());
+        out.extend_from_slice(&self.permissions.0.to_be_bytes());
+        out
+    }
+}
+
+/// Trusted root keys and revoked certificates.
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    roots: Vec<VerifyingKey>,
+    revoked: HashSet<(H256, u64)>,
+}
+
+impl TrustStore {
+    pub fn new() -> TrustStore {
+        TrustStore::default()
+    }
+
+    pub fn add_root(&mut self, key: VerifyingKey) {
+        if !self.roots.contains(&key) {
+            self.roots.push(key);
+        }
+    }
+
+    pub fn roots(&self) -> &[VerifyingKey] {
+        &self.roots
+    }
+
+    /// Revoke the certificate with `serial` issued by the key with id `issuer`.
+    pub fn revoke(&mut self, issuer: H256, serial: u64) {
+        self.revoked.insert((issuer, serial));
+    }
+
+    pub fn is_revoked(&self, certificate: &Certificate) -> bool {
+        self.revoked
+            .contains(&(certificate.issuer, certificate.serial))
+    }
+
+    /// Find a chain from `leaf` through certificates in `pool` to a root, leaf first.
+    /// Every link is checked as `verify_chain` checks it at `now`, and every pool
+    /// certificate for an issuer is tried, so a revoked, expired or bogus certificate
+    /// for the same key can't hide a good one. The shortest chain wins; if there is
+    /// none, the first failure found is returned.
+    pub fn build_chain(
+        &self,
+        leaf: &Certificate,
+        pool: &[Certificate],
+        now: u64,
+    ) -> Result<Vec<Certificate>, CertError> {
+        // breadth first over pool indices, `None` standing for the leaf.
+        // issued[i] is the certificate pool[i] was reached from.
+        let mut issued: Vec<Option<Option<usize>>> = vec![None; pool.len()];
+        let mut frontier: Vec<Option<usize>> = vec![None];
+        let mut first_error = None;
+        for depth in 1..=MAX_CHAIN_LEN {
+            let mut next = Vec::new();
+            for &node in frontier.iter() {
+                let certificate = node.map_or(leaf, |i| &pool[i]);
+                if let Some(root) = self.root(&certificate.issuer) {
+                    // roots may delegate anything
+                    match self.check_link(certificate, root, Permissions(u32::MAX), now) {
+                        Ok(()) => return Ok(trace(leaf, pool, &issued, node)),
+                        Err(e) => {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                    continue;
+                }
+                if depth == MAX_CHAIN_LEN {
+                    first_error.get_or_insert(CertError::ChainTooLong);
+                    continue;
+                }
+                let mut known = false;
+                for (i, candidate) in pool.iter().enumerate() {
+                    if candidate.key_id() != certificate.issuer {
+                        continue;
+                    }
+                    known = true;
+                    // already reached by a chain at least as short
+                    if issued[i].is_some() {
+                        continue;
+                    }
+                    match self.check_link(
+                        certificate,
+                        &candidate.public_key,
+                        candidate.permissions,
+                        now,
+                    ) {
+                        Ok(()) => {
+                            issued[i] = Some(node);
+                            next.push(Some(i));
+                        }
+                        Err(e) => {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+                if !known {
+                    first_error.get_or_insert(CertError::UnknownIssuer(certificate.issuer));
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        Err(first_error.unwrap_or(CertError::ChainTooLong))
+    }
+
+    /// Check a leaf-first chain at time `now` and that the leaf holds `required`.
+    pub fn verify_chain(
+        &self,
+        chain: &[Certificate],
+        now: u64,
+        required: Permissions,
+    ) -> Result<(), CertError> {
+        let leaf = chain.first().ok_or(CertError::EmptyChain)?;
+        if chain.len() > MAX_CHAIN_LEN {
+            return Err(CertError::ChainTooLong);
+        }
+        for (i, certificate) in chain.iter().enumerate() {
+            let (issuer_key, issuer_permissions) = match chain.get(i + 1) {
+                Some(issuer) => (&issuer.public_key, issuer.permissions),
+                None => {
+                    let root = self
+                        .root(&certificate.issuer)
+                        .ok_or(CertError::UnknownIssuer(certificate.issuer))?;
+                    // roots may delegate anything
+                    (root, Permissions(u32::MAX))
+                }
+            };
+            self.check_link(certificate, issuer_key, issuer_permissions, now)?;
+        }
+        if !leaf.permissions.contains(required) {
+            return Err(CertError::MissingPermissions);
+        }
+        Ok(())
+    }
+
+    // one link of a chain: `certificate` as issued by a key holding `issuer_permissions`
+    fn check_link(
+        &self,
+        certificate: &Certificate,
+        issuer_key: &VerifyingKey,
+        issuer_permissions: Permissions,
+        now: u64,
+    ) -> Result<(), CertError> {
+        let serial = certificate.serial;
+        if !certificate.verify_signature(issuer_key) {
+            return Err(CertError::InvalidSignature { serial });
+        }
+        if self.is_revoked(certificate) {
+            return Err(CertError::Revoked { serial });
+        }
+        if now < certificate.not_before {
+            return Err(CertError::NotYetValid { serial });
+        }
+        if now > certificate.not_after {
+            return Err(CertError::Expired { serial });
+        }
+        if !issuer_permissions.contains(Permissions::CERTIFY) {
+            return Err(CertError::NotCertifier { serial });
+        }
+        if !issuer_permissions.contains(certificate.permissions) {
+            return Err(CertError::PermissionEscalation { serial });
+        }
+        Ok(())
+    }
+
+    /// `build_chain` then `verify_chain`, returning the verified chain.
+    pub fn verify(
+        &self,
+        leaf: &Certificate,
+        pool: &[Certificate],
+        now: u64,
+        required: Permissions,
+    ) -> Result<Vec<Certificate>, CertError> {
+        let chain = self.build_chain(leaf, pool, now)?;
+        self.verify_chain(&chain, now, required)?;
+        Ok(chain)
+    }
+
+    fn root(&self, id: &H256) -> Option<&VerifyingKey> {
+        self.roots.iter().find(|root| key_id(root) == *id)
+    }
+}
+
+// leaf first chain ending at `end`, following the links `build_chain` recorded
+fn trace(
+    leaf: &Certificate,
+    pool: &[Certificate],
+    issued: &[Option<Option<usize>>],
+    end: Option<usize>,
+) -> Vec<Certificate> {
+    let mut chain = Vec::new();
+    let mut node = end;
+    while let Some(i) = node {
+        chain.push(pool[i].clone());
+        node = issued[i].expect("every reached certificate has a link");
+    }
+    chain.push(leaf.clone());
+    chain.reverse();
+    chain
+}
+
+fn invalid(e: &str) -> CertError {
+    CertError::InvalidEncoding(e.to_string())
+}
+
+fn algorithm_code(algorithm: Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::Ed25519 => 1,
+        Algorithm::EcdsaP256Sha256 => 2,
+        Algorithm::EcdsaP384Sha384 => 3,
+    }
+}
+
+fn algorithm_from_code(code: u8) -> Result<Algorithm, CertError> {
+    match code {
+        1 => Ok(Algorithm::Ed25519),
+        2 => Ok(Algorithm::EcdsaP256Sha256),
+        3 => Ok(Algorithm::EcdsaP384Sha384),
+        _ => Err(invalid("unknown algorithm")),
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    // subjects are checked on signing, keys and signatures are short
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CertError> {
+        if self.0.len() < n {
+            return Err(invalid("truncated"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> Result<u64, CertError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], CertError> {
+        let len = self.take(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pki {
+        root: SigningKey,
+        operator: SigningKey,
+        node: SigningKey,
+        operator_cert: Certificate,
+        node_cert: Certificate,
+        store: TrustStore,
+    }
+
+    fn request(
+        serial: u64,
+        subject: &str,
+        key: &SigningKey,
+        permissions: Permissions,
+    ) -> CertificateRequest {
+        CertificateRequest {
+            serial,
+            subject: subject.to_string(),
+            public_key: key.verifying_key(),
+            not_before: 1000,
+            not_after: 2000,
+            permissions,
+        }
+    }
+
+    // root -> operator (P-256) -> node
+    fn pki() -> Pki {
+        let root = SigningKey::generate(Algorithm::Ed25519);
+        let operator = SigningKey::generate(Algorithm::EcdsaP256Sha256);
+        let node = SigningKey::generate(Algorithm::Ed25519);
+        let operator_cert = request(
+            1,
+            "operator",
+            &operator,
+            Permissions::SIGN | Permissions::CERTIFY,
+        )
+        .sign(&root)
+        .unwrap();
+        let node_cert = request(7, "node-1", &node, Permissions::SIGN)
+            .sign(&operator)
+            .unwrap();
+        let mut store = TrustStore::new();
+        store.add_root(root.verifying_key());
+        Pki {
+            root,
+            operator,
+            node,
+            operator_cert,
+            node_cert,
+            store,
+        }
+    }
+
+    #[test]
+    fn verifies_delegated_chain() {
+        let pki = pki();
+        let pool = [pki.operator_cert.clone()];
+        let chain = pki
+            .store
+            .verify(&pki.node_cert, &pool, 1500, Permissions::SIGN)
+            .unwrap();
+        assert_eq!(
+            chain,
+            vec![pki.node_cert.clone(), pki.operator_cert.clone()]
+        );
+        assert_eq!(chain[0].key_id(), key_id(&pki.node.verifying_key()));
+
+        assert_eq!(
+            pki.store.verify_chain(&chain, 1500, Permissions::CERTIFY),
+            Err(CertError::MissingPermissions)
+        );
+        assert_eq!(
+            pki.store.verify_chain(&chain, 2001, Permissions::SIGN),
+            Err(CertError::Expired { serial: 7 })
+        );
+        assert_eq!(
+            pki.store.verify_chain(&chain, 999, Permissions::SIGN),
+            Err(CertError::NotYetValid { serial: 7 })
+        );
+        assert_eq!(
+            TrustStore::new().verify(&pki.node_cert, &pool, 1500, Permissions::SIGN),
+            Err(CertError::UnknownIssuer(key_id(&pki.root.verifying_key())))
+        );
+    }
+
+    #[test]
+    fn revocation_by_serial() {
+        let mut pki = pki();
+        let pool = [pki.operator_cert.clone()];
+        // revoking the operator cuts off everything it issued
+        pki.store.revoke(key_id(&pki.root.verifying_key()), 1);
+        assert_eq!(
+            pki.store
+                .verify(&pki.node_cert, &pool, 1500, Permissions::SIGN),
+            Err(CertError::Revoked { serial: 1 })
+        );
+
+        let mut pki = self::pki();
+        let pool = [pki.operator_cert.clone()];
+        pki.store.revoke(pki.node_cert.issuer, 7);
+        assert!(pki.store.is_revoked(&pki.node_cert));
+        assert_eq!(
+            pki.store
+                .verify(&pki.node_cert, &pool, 1500, Permissions::SIGN),
+            Err(CertError::Revoked { serial: 7 })
+        );
+    }
+
+    #[test]
+    fn tries_every_issuer_certificate() {
+        let mut pki = pki();
+        let operator_request = |serial| {
+            request(
+                serial,
+                "operator",
+                &pki.operator,
+                Permissions::SIGN | Permissions::CERTIFY,
+            )
+        };
+        let reissued = operator_request(2).sign(&pki.root).unwrap();
+        // valid itself, but issued by a key that leads nowhere
+        let stranger = SigningKey::generate(Algorithm::Ed25519);
+        let stray = operator_request(3).sign(&stranger).unwrap();
+        // right key id, but not allowed to certify
+        let weak = request(4, "operator", &pki.operator, Permissions::SIGN)
+            .sign(&pki.root)
+            .unwrap();
+        pki.store.revoke(key_id(&pki.root.verifying_key()), 1);
+
+        let pool = [
+            stray.clone(),
+            weak,
+            pki.operator_cert.clone(),
+            reissued.clone(),
+        ];
+        let chain = pki
+            .store
+            .verify(&pki.node_cert, &pool, 1500, Permissions::SIGN)
+            .unwrap();
+        assert_eq!(chain, vec![pki.node_cert.clone(), reissued]);
+
+        // without the good certificate the first failure is reported
+        assert_eq!(
+            pki.store.build_chain(&pki.node_cert, &pool[..3], 1500),
+            Err(CertError::NotCertifier { serial: 7 })
+        );
+        assert_eq!(
+            pki.store.build_chain(&pki.node_cert, &[stray], 1500),
+            Err(CertError::UnknownIssuer(key_id(&stranger.verifying_key())))
+        );
+    }
+
+    #[test]
+    fn issuers_cannot_escalate() {
+        let pki = pki();
+        let pool = [pki.operator_cert.clone()];
+        let other = SigningKey::generate(Algorithm::Ed25519);
+        let escalated = request(8, "node-2", &other, Permissions::SIGN | Permissions::REVOKE)
+            .sign(&pki.operator)
+            .unwrap();
+        assert_eq!(
+            pki.store.verify(&escalated, &pool, 1500, Permissions::SIGN),
+            Err(CertError::PermissionEscalation { serial: 8 })
+        );
+
+        // node is not a certifier
+        let sub = request(9, "sub", &other, Permissions::SIGN)
+            .sign(&pki.node)
+            .unwrap();
+        let pool = [pki.operator_cert.clone(), pki.node_cert.clone()];
+        assert_eq!(
+            pki.store.verify(&sub, &pool, 1500, Permissions::SIGN),
+            Err(CertError::NotCertifier { serial: 9 })
+        );
+
+        assert_eq!(
+            CertificateRequest {
+                not_before: 3000,
+                ..request(10, "x", &other, Permissions::SIGN)
+            }
+            .sign(&pki.root),
+            Err(CertError::InvalidValidity)
+        );
+    }
+
+    #[test]
+    fn encoding_round_trip_and_tampering() {
+        let pki = pki();
+        for certificate in [&pki.operator_cert, &pki.node_cert].iter() {
+            let bytes = certificate.to_bytes();
+            assert_eq!(&Certificate::from_bytes(&bytes).unwrap(), *certificate);
+            assert_eq!(
+                &Certificate::from_hex(&certificate.to_hex()).unwrap(),
+                *certificate
+            );
+            assert!(Certificate::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        let mut forged = pki.node_cert.clone();
+        forged.permissions = Permissions::SIGN | Permissions::AUTHENTICATE;
+        assert!(!forged.verify_signature(&pki.operator.verifying_key()));
+        assert_eq!(
+            pki.store.verify_chain(
+                &[forged, pki.operator_cert.clone()],
+                1500,
+                Permissions::SIGN
+            ),
+            Err(CertError::InvalidSignature { serial: 7 })
+        );
+
+        let bytes = bincode::serialize(&pki.node_cert).unwrap();
+        let back: Certificate = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back, pki.node_cert);
+    }
+}
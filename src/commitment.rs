@@ -0,0 +1,348 @@
+use crate::hash::{Hashable, Hasher, HasherType, H256};
+use crate::merkle_tree::merkle_tree::{self, MerkleTree};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::Zeroize;
+use crate::HASH_TYPE;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub enum CommitmentError {
+    /// a batch needs at least one value
+    Empty,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CommitmentError::Empty => write!(f, "cannot commit to an empty batch"),
+            CommitmentError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+impl From<RandomError> for CommitmentError {
+    fn from(e: RandomError) -> Self {
+        CommitmentError::Random(e)
+    }
+}
+
+// every commitment hash starts with this tag, so a commitment can never be
+// mistaken for (or replayed as) some other hash the crate produces
+const COMMITMENT_TAG: &[u8] = b"crypto_lib/commitment/v1";
+
+/// A hiding, binding commitment to a value: hash(tag || blinding || value).
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Commitment(H256);
+
+/// Everything needed to open a `Commitment`: the value and its random blinding.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Opening {
+    pub value: Vec<u8>,
+    pub blinding: [u8; 32],
+}
+
+/// Commit to `value` with fresh 32-byte random blinding.
+/// Publish the `Commitment`, keep the `Opening` until the reveal phase.
+pub fn commit(value: &[u8]) -> (Commitment, Opening) {
+    commit_with_rng(value, &SystemRandom::new()).unwrap()
+}
+
+/// `commit` with the blinding drawn from `rng`.
+pub fn commit_with_rng(
+    value: &[u8],
+    rng: &dyn SecureRandom,
+) -> Result<(Commitment, Opening), RandomError> {
+    let mut blinding = [0u8; 32];
+    rng.fill(&mut blinding)?;
+    let opening = Opening {
+        value: value.to_vec(),
+        blinding,
+    };
+    Ok((opening.commitment(), opening))
+}
+
+impl Drop for Opening {
+    fn drop(&mut self) {
+        self.value.zeroize();
+        self.blinding.zeroize();
+    }
+}
+
+impl Opening {
+    /// Recompute the commitment this opening corresponds to.
+    pub fn commitment(&self) -> Commitment {
+        let mut ctx = Hasher::new(&HASH_TYPE);
+        ctx.update(COMMITMENT_TAG);
+        ctx.update(&self.blinding);
+        ctx.update(&self.value);
+        Commitment(ctx.finish())
+    }
+}
+
+impl Commitment {
+    /// Check that `opening` really is the opening of this commitment.
+    pub fn verify(&self, opening: &Opening) -> bool {
+        opening.commitment() == *self
+    }
+
+    /// Open the commitment, returning the committed value if the opening is valid.
+    pub fn open<'a>(&self, opening: &'a Opening) -> Option<&'a [u8]> {
+        if self.verify(opening) {
+            Some(&opening.value)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_h256(&self) -> &H256 {
+        &self.0
+    }
+}
+
+impl Hashable for Commitment {
+    fn hash(&self, h_type: &HasherType) -> H256 {
+        self.0.hash(h_type)
+    }
+}
+
+impl std::convert::From<H256> for Commitment {
+    fn from(hash: H256) -> Commitment {
+        Commitment(hash)
+    }
+}
+
+/// Many values committed under a single `MerkleTree` root.
+/// The root is published; each value can later be opened on its own.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchCommitment {
+    commitments: Vec<Commitment>,
+    openings: Vec<Opening>,
+    tree: MerkleTree<Commitment>,
+}
+
+/// Opening of one value in a `BatchCommitment`, checked against the batch root.
+/// The leaf count is not part of the opening: the verifier supplies the one it
+/// trusts, otherwise an index could be shifted past the committed values.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BatchOpening {
+    pub commitment: Commitment,
+    pub opening: Opening,
+    pub index: usize,
+    pub proof: Vec<H256>,
+}
+
+impl BatchCommitment {
+    /// Commit to every value in `values`, each with its own blinding.
+    /// Panics if `values` is empty.
+    pub fn new<V: AsRef<[u8]>>(values: &[V]) -> Self {
+        match BatchCommitment::new_with_rng(values, &SystemRandom::new()) {
+            Ok(batch) => batch,
+            Err(CommitmentError::Empty) => {
+                panic!("Cannot build a batch commitment over zero values.")
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// `new` with every blinding drawn from `rng`.
+    pub fn new_with_rng<V: AsRef<[u8]>>(
+        values: &[V],
+        rng: &dyn SecureRandom,
+    ) -> Result<Self, CommitmentError> {
+        if values.is_empty() {
+            return Err(CommitmentError::Empty);
+        }
+        let mut commitments: Vec<Commitment> = Vec::with_capacity(values.len());
+        let mut openings: Vec<Opening> = Vec::with_capacity(values.len());
+        for value in values.iter() {
+            let (commitment, opening) = commit_with_rng(value.as_ref(), rng)?;
+            commitments.push(commitment);
+            openings.push(opening);
+        }
+        let tree = MerkleTree::new(&commitments);
+        Ok(BatchCommitment {
+            commitments,
+            openings,
+            tree,
+        })
+    }
+
+    /// The Merkle root to publish.
+    pub fn root(&self) -> H256 {
+        self.tree.root()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commitments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commitments.is_empty()
+    }
+
+    /// Opening (with inclusion proof) of the value at `index`.
+    pub fn opening(&self, index: usize) -> BatchOpening {
+        if index >= self.commitments.len() {
+            panic!("you are questing a non-existing value");
+        }
+        BatchOpening {
+            commitment: self.commitments[index],
+            opening: self.openings[index].clone(),
+            index,
+            proof: self.tree.proof(index),
+        }
+    }
+}
+
+impl BatchOpening {
+    /// Verify the opening against the published batch `root` of `leaf_count` values.
+    pub fn verify(&self, root: &H256, leaf_count: usize) -> bool {
+        if self.index >= leaf_count || self.proof.len() != merkle_tree::proof_len(leaf_count) {
+            return false;
+        }
+        if !self.commitment.verify(&self.opening) {
+            return false;
+        }
+        merkle_tree::verify(
+            root,
+            &self.commitment.hash(&HASH_TYPE),
+            &self.proof,
+            self.index,
+            leaf_count,
+        )
+    }
+
+    /// Open the value, if the opening is valid under `root`.
+    pub fn open(&self, root: &H256, leaf_count: usize) -> Option<&[u8]> {
+        if self.verify(root, leaf_count) {
+            Some(&self.opening.value)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_and_open() {
+        let (commitment, opening) = commit(b"sealed bid: 42");
+        assert!(commitment.verify(&opening));
+        assert_eq!(commitment.open(&opening), Some(&b"sealed bid: 42"[..]));
+    }
+
+    #[test]
+    fn wrong_value_or_blinding_rejected() {
+        let (commitment, opening) = commit(b"heads");
+        let mut wrong_value = opening.clone();
+        wrong_value.value = b"tails".to_vec();
+        assert!(!commitment.verify(&wrong_value));
+        assert_eq!(commitment.open(&wrong_value), None);
+
+        let mut wrong_blinding = opening.clone();
+        wrong_blinding.blinding[0] ^= 1;
+        assert!(!commitment.verify(&wrong_blinding));
+    }
+
+    #[test]
+    fn same_value_hides_behind_blinding() {
+        let (c1, _) = commit(b"same");
+        let (c2, _) = commit(b"same");
+        assert_ne!(c1, c2);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let (commitment, opening) = commit(b"beacon contribution");
+        let c_bytes = bincode::serialize(&commitment).unwrap();
+        let o_bytes = bincode::serialize(&opening).unwrap();
+        let c_back: Commitment = bincode::deserialize(&c_bytes).unwrap();
+        let o_back: Opening = bincode::deserialize(&o_bytes).unwrap();
+        assert_eq!(c_back, commitment);
+        assert!(c_back.verify(&o_back));
+    }
+
+    #[test]
+    fn batch_open_every_value() {
+        let values: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 3]).collect();
+        let batch = BatchCommitment::new(&values);
+        let root = batch.root();
+        for (i, value) in values.iter().enumerate() {
+            let batch_opening = batch.opening(i);
+            assert!(batch_opening.verify(&root, values.len()));
+            assert_eq!(batch_opening.open(&root, values.len()), Some(&value[..]));
+        }
+    }
+
+    #[test]
+    fn batch_tampered_opening_rejected() {
+        let values = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let batch = BatchCommitment::new(&values);
+        let root = batch.root();
+
+        let mut tampered = batch.opening(1);
+        tampered.opening.value = b"z".to_vec();
+        assert!(!tampered.verify(&root, 3));
+
+        // a valid standalone opening moved to another index must not verify
+        let mut moved = batch.opening(1);
+        moved.index = 0;
+        assert!(!moved.verify(&root, 3));
+
+        let other_root = BatchCommitment::new(&values).root();
+        assert!(!batch.opening(1).verify(&other_root, 3));
+    }
+
+    #[test]
+    fn batch_with_rng() {
+        use crate::rng::tests::{FailingRng, FixedRng};
+        let values = vec![b"a".to_vec(), b"b".to_vec()];
+        let rng = FixedRng(vec![7]);
+        let batch = BatchCommitment::new_with_rng(&values, &rng).unwrap();
+        assert_eq!(
+            batch.root(),
+            BatchCommitment::new_with_rng(&values, &rng).unwrap().root()
+        );
+        assert_eq!(batch.opening(0).opening.blinding, [7u8; 32]);
+        assert!(batch.opening(1).verify(&batch.root(), 2));
+        assert!(matches!(
+            BatchCommitment::new_with_rng(&values, &FailingRng),
+            Err(CommitmentError::Random(_))
+        ));
+        let empty: Vec<Vec<u8>> = vec![];
+        assert!(matches!(
+            BatchCommitment::new_with_rng(&empty, &rng),
+            Err(CommitmentError::Empty)
+        ));
+    }
+
+    #[test]
+    fn batch_index_aliasing_rejected() {
+        let values = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let batch = BatchCommitment::new(&values);
+        let root = batch.root();
+        assert!(batch.opening(2).verify(&root, 3));
+
+        // the last leaf is duplicated to fill the tree, but index 3 was never committed
+        let mut duplicated = batch.opening(2);
+        duplicated.index = 3;
+        assert!(!duplicated.verify(&root, 3));
+
+        // shifting by a multiple of 2^depth walks the same path
+        for i in 0..3 {
+            let mut shifted = batch.opening(i);
+            shifted.index = i + 4;
+            assert!(!shifted.verify(&root, 3));
+        }
+
+        // a proof padded to look like a deeper tree
+        let mut padded = batch.opening(0);
+        padded.proof.insert(0, root);
+        assert!(!padded.verify(&root, 3));
+    }
+}
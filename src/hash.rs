@@ -1,3 +1,5 @@
+use crate::argon2::{self, PowParams};
+use crate::secret::{constant_time_eq, Zeroize};
 use blake3;
 use hex;
 use serde::{Deserialize, Serialize};
@@ -12,13 +14,23 @@ use std::convert::TryInto;
 // this way we can merge multiple types of hasher into one union, and use match to determine the hasher type
 
 pub enum Hasher {
-    Blake3Hash { hasher: blake3::Hasher },
-    RingSHA256 { hasher: ring::digest::Context },
+    Blake3Hash {
+        hasher: blake3::Hasher,
+    },
+    RingSHA256 {
+        hasher: ring::digest::Context,
+    },
+    // memory-hard, input is buffered and only hashed on finish
+    Argon2id {
+        params: PowParams,
+        buffer: Vec<u8>,
+    },
 }
 
 pub enum HasherType {
     Blake3Hash,
     RingSHA256,
+    Argon2id(PowParams),
 }
 
 /// An object that can be meaningfully hashed.
@@ -45,6 +57,12 @@ impl Hasher {
                     hasher: ring::digest::Context::new(&ring::digest::SHA256),
                 };
             }
+            HasherType::Argon2id(params) => {
+                return Hasher::Argon2id {
+                    params,
+                    buffer: Vec::new(),
+                };
+            }
         }
     }
 
@@ -56,6 +74,9 @@ impl Hasher {
             Hasher::RingSHA256 { hasher } => {
                 *hasher = ring::digest::Context::new(&ring::digest::SHA256);
             }
+            Hasher::Argon2id { buffer, .. } => {
+                buffer.clear();
+            }
         }
         // self.internal.reset();
     }
@@ -68,6 +89,9 @@ impl Hasher {
             Hasher::RingSHA256 { hasher } => {
                 return (*hasher).clone().finish().into();
             }
+            Hasher::Argon2id { params, buffer } => {
+                return argon2::pow_hash(buffer, params);
+            }
         }
         // self.internal.finalize().into()
     }
@@ -80,6 +104,9 @@ impl Hasher {
             Hasher::RingSHA256 { hasher } => {
                 hasher.update(input);
             }
+            Hasher::Argon2id { buffer, .. } => {
+                buffer.extend_from_slice(input);
+            }
         }
         // self.internal.update(input);
     }
@@ -90,9 +117,62 @@ pub fn get_hasher() -> blake3::Hasher {
 }
 
 /// A SHA256 hash.
-#[derive(Eq, PartialEq, Serialize, Deserialize, Clone, Hash, Default, Copy)]
+#[derive(Eq, Serialize, Deserialize, Clone, Default, Copy)]
 pub struct H256([u8; 32]); // big endian u256
 
+// constant time, hashes are compared against roots and MACs in verification paths
+impl PartialEq for H256 {
+    fn eq(&self, other: &H256) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl std::hash::Hash for H256 {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        self.0.hash(state);
+    }
+}
+
+impl Zeroize for H256 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl H256 {
+    /// XOR distance to `other`, the Kademlia metric. Compare distances with `Ord`.
+    pub fn xor(&self, other: &H256) -> H256 {
+        let mut out: [u8; 32] = [0; 32];
+        for (byte, (a, b)) in out.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            *byte = a ^ b;
+        }
+        H256(out)
+    }
+
+    /// number of leading zero bits, 256 for the zero hash
+    pub fn leading_zeros(&self) -> u32 {
+        let mut zeros = 0;
+        for byte in self.0.iter() {
+            if *byte != 0 {
+                return zeros + byte.leading_zeros();
+            }
+            zeros += 8;
+        }
+        zeros
+    }
+
+    /// Index of the k-bucket `other` falls into as seen from `self`:
+    /// bucket i holds distances in [2^i, 2^(i+1)). `None` when both are equal.
+    pub fn bucket_index(&self, other: &H256) -> Option<usize> {
+        let zeros = self.xor(other).leading_zeros();
+        if zeros == 256 {
+            None
+        } else {
+            Some(255 - zeros as usize)
+        }
+    }
+}
+
 impl Hashable for H256 {
     fn hash(&self, h_type: &HasherType) -> H256 {
         // ring::digest::digest(&ring::digest::SHA256, &self.0).into()
@@ -242,9 +322,21 @@ impl PartialOrd for H256 {
 
 // for H160
 /// A hash with 160 bits, used in btc address .
-#[derive(Eq, PartialEq, Serialize, Deserialize, Clone, Hash, Default, Copy)]
+#[derive(Eq, Serialize, Deserialize, Clone, Default, Copy)]
 pub struct H160([u8; 20]); // big endian u256
 
+impl PartialEq for H160 {
+    fn eq(&self, other: &H160) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl std::hash::Hash for H160 {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        self.0.hash(state);
+    }
+}
+
 // impl Hashable for H160 {
 //     fn hash(&self) -> H160 {
 //         ring::digest::digest(&ring::digest::SHA256, &self.0).into()
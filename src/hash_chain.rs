@@ -0,0 +1,277 @@
+use crate::hash::{Hasher, H256};
+use crate::secret::Zeroize;
+use crate::HASH_TYPE;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// ``` notrust
+/// A hash chain of length n, as used by S/KEY one-time passwords and PayWord
+/// micropayments.
+///
+///  seed -> H(seed) -> H(H(seed)) -> ... -> H^n(seed) = anchor
+///
+/// The anchor is published first. Afterwards elements are revealed backwards,
+/// the element at `position` p is H^(n-p)(seed), so anyone holding the anchor
+/// can check it by hashing it p times.
+///
+///  position:  n     n-1   ...   1     0
+///  element:  seed   H()   ...  H^(n-1) anchor
+///
+/// Instead of keeping all n elements we keep a checkpoint every ceil(sqrt(n))
+/// steps, so any element costs at most sqrt(n) hashes and memory is O(sqrt(n)).
+/// ```
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct HashChain {
+    length: usize,
+    // distance (in hashes) between two neighbouring checkpoints
+    interval: usize,
+    // checkpoints[k] = H^(k * interval)(seed), checkpoints[0] is the seed itself
+    checkpoints: Vec<H256>,
+    anchor: H256,
+}
+
+// mirrors HashChain, checked before `get` can divide by `interval` or index checkpoints
+#[derive(Deserialize)]
+struct HashChainFields {
+    length: usize,
+    interval: usize,
+    checkpoints: Vec<H256>,
+    anchor: H256,
+}
+
+impl<'de> Deserialize<'de> for HashChain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HashChain, D::Error> {
+        let mut fields = HashChainFields::deserialize(deserializer)?;
+        let expected = match (fields.length, fields.interval) {
+            (0, _) => 0,
+            (_, 0) => usize::MAX,
+            (length, interval) => (length - 1) / interval + 1,
+        };
+        if fields.checkpoints.len() != expected {
+            for checkpoint in fields.checkpoints.iter_mut() {
+                checkpoint.zeroize();
+            }
+            return Err(serde::de::Error::custom(
+                "hash chain interval does not match its checkpoints",
+            ));
+        }
+        Ok(HashChain {
+            length: fields.length,
+            interval: fields.interval,
+            checkpoints: fields.checkpoints,
+            anchor: fields.anchor,
+        })
+    }
+}
+
+/// one step along the chain
+fn step(ctx: &mut Hasher, input: &H256) -> H256 {
+    ctx.reset();
+    ctx.update(input.as_ref());
+    ctx.finish()
+}
+
+/// hash `start` for `times` steps
+fn walk(start: &H256, times: usize) -> H256 {
+    let mut ctx = Hasher::new(&HASH_TYPE);
+    let mut current = *start;
+    for _ in 0..times {
+        current = step(&mut ctx, &current);
+    }
+    current
+}
+
+// the checkpoints are preimages of elements not yet revealed
+impl Drop for HashChain {
+    fn drop(&mut self) {
+        for checkpoint in self.checkpoints.iter_mut() {
+            checkpoint.zeroize();
+        }
+    }
+}
+
+impl HashChain {
+    /// Generate a chain of `length` hashes starting from `seed`.
+    pub fn new(seed: &H256, length: usize) -> Self {
+        if length == 0 {
+            panic!("Hash chain length must be larger than 0");
+        }
+        // ceil(sqrt(length))
+        let mut interval = (length as f64).sqrt() as usize;
+        if interval * interval < length {
+            interval += 1;
+        }
+
+        let mut ctx = Hasher::new(&HASH_TYPE);
+        let mut checkpoints: Vec<H256> = Vec::with_capacity(length / interval + 1);
+        let mut current = *seed;
+        for i in 0..length {
+            if i % interval == 0 {
+                checkpoints.push(current);
+            }
+            current = step(&mut ctx, &current);
+        }
+        HashChain {
+            length,
+            interval,
+            checkpoints,
+            anchor: current,
+        }
+    }
+
+    /// The last element of the chain, H^n(seed), to be published up front.
+    pub fn anchor(&self) -> H256 {
+        self.anchor
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// number of stored checkpoints, roughly sqrt(n)
+    pub fn checkpoint_count(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Element at `position`, i.e. the value that hashes to the anchor in
+    /// `position` steps. Position 0 is the anchor, position n is the seed.
+    pub fn get(&self, position: usize) -> Option<H256> {
+        if position > self.length {
+            return None;
+        }
+        if position == 0 {
+            return Some(self.anchor);
+        }
+        // how far away from the seed we are
+        let steps = self.length - position;
+        let checkpoint = &self.checkpoints[steps / self.interval];
+        Some(walk(checkpoint, steps % self.interval))
+    }
+
+    /// Reveal elements in the order they are meant to be spent: position 1, 2, ..., n.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, H256)> + '_ {
+        (1..=self.length).map(move |position| (position, self.get(position).unwrap()))
+    }
+}
+
+/// Verify that `element` sits at `position` of the chain of `length` ending in
+/// `anchor`. Costs `position` hashes, so positions past `length` are rejected
+/// before walking.
+pub fn verify(anchor: &H256, element: &H256, position: usize, length: usize) -> bool {
+    if position > length {
+        return false;
+    }
+    walk(element, position) == *anchor
+}
+
+/// Verify a newly revealed element against the last one we accepted, so a
+/// receiver only pays for the hashes between the two positions. `length` is
+/// the length of the chain, positions past it are rejected before walking.
+pub fn verify_from(
+    known: &H256,
+    known_position: usize,
+    element: &H256,
+    position: usize,
+    length: usize,
+) -> bool {
+    if position <= known_position || position > length {
+        return false;
+    }
+    walk(element, position - known_position) == *known
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! gen_seed {
+        () => {{
+            (hex!("0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d")).into()
+        }};
+    }
+
+    #[test]
+    fn anchor_is_nth_hash() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 10);
+        assert_eq!(chain.anchor(), walk(&seed, 10));
+        assert_eq!(chain.get(0), Some(chain.anchor()));
+        assert_eq!(chain.get(10), Some(seed));
+        assert_eq!(chain.get(11), None);
+    }
+
+    #[test]
+    fn every_position_verifies() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 37);
+        let anchor = chain.anchor();
+        for (position, element) in chain.iter() {
+            assert_eq!(element, walk(&seed, 37 - position));
+            assert!(verify(&anchor, &element, position, 37));
+            assert!(!verify(&anchor, &element, position + 1, 37));
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_sqrt() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 10000);
+        assert_eq!(chain.checkpoint_count(), 100);
+        let chain = HashChain::new(&seed, 10001);
+        assert!(chain.checkpoint_count() <= 101);
+    }
+
+    #[test]
+    fn incremental_payments() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 100);
+        let mut known = chain.anchor();
+        let mut known_position = 0;
+        // pay 1, then 5, then 3 units
+        for amount in [1, 5, 3].iter() {
+            let position = known_position + amount;
+            let payword = chain.get(position).unwrap();
+            assert!(verify_from(&known, known_position, &payword, position, 100));
+            known = payword;
+            known_position = position;
+        }
+        // replaying an old payword must not be accepted
+        let old = chain.get(6).unwrap();
+        assert!(!verify_from(&known, known_position, &old, 6, 100));
+    }
+
+    #[test]
+    fn positions_past_length_rejected() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 10);
+        let anchor = chain.anchor();
+        // the seed hashed 11 times is a valid element of a longer chain only
+        let beyond = HashChain::new(&seed, 11).anchor();
+        assert!(verify(&beyond, &seed, 11, 11));
+        assert!(!verify(&beyond, &seed, 11, 10));
+        // returns before walking, otherwise this would never finish
+        assert!(!verify(&anchor, &seed, usize::MAX, 10));
+        assert!(!verify_from(&anchor, 0, &seed, usize::MAX, 10));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let seed: H256 = gen_seed!();
+        let chain = HashChain::new(&seed, 50);
+        let bytes = bincode::serialize(&chain).unwrap();
+        let chain_back: HashChain = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(chain_back.anchor(), chain.anchor());
+        assert_eq!(chain_back.get(17), chain.get(17));
+
+        // interval 0 would divide by zero in `get`
+        let mut broken: serde_json::Value = serde_json::to_value(&chain).unwrap();
+        broken["interval"] = 0.into();
+        assert!(serde_json::from_value::<HashChain>(broken).is_err());
+        let mut broken: serde_json::Value = serde_json::to_value(&chain).unwrap();
+        broken["interval"] = 50.into();
+        assert!(serde_json::from_value::<HashChain>(broken).is_err());
+    }
+}
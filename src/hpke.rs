@@ -0,0 +1,572 @@
+//! Public-key encryption to X25519 recipients, HPKE base mode (RFC 9180).
+//!
+//! The suite is DHKEM(X25519, HKDF-SHA256) + HKDF-SHA256 + an AEAD from
+//! `crate::aead`. X25519 is done with `x25519-dalek` on both sides: ring has
+//! no long lived X25519 keys, and its ephemeral keys can't be drawn from a
+//! caller supplied random source.
+//!
+//! ```notrust
+//!   sender:    skE, pkE = ephemeral; dh = X25519(skE, pkR)
+//!   both:      shared_secret = ExtractAndExpand(dh, pkE || pkR)
+//!              key, base_nonce = KeySchedule(mode_base, shared_secret, info)
+//!   envelope:  version(1) | aead id(2, BE) | enc = pkE(32) | ciphertext || tag
+//! ```
+//!
+//! Each envelope is a single-shot HPKE message (sequence number 0), so
+//! `open_base`/`seal_base` interoperate with other RFC 9180 implementations.
+use crate::aead::{AeadAlgorithm, TAG_LEN};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{Zeroize, Zeroizing};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+const VERSION: u8 = 1;
+const KEM_ID: u16 = 0x0020;
+const KDF_ID: u16 = 0x0001;
+const MODE_BASE: u8 = 0x00;
+pub const ENC_LEN: usize = 32;
+const HEADER_LEN: usize = 1 + 2 + ENC_LEN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HpkeError {
+    InvalidKey,
+    /// the envelope is too short or malformed
+    InvalidEnvelope,
+    UnsupportedAead(u16),
+    /// wrong recipient, wrong info/aad or tampered ciphertext
+    DecryptionFailed,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for HpkeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HpkeError::InvalidKey => write!(f, "invalid x25519 key"),
+            HpkeError::InvalidEnvelope => write!(f, "malformed hpke envelope"),
+            HpkeError::UnsupportedAead(id) => write!(f, "unsupported aead id {:#06x}", id),
+            HpkeError::DecryptionFailed => write!(f, "decryption failed"),
+            HpkeError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HpkeError {}
+
+impl From<RandomError> for HpkeError {
+    fn from(e: RandomError) -> Self {
+        HpkeError::Random(e)
+    }
+}
+
+/// The HPKE AEAD identifier for `algorithm`.
+pub fn aead_id(algorithm: AeadAlgorithm) -> u16 {
+    match algorithm {
+        AeadAlgorithm::Aes256Gcm => 0x0002,
+        AeadAlgorithm::ChaCha20Poly1305 => 0x0003,
+    }
+}
+
+fn aead_from_id(id: u16) -> Option<AeadAlgorithm> {
+    match id {
+        0x0002 => Some(AeadAlgorithm::Aes256Gcm),
+        0x0003 => Some(AeadAlgorithm::ChaCha20Poly1305),
+        _ => None,
+    }
+}
+
+/// An X25519 public key to encrypt to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RecipientPublicKey([u8; 32]);
+
+impl RecipientPublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<RecipientPublicKey, HpkeError> {
+        if bytes.len() != 32 {
+            return Err(HpkeError::InvalidKey);
+        }
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(bytes);
+        Ok(RecipientPublicKey(raw))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+/// A long lived X25519 key pair able to open envelopes.
+pub struct RecipientKeyPair {
+    secret: x25519_dalek::StaticSecret,
+    public: RecipientPublicKey,
+}
+
+impl RecipientKeyPair {
+    pub fn generate() -> RecipientKeyPair {
+        RecipientKeyPair::generate_with_rng(&SystemRandom::new()).unwrap()
+    }
+
+    /// A key pair whose private scalar is drawn from `rng`.
+    pub fn generate_with_rng(rng: &dyn SecureRandom) -> Result<RecipientKeyPair, RandomError> {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        rng.fill(&mut *secret)?;
+        Ok(RecipientKeyPair::from_secret_bytes(&*secret).unwrap())
+    }
+
+    /// Rebuild from the 32-byte private scalar (clamped as usual by X25519).
+    pub fn from_secret_bytes(bytes: &[u8]) -> Result<RecipientKeyPair, HpkeError> {
+        if bytes.len() != 32 {
+            return Err(HpkeError::InvalidKey);
+        }
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(bytes);
+        let secret = x25519_dalek::StaticSecret::from(raw);
+        raw.zeroize();
+        let public = RecipientPublicKey(*x25519_dalek::PublicKey::from(&secret).as_bytes());
+        Ok(RecipientKeyPair { secret, public })
+    }
+
+    pub fn secret_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.secret.to_bytes())
+    }
+
+    pub fn public_key(&self) -> RecipientPublicKey {
+        self.public
+    }
+
+    fn diffie_hellman(&self, peer: &[u8; 32]) -> Result<Zeroizing<[u8; 32]>, HpkeError> {
+        let shared = self
+            .secret
+            .diffie_hellman(&x25519_dalek::PublicKey::from(*peer));
+        let shared = Zeroizing::new(*shared.as_bytes());
+        // a low order peer point gives an all zero secret, RFC 9180 section 7.1.4
+        if shared.iter().all(|&b| b == 0) {
+            return Err(HpkeError::DecryptionFailed);
+        }
+        Ok(shared)
+    }
+}
+
+impl std::fmt::Debug for RecipientKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RecipientKeyPair")
+            .field("public", &self.public.to_hex())
+            .finish()
+    }
+}
+
+/// Encrypt `plaintext` to `recipient` and wrap it in an envelope.
+pub fn seal(
+    recipient: &RecipientPublicKey,
+    algorithm: AeadAlgorithm,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, HpkeError> {
+    seal_with_rng(
+        recipient,
+        algorithm,
+        info,
+        aad,
+        plaintext,
+        &SystemRandom::new(),
+    )
+}
+
+/// `seal` with the sender's ephemeral key drawn from `rng`.
+pub fn seal_with_rng(
+    recipient: &RecipientPublicKey,
+    algorithm: AeadAlgorithm,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    rng: &dyn SecureRandom,
+) -> Result<Vec<u8>, HpkeError> {
+    let (enc, ciphertext) = seal_base_with_rng(recipient, algorithm, info, aad, plaintext, rng)?;
+    let mut envelope = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    envelope.push(VERSION);
+    envelope.extend_from_slice(&aead_id(algorithm).to_be_bytes());
+    envelope.extend_from_slice(&enc);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Open an envelope made by `seal`; `info` and `aad` must match.
+pub fn open(
+    recipient: &RecipientKeyPair,
+    envelope: &[u8],
+    info: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, HpkeError> {
+    if envelope.len() < HEADER_LEN + TAG_LEN || envelope[0] != VERSION {
+        return Err(HpkeError::InvalidEnvelope);
+    }
+    let id = u16::from_be_bytes([envelope[1], envelope[2]]);
+    let algorithm = aead_from_id(id).ok_or(HpkeError::UnsupportedAead(id))?;
+    let mut enc = [0u8; ENC_LEN];
+    enc.copy_from_slice(&envelope[3..HEADER_LEN]);
+    open_base(
+        recipient,
+        algorithm,
+        &enc,
+        info,
+        aad,
+        &envelope[HEADER_LEN..],
+    )
+}
+
+/// RFC 9180 single-shot `SealBase`, returning the encapsulated key and ciphertext.
+pub fn seal_base(
+    recipient: &RecipientPublicKey,
+    algorithm: AeadAlgorithm,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; ENC_LEN], Vec<u8>), HpkeError> {
+    seal_base_with_rng(
+        recipient,
+        algorithm,
+        info,
+        aad,
+        plaintext,
+        &SystemRandom::new(),
+    )
+}
+
+/// `seal_base` with the ephemeral key (skE) drawn from `rng`.
+pub fn seal_base_with_rng(
+    recipient: &RecipientPublicKey,
+    algorithm: AeadAlgorithm,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    rng: &dyn SecureRandom,
+) -> Result<([u8; ENC_LEN], Vec<u8>), HpkeError> {
+    let ephemeral = RecipientKeyPair::generate_with_rng(rng)?;
+    let enc = ephemeral.public.0;
+    // a low order recipient key gives an all zero secret
+    let dh = ephemeral
+        .diffie_hellman(&recipient.0)
+        .map_err(|_| HpkeError::InvalidKey)?;
+    let shared_secret = extract_and_expand(&dh[..], &enc, &recipient.0);
+    let (key, nonce) = key_schedule(algorithm, &shared_secret, info);
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| HpkeError::InvalidEnvelope)?;
+    Ok((enc, in_out))
+}
+
+/// RFC 9180 single-shot `OpenBase`.
+pub fn open_base(
+    recipient: &RecipientKeyPair,
+    algorithm: AeadAlgorithm,
+    enc: &[u8; ENC_LEN],
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, HpkeError> {
+    let dh = recipient.diffie_hellman(enc)?;
+    let shared_secret = extract_and_expand(&dh[..], enc, &recipient.public.0);
+    let (key, nonce) = key_schedule(algorithm, &shared_secret, info);
+
+    let mut in_out = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| HpkeError::DecryptionFailed)?
+        .len();
+    in_out.truncate(len);
+    Ok(in_out)
+}
+
+fn kem_suite_id() -> Vec<u8> {
+    let mut id = b"KEM".to_vec();
+    id.extend_from_slice(&KEM_ID.to_be_bytes());
+    id
+}
+
+fn hpke_suite_id(algorithm: AeadAlgorithm) -> Vec<u8> {
+    let mut id = b"HPKE".to_vec();
+    id.extend_from_slice(&KEM_ID.to_be_bytes());
+    id.extend_from_slice(&KDF_ID.to_be_bytes());
+    id.extend_from_slice(&aead_id(algorithm).to_be_bytes());
+    id
+}
+
+// DHKEM ExtractAndExpand, kem_context = enc || pkR
+fn extract_and_expand(dh: &[u8], enc: &[u8], recipient: &[u8]) -> Zeroizing<Vec<u8>> {
+    let suite_id = kem_suite_id();
+    let eae_prk = labeled_extract(&suite_id, b"", b"eae_prk", dh);
+    let kem_context = [enc, recipient].concat();
+    labeled_expand(&suite_id, &eae_prk, b"shared_secret", &kem_context, 32)
+}
+
+fn key_schedule(
+    algorithm: AeadAlgorithm,
+    shared_secret: &[u8],
+    info: &[u8],
+) -> (LessSafeKey, Nonce) {
+    let suite_id = hpke_suite_id(algorithm);
+    let psk_id_hash = labeled_extract(&suite_id, b"", b"psk_id_hash", b"");
+    let info_hash = labeled_extract(&suite_id, b"", b"info_hash", info);
+    let mut context = vec![MODE_BASE];
+    context.extend_from_slice(&psk_id_hash);
+    context.extend_from_slice(&info_hash);
+
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", b"");
+    let ring_algorithm = algorithm.ring_algorithm();
+    let key = labeled_expand(
+        &suite_id,
+        &secret,
+        b"key",
+        &context,
+        ring_algorithm.key_len(),
+    );
+    let base_nonce = labeled_expand(&suite_id, &secret, b"base_nonce", &context, NONCE_LEN);
+    let key = LessSafeKey::new(UnboundKey::new(ring_algorithm, &key).unwrap());
+    // sequence number 0, so the nonce is base_nonce itself
+    (key, Nonce::try_assume_unique_for_key(&base_nonce).unwrap())
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Zeroizing<Vec<u8>> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, salt);
+    let mut ctx = hmac::Context::with_key(&key);
+    ctx.update(b"HPKE-v1");
+    ctx.update(suite_id);
+    ctx.update(label);
+    ctx.update(ikm);
+    Zeroizing::new(ctx.sign().as_ref().to_vec())
+}
+
+// HKDF-Expand with info = I2OSP(L, 2) || "HPKE-v1" || suite_id || label || info
+fn labeled_expand(
+    suite_id: &[u8],
+    prk: &[u8],
+    label: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Zeroizing<Vec<u8>> {
+    let mut labeled_info = (len as u16).to_be_bytes().to_vec();
+    labeled_info.extend_from_slice(b"HPKE-v1");
+    labeled_info.extend_from_slice(suite_id);
+    labeled_info.extend_from_slice(label);
+    labeled_info.extend_from_slice(info);
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, prk);
+    let mut out = Zeroizing::new(Vec::with_capacity(len));
+    let mut previous = Zeroizing::new(vec![]);
+    let mut counter = 1u8;
+    while out.len() < len {
+        let mut ctx = hmac::Context::with_key(&key);
+        ctx.update(&previous);
+        ctx.update(&labeled_info);
+        ctx.update(&[counter]);
+        previous = Zeroizing::new(ctx.sign().as_ref().to_vec());
+        out.extend_from_slice(&previous);
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [AeadAlgorithm; 2] =
+        [AeadAlgorithm::Aes256Gcm, AeadAlgorithm::ChaCha20Poly1305];
+
+    #[test]
+    fn rfc7748_x25519() {
+        // RFC 7748 section 6.1
+        let alice = RecipientKeyPair::from_secret_bytes(&hex!(
+            "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a"
+        ))
+        .unwrap();
+        let bob = RecipientKeyPair::from_secret_bytes(&hex!(
+            "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb"
+        ))
+        .unwrap();
+        assert_eq!(
+            alice.public_key().to_hex(),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"
+        );
+        assert_eq!(
+            bob.public_key().to_hex(),
+            "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"
+        );
+        let shared = alice.diffie_hellman(bob.public_key().as_bytes()).unwrap();
+        assert_eq!(
+            hex::encode(&shared[..]),
+            "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"
+        );
+        assert_eq!(
+            bob.diffie_hellman(alice.public_key().as_bytes()).unwrap(),
+            shared
+        );
+    }
+
+    #[test]
+    fn opens_messages_from_another_implementation() {
+        // single-shot base mode messages produced by OpenSSL's HPKE (via pyca/cryptography)
+        // for recipient secret 00..1f, info "crypto_lib hpke test", empty aad
+        let recipient = RecipientKeyPair::from_secret_bytes(&hex!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+        ))
+        .unwrap();
+        assert_eq!(
+            recipient.public_key().to_hex(),
+            "8f40c5adb68f25624ae5b214ea767a6ec94d829d3d7b5e1ad1ba6f3e2138285f"
+        );
+        let messages = [
+            (
+                AeadAlgorithm::Aes256Gcm,
+                "911cc2e1be4260019b0f6b281af6f41a7c0c98f1eefd921b4b5fef10d0dd3864\
+                 2a96b0ab05986ca853f6c9e1c1a0c1a3c12e8f3423237fcd581be3e0f6531964138fa277",
+            ),
+            (
+                AeadAlgorithm::ChaCha20Poly1305,
+                "e86f0bdf979968995772e6d86b99cc02446e5d27b5bc83c747076c1886f36115\
+                 c0399741366fef52b7452db04519ea37b7c427843868403484ecc9c56f459dd1bedc8bd2",
+            ),
+        ];
+        for (algorithm, message) in messages.iter() {
+            let bytes = hex::decode(message).unwrap();
+            let mut enc = [0u8; ENC_LEN];
+            enc.copy_from_slice(&bytes[..ENC_LEN]);
+            let plaintext = open_base(
+                &recipient,
+                *algorithm,
+                &enc,
+                b"crypto_lib hpke test",
+                b"",
+                &bytes[ENC_LEN..],
+            )
+            .unwrap();
+            assert_eq!(plaintext, b"hpke interop message");
+        }
+    }
+
+    #[test]
+    fn rfc9180_base_vector() {
+        // RFC 9180 A.2.1: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, ChaCha20Poly1305,
+        // first message; injecting skEm makes sealing deterministic
+        let sk_e = hex!("f4ec9b33b792c372c1d2c2063507b684ef925b8c75a42dbcbf57d63ccd381600");
+        let recipient = RecipientKeyPair::from_secret_bytes(&hex!(
+            "8057991eef8f1f1af18f4a9491d16a1ce333f695d4db8e38da75975c4478e0fb"
+        ))
+        .unwrap();
+        assert_eq!(
+            recipient.public_key().to_hex(),
+            "4310ee97d88cc1f088a5576c77ab0cf5c3ac797f3d95139c6c84b5429c59662a"
+        );
+        let info = hex!("4f6465206f6e2061204772656369616e2055726e");
+        let aad = hex!("436f756e742d30");
+        let plaintext = hex!("4265617574792069732074727574682c20747275746820626561757479");
+        let (enc, ciphertext) = seal_base_with_rng(
+            &recipient.public_key(),
+            AeadAlgorithm::ChaCha20Poly1305,
+            &info,
+            &aad,
+            &plaintext,
+            &crate::rng::tests::FixedRng(sk_e.to_vec()),
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(enc),
+            "1afa08d3dec047a643885163f1180476fa7ddb54c6a8029ea33f95796bf2ac4a"
+        );
+        assert_eq!(
+            hex::encode(&ciphertext),
+            "1c5250d8034ec2b784ba2cfd69dbdb8af406cfe3ff938e131f0def8c8b60b4db\
+             21993c62ce81883d2dd1b51a28"
+        );
+        assert_eq!(
+            open_base(
+                &recipient,
+                AeadAlgorithm::ChaCha20Poly1305,
+                &enc,
+                &info,
+                &aad,
+                &ciphertext
+            )
+            .unwrap(),
+            plaintext.to_vec()
+        );
+        assert_eq!(
+            seal_with_rng(
+                &recipient.public_key(),
+                AeadAlgorithm::ChaCha20Poly1305,
+                &info,
+                &aad,
+                &plaintext,
+                &crate::rng::tests::FailingRng,
+            ),
+            Err(HpkeError::Random(crate::rng::RandomError))
+        );
+    }
+
+    #[test]
+    fn seal_open_round_trip() {
+        let recipient = RecipientKeyPair::generate();
+        for &algorithm in ALGORITHMS.iter() {
+            let envelope = seal(
+                &recipient.public_key(),
+                algorithm,
+                b"info",
+                b"aad",
+                b"for your eyes only",
+            )
+            .unwrap();
+            assert_eq!(
+                open(&recipient, &envelope, b"info", b"aad").unwrap(),
+                b"for your eyes only"
+            );
+            assert!(open(&recipient, &envelope, b"other", b"aad").is_err());
+            assert!(open(&recipient, &envelope, b"info", b"other").is_err());
+            assert!(open(&RecipientKeyPair::generate(), &envelope, b"info", b"aad").is_err());
+        }
+    }
+
+    #[test]
+    fn tampered_envelopes_rejected() {
+        let recipient = RecipientKeyPair::generate();
+        let envelope = seal(
+            &recipient.public_key(),
+            AeadAlgorithm::ChaCha20Poly1305,
+            b"",
+            b"",
+            b"payload",
+        )
+        .unwrap();
+        for i in 0..envelope.len() {
+            let mut flipped = envelope.clone();
+            flipped[i] ^= 0x01;
+            assert!(open(&recipient, &flipped, b"", b"").is_err(), "byte {}", i);
+        }
+        assert_eq!(
+            open(&recipient, &envelope[..HEADER_LEN], b"", b""),
+            Err(HpkeError::InvalidEnvelope)
+        );
+        // an all zero (low order) encapsulated key
+        let mut low_order = envelope;
+        for byte in low_order[3..HEADER_LEN].iter_mut() {
+            *byte = 0;
+        }
+        assert_eq!(
+            open(&recipient, &low_order, b"", b""),
+            Err(HpkeError::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn secret_round_trip_and_debug() {
+        let recipient = RecipientKeyPair::generate();
+        let again = RecipientKeyPair::from_secret_bytes(&recipient.secret_bytes()[..]).unwrap();
+        assert_eq!(again.public_key(), recipient.public_key());
+        assert!(!format!("{:?}", recipient).contains(&hex::encode(&recipient.secret_bytes()[..])));
+    }
+}
@@ -0,0 +1,563 @@
+//! ``` notrust
+//! Kademlia routing table keyed by `H256` node ids under the XOR metric.
+//!
+//! Seen from our own id, every other id falls into one of 256 buckets by the
+//! position of the highest differing bit:
+//!
+//!  bucket 255: ids that differ in the first bit  (half of the id space)
+//!  bucket 254: ids that share 1 bit, differ in the 2nd (a quarter)
+//!  ...
+//!  bucket 0:   ids that differ only in the last bit
+//!
+//! Each bucket keeps at most k peers ordered from least to most recently seen.
+//! A full bucket only lets a new peer in when its least recently seen peer
+//! fails to answer a ping, so long-lived peers are preferred.
+//! ```
+
+use crate::hash::H256;
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+/// default bucket size
+pub const K: usize = 20;
+/// default lookup parallelism
+pub const ALPHA: usize = 3;
+/// buckets not touched for this many seconds should be refreshed
+pub const REFRESH_INTERVAL: u64 = 3600;
+
+/// A peer known by its id plus whatever the transport needs to reach it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PeerInfo<A> {
+    pub id: H256,
+    pub address: A,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KBucket<A> {
+    // front is the least recently seen peer, back the most recent
+    peers: VecDeque<PeerInfo<A>>,
+    // last time (seconds) a peer in this bucket was inserted, updated or looked up
+    last_touched: u64,
+}
+
+/// What `RoutingTable::insert` did with a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertOutcome<A> {
+    /// the peer was new and there was room
+    Inserted,
+    /// the peer was already known and moved to the most recently seen end
+    Updated,
+    /// the bucket was full and this stale peer was evicted to make room
+    Evicted(PeerInfo<A>),
+    /// the bucket was full and every peer in it is alive, the new one is dropped
+    Rejected,
+    /// that is our own id
+    IsLocal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingTable<A> {
+    local_id: H256,
+    k: usize,
+    refresh_interval: u64,
+    buckets: Vec<KBucket<A>>,
+}
+
+// mirrors RoutingTable, checked before `insert` can index buckets or evict with k == 0
+#[derive(Deserialize)]
+struct RoutingTableFields<A> {
+    local_id: H256,
+    k: usize,
+    refresh_interval: u64,
+    buckets: Vec<KBucket<A>>,
+}
+
+impl<'de, A: Deserialize<'de>> Deserialize<'de> for RoutingTable<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RoutingTable<A>, D::Error> {
+        let fields = RoutingTableFields::deserialize(deserializer)?;
+        if fields.buckets.len() != 256 {
+            return Err(serde::de::Error::custom(
+                "routing table must have 256 buckets",
+            ));
+        }
+        if fields.k == 0 {
+            return Err(serde::de::Error::custom(
+                "bucket size must be larger than 0",
+            ));
+        }
+        Ok(RoutingTable {
+            local_id: fields.local_id,
+            k: fields.k,
+            refresh_interval: fields.refresh_interval,
+            buckets: fields.buckets,
+        })
+    }
+}
+
+impl<A: Clone> RoutingTable<A> {
+    pub fn new(local_id: H256, now: u64) -> Self {
+        Self::with_params(local_id, K, REFRESH_INTERVAL, now)
+    }
+
+    pub fn with_params(local_id: H256, k: usize, refresh_interval: u64, now: u64) -> Self {
+        if k == 0 {
+            panic!("Bucket size must be larger than 0");
+        }
+        let buckets = (0..256)
+            .map(|_| KBucket {
+                peers: VecDeque::new(),
+                last_touched: now,
+            })
+            .collect();
+        RoutingTable {
+            local_id,
+            k,
+            refresh_interval,
+            buckets,
+        }
+    }
+
+    pub fn local_id(&self) -> &H256 {
+        &self.local_id
+    }
+
+    /// total number of peers in the table
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.peers.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// peers of bucket `index`, least recently seen first
+    pub fn bucket(&self, index: usize) -> Vec<PeerInfo<A>> {
+        self.buckets[index].peers.iter().cloned().collect()
+    }
+
+    pub fn contains(&self, id: &H256) -> bool {
+        match self.local_id.bucket_index(id) {
+            Some(index) => self.buckets[index].peers.iter().any(|p| p.id == *id),
+            None => false,
+        }
+    }
+
+    /// Record that we heard from `peer`.
+    ///
+    /// When its bucket is full, `ping` is called with the least recently seen peer
+    /// of that bucket and must return whether it answered. A live peer is kept
+    /// (and moved to the back), a dead one is evicted in favour of `peer`.
+    pub fn insert<F>(&mut self, peer: PeerInfo<A>, now: u64, mut ping: F) -> InsertOutcome<A>
+    where
+        F: FnMut(&PeerInfo<A>) -> bool,
+    {
+        let index = match self.local_id.bucket_index(&peer.id) {
+            Some(index) => index,
+            None => return InsertOutcome::IsLocal,
+        };
+        let k = self.k;
+        let bucket = &mut self.buckets[index];
+        bucket.last_touched = now;
+
+        if let Some(pos) = bucket.peers.iter().position(|p| p.id == peer.id) {
+            bucket.peers.remove(pos);
+            bucket.peers.push_back(peer);
+            return InsertOutcome::Updated;
+        }
+        if bucket.peers.len() < k {
+            bucket.peers.push_back(peer);
+            return InsertOutcome::Inserted;
+        }
+
+        let oldest = bucket.peers.pop_front().unwrap();
+        if ping(&oldest) {
+            bucket.peers.push_back(oldest);
+            InsertOutcome::Rejected
+        } else {
+            bucket.peers.push_back(peer);
+            InsertOutcome::Evicted(oldest)
+        }
+    }
+
+    /// Drop a peer, e.g. after repeated RPC failures.
+    pub fn remove(&mut self, id: &H256) -> Option<PeerInfo<A>> {
+        let index = self.local_id.bucket_index(id)?;
+        let bucket = &mut self.buckets[index];
+        let pos = bucket.peers.iter().position(|p| p.id == *id)?;
+        bucket.peers.remove(pos)
+    }
+
+    /// The (up to) `count` known peers closest to `target`, closest first.
+    pub fn closest(&self, target: &H256, count: usize) -> Vec<PeerInfo<A>> {
+        let mut peers: Vec<PeerInfo<A>> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.peers.iter().cloned())
+            .collect();
+        peers.sort_by_key(|p| p.id.xor(target));
+        peers.truncate(count);
+        peers
+    }
+
+    /// Mark the bucket `target` falls into as used, call this after a lookup for `target`.
+    pub fn touch(&mut self, target: &H256, now: u64) {
+        if let Some(index) = self.local_id.bucket_index(target) {
+            self.buckets[index].last_touched = now;
+        }
+    }
+
+    /// Buckets that have not been touched within the refresh interval.
+    /// Only buckets from the closest non-empty one upwards are reported: bucket
+    /// i covers 2^i ids, so the buckets closer than our nearest peer are tiny
+    /// and almost always empty, while the far buckets hold most of the network.
+    pub fn buckets_to_refresh(&self, now: u64) -> Vec<usize> {
+        let lowest = match self.buckets.iter().position(|b| !b.peers.is_empty()) {
+            Some(lowest) => lowest,
+            None => return Vec::new(),
+        };
+        (lowest..256)
+            .filter(|&i| now.saturating_sub(self.buckets[i].last_touched) >= self.refresh_interval)
+            .collect()
+    }
+
+    /// A random id that falls into bucket `index`, used as the lookup target
+    /// when refreshing that bucket. Panics unless `index < 256`, as with every
+    /// index `buckets_to_refresh` returns.
+    pub fn random_id_in_bucket(&self, index: usize) -> H256 {
+        self.random_id_in_bucket_with_rng(index, &SystemRandom::new())
+            .unwrap()
+    }
+
+    /// `random_id_in_bucket` with the low bits drawn from `rng`.
+    pub fn random_id_in_bucket_with_rng(
+        &self,
+        index: usize,
+        rng: &dyn SecureRandom,
+    ) -> Result<H256, RandomError> {
+        assert!(index < 256, "bucket index {} out of range", index);
+        let mut random = [0u8; 32];
+        rng.fill(&mut random)?;
+        let mut raw: [u8; 32] = self.local_id.into();
+        // bit position (from the most significant end) that has to differ
+        let bit = 255 - index;
+        raw[bit / 8] ^= 0x80 >> (bit % 8);
+        // everything below that bit is random
+        for pos in (bit + 1)..256 {
+            let mask = 0x80 >> (pos % 8);
+            if random[pos / 8] & mask != 0 {
+                raw[pos / 8] ^= mask;
+            }
+        }
+        Ok(raw.into())
+    }
+}
+
+/// ``` notrust
+/// Iterative FIND_NODE lookup.
+///
+/// Start from the closest peers we know, ask up to `alpha` of the closest
+/// unqueried ones for their closest peers to `target`, merge the answers and
+/// repeat until the k closest peers seen have all been queried.
+///
+/// `query` performs the RPC and returns `None` when the peer did not answer;
+/// such peers are dropped from the result.
+/// ```
+pub fn lookup<A, F>(
+    table: &RoutingTable<A>,
+    target: &H256,
+    alpha: usize,
+    mut query: F,
+) -> Vec<PeerInfo<A>>
+where
+    A: Clone,
+    F: FnMut(&PeerInfo<A>) -> Option<Vec<PeerInfo<A>>>,
+{
+    let k = table.k;
+    let mut shortlist: Vec<PeerInfo<A>> = table.closest(target, k);
+    let mut seen: HashSet<H256> = shortlist.iter().map(|p| p.id).collect();
+    seen.insert(table.local_id);
+    let mut queried: HashSet<H256> = HashSet::new();
+    let mut failed: HashSet<H256> = HashSet::new();
+
+    loop {
+        // only the k closest candidates are worth asking, the rest are kept
+        // around in case some of those turn out to be offline
+        let to_query: Vec<PeerInfo<A>> = shortlist
+            .iter()
+            .take(k)
+            .filter(|p| !queried.contains(&p.id))
+            .take(alpha)
+            .cloned()
+            .collect();
+        if to_query.is_empty() {
+            break;
+        }
+        for peer in to_query.iter() {
+            queried.insert(peer.id);
+            match query(peer) {
+                Some(found) => {
+                    for candidate in found.into_iter() {
+                        if seen.insert(candidate.id) {
+                            shortlist.push(candidate);
+                        }
+                    }
+                }
+                None => {
+                    failed.insert(peer.id);
+                }
+            }
+        }
+        shortlist.retain(|p| !failed.contains(&p.id));
+        shortlist.sort_by_key(|p| p.id.xor(target));
+    }
+    shortlist.truncate(k);
+    shortlist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::tests::generate_random_hash;
+    use crate::rng::TestRng;
+    use std::collections::HashMap;
+
+    fn id_with_first_byte(byte: u8) -> H256 {
+        let mut raw = [0u8; 32];
+        raw[0] = byte;
+        raw.into()
+    }
+
+    #[test]
+    fn xor_distance_and_bucket_index() {
+        let zero = H256::default();
+        let a = id_with_first_byte(0x80);
+        let b = id_with_first_byte(0x01);
+        assert_eq!(a.xor(&a), zero);
+        assert_eq!(a.xor(&b), id_with_first_byte(0x81));
+        assert_eq!(zero.leading_zeros(), 256);
+        assert_eq!(b.leading_zeros(), 7);
+        assert_eq!(zero.bucket_index(&a), Some(255));
+        assert_eq!(zero.bucket_index(&b), Some(248));
+        assert_eq!(a.bucket_index(&a), None);
+        let mut last = [0u8; 32];
+        last[31] = 1;
+        assert_eq!(zero.bucket_index(&last.into()), Some(0));
+    }
+
+    #[test]
+    fn insert_update_and_lru_eviction() {
+        let mut table: RoutingTable<u32> = RoutingTable::with_params(H256::default(), 2, 60, 0);
+        // all three land in bucket 255
+        let p1 = PeerInfo {
+            id: id_with_first_byte(0x80),
+            address: 1,
+        };
+        let p2 = PeerInfo {
+            id: id_with_first_byte(0x81),
+            address: 2,
+        };
+        let p3 = PeerInfo {
+            id: id_with_first_byte(0x82),
+            address: 3,
+        };
+        assert_eq!(
+            table.insert(p1.clone(), 0, |_| true),
+            InsertOutcome::Inserted
+        );
+        assert_eq!(
+            table.insert(p2.clone(), 0, |_| true),
+            InsertOutcome::Inserted
+        );
+        // p1 is seen again and becomes the most recent
+        assert_eq!(
+            table.insert(p1.clone(), 1, |_| true),
+            InsertOutcome::Updated
+        );
+        assert_eq!(table.bucket(255), vec![p2.clone(), p1.clone()]);
+
+        // bucket full, least recent (p2) answers the ping: p3 rejected
+        let mut pinged = Vec::new();
+        let outcome = table.insert(p3.clone(), 2, |p| {
+            pinged.push(p.id);
+            true
+        });
+        assert_eq!(outcome, InsertOutcome::Rejected);
+        assert_eq!(pinged, vec![p2.id]);
+        assert_eq!(table.bucket(255), vec![p1.clone(), p2.clone()]);
+
+        // now p1 is the least recent and does not answer: it is evicted
+        assert_eq!(
+            table.insert(p3.clone(), 3, |_| false),
+            InsertOutcome::Evicted(p1.clone())
+        );
+        assert_eq!(table.bucket(255), vec![p2, p3]);
+        assert!(!table.contains(&p1.id));
+    }
+
+    #[test]
+    fn local_id_never_inserted() {
+        let local = generate_random_hash();
+        let mut table: RoutingTable<()> = RoutingTable::new(local, 0);
+        let outcome = table.insert(
+            PeerInfo {
+                id: local,
+                address: (),
+            },
+            0,
+            |_| true,
+        );
+        assert_eq!(outcome, InsertOutcome::IsLocal);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn closest_matches_brute_force() {
+        let local = generate_random_hash();
+        let mut table: RoutingTable<usize> = RoutingTable::new(local, 0);
+        let peers: Vec<PeerInfo<usize>> = (0..200)
+            .map(|i| PeerInfo {
+                id: generate_random_hash(),
+                address: i,
+            })
+            .collect();
+        for peer in peers.iter() {
+            table.insert(peer.clone(), 0, |_| true);
+        }
+        let target = generate_random_hash();
+        let mut expected: Vec<PeerInfo<usize>> = peers
+            .iter()
+            .filter(|p| table.contains(&p.id))
+            .cloned()
+            .collect();
+        expected.sort_by_key(|p| p.id.xor(&target));
+        expected.truncate(K);
+        assert_eq!(table.closest(&target, K), expected);
+        assert!(table.remove(&expected[0].id).is_some());
+        assert_eq!(table.closest(&target, 1)[0], expected[1]);
+    }
+
+    #[test]
+    fn refresh_scheduling() {
+        let local = H256::default();
+        let mut table: RoutingTable<()> = RoutingTable::with_params(local, K, 100, 0);
+        assert!(table.buckets_to_refresh(1000).is_empty());
+        table.insert(
+            PeerInfo {
+                id: id_with_first_byte(0x80),
+                address: (),
+            },
+            0,
+            |_| true,
+        );
+        table.insert(
+            PeerInfo {
+                id: id_with_first_byte(0x01),
+                address: (),
+            },
+            50,
+            |_| true,
+        );
+        assert!(table.buckets_to_refresh(99).is_empty());
+        let due = table.buckets_to_refresh(120);
+        // the closest peer sits in bucket 248, nothing below it is worth a lookup
+        assert!(due.iter().all(|&index| index >= 248));
+        // 248 itself was touched at 50, 249..=255 are stale
+        assert!(!due.contains(&248));
+        assert_eq!(due, (249..256).collect::<Vec<usize>>());
+
+        for index in due.iter() {
+            let target = table.random_id_in_bucket(*index);
+            assert_eq!(local.bucket_index(&target), Some(*index));
+            table.touch(&target, 120);
+        }
+        assert!(table.buckets_to_refresh(120).is_empty());
+    }
+
+    #[test]
+    fn lookup_in_simulated_network() {
+        // every simulated node has heard of every other node, the buckets
+        // then decide which ones it actually keeps
+        // a fixed seed keeps the run (and its convergence) the same every time
+        let rng = TestRng::new(b"kademlia lookup in simulated network");
+        let random_id = || {
+            let mut raw = [0u8; 32];
+            rng.fill(&mut raw).unwrap();
+            H256::from(raw)
+        };
+        let ids: Vec<H256> = (0..300).map(|_| random_id()).collect();
+        let mut network: HashMap<H256, RoutingTable<()>> = HashMap::new();
+        for id in ids.iter() {
+            let mut table = RoutingTable::new(*id, 0);
+            for other in ids.iter() {
+                table.insert(
+                    PeerInfo {
+                        id: *other,
+                        address: (),
+                    },
+                    0,
+                    |_| true,
+                );
+            }
+            network.insert(*id, table);
+        }
+        // a few nodes are offline
+        let offline: HashSet<H256> = ids.iter().skip(1).step_by(10).cloned().collect();
+
+        let target = random_id();
+        let start = &network[&ids[0]];
+        let mut rpcs = 0;
+        let found = lookup(start, &target, ALPHA, |peer| {
+            rpcs += 1;
+            if offline.contains(&peer.id) {
+                return None;
+            }
+            Some(network[&peer.id].closest(&target, K))
+        });
+
+        let mut online: Vec<H256> = ids
+            .iter()
+            .skip(1)
+            .filter(|id| !offline.contains(id))
+            .cloned()
+            .collect();
+        online.sort_by_key(|id| id.xor(&target));
+        // lookups are only guaranteed to converge with high probability, so we
+        // check the closest few exactly and that nothing offline sneaks in
+        let found_ids: Vec<H256> = found.iter().map(|p| p.id).collect();
+        assert!(found_ids.len() > 5 && found_ids.len() <= K);
+        assert_eq!(found_ids[..5].to_vec(), online[..5].to_vec());
+        assert!(found_ids.iter().all(|id| !offline.contains(id)));
+        assert!(rpcs < ids.len());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut table: RoutingTable<u16> = RoutingTable::new(H256::default(), 0);
+        let peer = PeerInfo {
+            id: id_with_first_byte(0x80),
+            address: 8080,
+        };
+        table.insert(peer.clone(), 0, |_| true);
+        let json = serde_json::to_value(&table).unwrap();
+        let back: RoutingTable<u16> = serde_json::from_value(json.clone()).unwrap();
+        assert!(back.contains(&peer.id));
+        assert_eq!(back.closest(&peer.id, 1), vec![peer]);
+
+        // missing buckets would panic on the first insert
+        let mut broken = json.clone();
+        broken["buckets"].as_array_mut().unwrap().truncate(10);
+        assert!(serde_json::from_value::<RoutingTable<u16>>(broken).is_err());
+        // k == 0 would evict on every insert
+        let mut broken = json;
+        broken["k"] = 0.into();
+        assert!(serde_json::from_value::<RoutingTable<u16>>(broken).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket index 256 out of range")]
+    fn random_id_rejects_bucket_256() {
+        let table: RoutingTable<()> = RoutingTable::new(H256::default(), 0);
+        table.random_id_in_bucket(256);
+    }
+}
@@ -1,9 +1,761 @@
-use ring::rand;
-use ring::signature::Ed25519KeyPair;
+use crate::bip39::Mnemonic;
+use crate::hash::Hashable;
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{constant_time_eq, Zeroizing};
+use crate::slip10::{DerivationPath, ExtendedKey};
+use crate::HASH_TYPE;
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Generate a random key pair.
 pub fn random() -> Ed25519KeyPair {
-    let rng = rand::SystemRandom::new();
-    let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
-    Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref().into()).unwrap()
+    random_with_rng(&SystemRandom::new()).unwrap()
+}
+
+/// Generate a random key pair from the seed bytes `rng` produces.
+pub fn random_with_rng(rng: &dyn SecureRandom) -> Result<Ed25519KeyPair, RandomError> {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    rng.fill(&mut *seed)?;
+    // any 32 bytes are a valid Ed25519 seed
+    Ok(Ed25519KeyPair::from_seed_unchecked(&*seed).unwrap())
+}
+
+// PKCS#8 framing of an Ed25519 key, RFC 8410 / RFC 5958
+// v2 (what ring generates): prefix || seed || PKCS8_V2_MIDDLE || public key
+const PKCS8_V2_PREFIX: [u8; 16] = hex_literal::hex!("3053020101300506032b657004220420");
+const PKCS8_V2_MIDDLE: [u8; 5] = hex_literal::hex!("a123032100");
+// v1 (seed only): prefix || seed
+const PKCS8_V1_PREFIX: [u8; 16] = hex_literal::hex!("302e020100300506032b657004220420");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError {
+    /// ring refused the key material
+    InvalidKey,
+    /// wrong number of bytes for this type
+    InvalidLength,
+    InvalidHex,
+    /// ring only generates ECDSA keys from its own system random source
+    UnsupportedRng,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyError::InvalidKey => write!(f, "invalid key"),
+            KeyError::InvalidLength => write!(f, "invalid length"),
+            KeyError::InvalidHex => write!(f, "invalid hex"),
+            KeyError::UnsupportedRng => {
+                write!(
+                    f,
+                    "ECDSA keys can only be generated from the system random source"
+                )
+            }
+            KeyError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl From<RandomError> for KeyError {
+    fn from(e: RandomError) -> Self {
+        KeyError::Random(e)
+    }
+}
+
+/// An Ed25519 key pair. Keeps the 32-byte seed around so the key can be exported again.
+pub struct KeyPair {
+    seed: Zeroizing<[u8; 32]>,
+    inner: Ed25519KeyPair,
+}
+
+/// An Ed25519 public key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PublicKey([u8; 32]);
+
+/// An Ed25519 signature.
+#[derive(Clone, Copy)]
+pub struct Signature([u8; 64]);
+
+impl KeyPair {
+    /// Generate a fresh key pair from the system random source.
+    pub fn generate() -> KeyPair {
+        KeyPair::generate_with_rng(&SystemRandom::new()).unwrap()
+    }
+
+    /// Generate a key pair whose seed is drawn from `rng`.
+    pub fn generate_with_rng(rng: &dyn SecureRandom) -> Result<KeyPair, RandomError> {
+        let mut seed = Zeroizing::new([0u8; 32]);
+        rng.fill(&mut *seed)?;
+        Ok(KeyPair::from_seed(&*seed).unwrap())
+    }
+
+    /// Rebuild a key pair from its 32-byte seed (the RFC 8032 private key).
+    pub fn from_seed(seed: &[u8]) -> Result<KeyPair, KeyError> {
+        if seed.len() != 32 {
+            return Err(KeyError::InvalidLength);
+        }
+        let inner = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|_| KeyError::InvalidKey)?;
+        let mut seed_bytes = Zeroizing::new([0u8; 32]);
+        seed_bytes.copy_from_slice(seed);
+        Ok(KeyPair {
+            seed: seed_bytes,
+            inner,
+        })
+    }
+
+    /// Deterministic key for a BIP-39 backup phrase, derived along the SLIP-0010 `path`.
+    /// The same phrase, passphrase and path always give the same key.
+    pub fn from_mnemonic(mnemonic: &Mnemonic, passphrase: &str, path: &DerivationPath) -> KeyPair {
+        // a 64-byte seed and an all-hardened path can't fail to derive
+        ExtendedKey::master(&mnemonic.to_seed(passphrase)[..])
+            .and_then(|master| master.derive_path(path))
+            .unwrap()
+            .key_pair()
+    }
+
+    /// Load a PKCS#8 v1 or v2 document, as produced by `ring` or `to_pkcs8`.
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<KeyPair, KeyError> {
+        // let ring do the real validation, including the public key check for v2
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map_err(|_| KeyError::InvalidKey)?;
+        let is_v2 = pkcs8.len() == 85 && pkcs8[..16] == PKCS8_V2_PREFIX;
+        let is_v1 = pkcs8.len() == 48 && pkcs8[..16] == PKCS8_V1_PREFIX;
+        if !is_v1 && !is_v2 {
+            return Err(KeyError::InvalidKey);
+        }
+        KeyPair::from_seed(&pkcs8[16..48])
+    }
+
+    /// The PKCS#8 v2 document for this key, byte-identical to what `ring` generates.
+    pub fn to_pkcs8(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(85));
+        out.extend_from_slice(&PKCS8_V2_PREFIX);
+        out.extend_from_slice(&*self.seed);
+        out.extend_from_slice(&PKCS8_V2_MIDDLE);
+        out.extend_from_slice(self.inner.public_key().as_ref());
+        out
+    }
+
+    /// The underlying ring key pair, for code that still expects one.
+    pub fn as_ring(&self) -> &Ed25519KeyPair {
+        &self.inner
+    }
+
+    pub(crate) fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(self.inner.public_key().as_ref());
+        PublicKey(bytes)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(self.inner.sign(message).as_ref());
+        Signature(bytes)
+    }
+
+    /// Sign the crate hash of `value`.
+    /// Note the hash follows the crate wide `HASH_TYPE`, so sign and verify with the same build profile.
+    pub fn sign_hashable<T: Hashable>(&self, value: &T) -> Signature {
+        self.sign(value.hash(&HASH_TYPE).as_ref())
+    }
+}
+
+impl std::fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // the seed is secret, only ever show the public half
+        f.debug_struct("KeyPair")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl PublicKey {
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        signature::UnparsedPublicKey::new(&signature::ED25519, &self.0)
+            .verify(message, &signature.0)
+            .is_ok()
+    }
+
+    pub fn verify_hashable<T: Hashable>(&self, value: &T, signature: &Signature) -> bool {
+        self.verify(value.hash(&HASH_TYPE).as_ref(), signature)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKey, KeyError> {
+        if bytes.len() != 32 {
+            return Err(KeyError::InvalidLength);
+        }
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(bytes);
+        Ok(PublicKey(raw))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    pub fn from_hex(hex_str: &str) -> Result<PublicKey, KeyError> {
+        let bytes = hex::decode(hex_str).map_err(|_| KeyError::InvalidHex)?;
+        PublicKey::from_bytes(&bytes)
+    }
+}
+
+impl Signature {
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, KeyError> {
+        if bytes.len() != 64 {
+            return Err(KeyError::InvalidLength);
+        }
+        let mut raw = [0u8; 64];
+        raw.copy_from_slice(bytes);
+        Ok(Signature(raw))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0[..])
+    }
+
+    pub fn from_hex(hex_str: &str) -> Result<Signature, KeyError> {
+        let bytes = hex::decode(hex_str).map_err(|_| KeyError::InvalidHex)?;
+        Signature::from_bytes(&bytes)
+    }
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Signature) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Signature {}
+
+impl std::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl std::fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PublicKey({})", self.to_hex())
+    }
+}
+
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl std::fmt::Debug for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Signature({})", self.to_hex())
+    }
+}
+
+/// Signature algorithms a `SigningKey` can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+    Ed25519,
+    /// ECDSA over NIST P-256 with SHA-256, fixed length (r || s) signatures
+    EcdsaP256Sha256,
+    /// ECDSA over NIST P-384 with SHA-384, fixed length (r || s) signatures
+    EcdsaP384Sha384,
+}
+
+impl Algorithm {
+    fn ecdsa_signing(self) -> Option<&'static signature::EcdsaSigningAlgorithm> {
+        match self {
+            Algorithm::Ed25519 => None,
+            Algorithm::EcdsaP256Sha256 => Some(&signature::ECDSA_P256_SHA256_FIXED_SIGNING),
+            Algorithm::EcdsaP384Sha384 => Some(&signature::ECDSA_P384_SHA384_FIXED_SIGNING),
+        }
+    }
+
+    fn verification(self) -> &'static dyn signature::VerificationAlgorithm {
+        match self {
+            Algorithm::Ed25519 => &signature::ED25519,
+            Algorithm::EcdsaP256Sha256 => &signature::ECDSA_P256_SHA256_FIXED,
+            Algorithm::EcdsaP384Sha384 => &signature::ECDSA_P384_SHA384_FIXED,
+        }
+    }
+}
+
+/// A private key of any supported algorithm.
+pub struct SigningKey(SigningKeyInner);
+
+// kept private so the algorithm tag always matches the key
+enum SigningKeyInner {
+    Ed25519(KeyPair),
+    Ecdsa {
+        algorithm: Algorithm,
+        pkcs8: Zeroizing<Vec<u8>>,
+        inner: EcdsaKeyPair,
+    },
+}
+
+/// A public key tagged with its algorithm. ECDSA keys are uncompressed SEC1 points.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VerifyingKey {
+    pub algorithm: Algorithm,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    pub bytes: Vec<u8>,
+}
+
+/// A signature tagged with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedSignature {
+    pub algorithm: Algorithm,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    pub bytes: Vec<u8>,
+}
+
+impl SigningKey {
+    pub fn generate(algorithm: Algorithm) -> SigningKey {
+        match algorithm.ecdsa_signing() {
+            None => SigningKey::from(KeyPair::generate()),
+            Some(ecdsa) => {
+                let pkcs8 = EcdsaKeyPair::generate_pkcs8(ecdsa, &SystemRandom::new()).unwrap();
+                SigningKey::from_pkcs8(algorithm, pkcs8.as_ref()).unwrap()
+            }
+        }
+    }
+
+    /// Generate an Ed25519 key whose seed is drawn from `rng`. ring's ECDSA key
+    /// generation only accepts its own system random source, so ECDSA algorithms
+    /// fail with `UnsupportedRng`; use `generate` for those.
+    pub fn generate_with_rng(
+        algorithm: Algorithm,
+        rng: &dyn SecureRandom,
+    ) -> Result<SigningKey, KeyError> {
+        match algorithm {
+            Algorithm::Ed25519 => Ok(KeyPair::generate_with_rng(rng)?.into()),
+            Algorithm::EcdsaP256Sha256 | Algorithm::EcdsaP384Sha384 => {
+                Err(KeyError::UnsupportedRng)
+            }
+        }
+    }
+
+    pub fn from_pkcs8(algorithm: Algorithm, pkcs8: &[u8]) -> Result<SigningKey, KeyError> {
+        match algorithm.ecdsa_signing() {
+            None => KeyPair::from_pkcs8(pkcs8).map(SigningKey::from),
+            Some(ecdsa) => {
+                let inner =
+                    EcdsaKeyPair::from_pkcs8(ecdsa, pkcs8).map_err(|_| KeyError::InvalidKey)?;
+                Ok(SigningKey(SigningKeyInner::Ecdsa {
+                    algorithm,
+                    pkcs8: Zeroizing::new(pkcs8.to_vec()),
+                    inner,
+                }))
+            }
+        }
+    }
+
+    pub fn to_pkcs8(&self) -> Zeroizing<Vec<u8>> {
+        match &self.0 {
+            SigningKeyInner::Ed25519(key) => key.to_pkcs8(),
+            SigningKeyInner::Ecdsa { pkcs8, .. } => pkcs8.clone(),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match &self.0 {
+            SigningKeyInner::Ed25519(_) => Algorithm::Ed25519,
+            SigningKeyInner::Ecdsa { algorithm, .. } => *algorithm,
+        }
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        match &self.0 {
+            SigningKeyInner::Ed25519(key) => key.public_key().into(),
+            SigningKeyInner::Ecdsa {
+                algorithm, inner, ..
+            } => VerifyingKey {
+                algorithm: *algorithm,
+                bytes: inner.public_key().as_ref().to_vec(),
+            },
+        }
+    }
+
+    /// Sign `message`. ECDSA signatures are randomized, Ed25519 ones deterministic.
+    pub fn sign(&self, message: &[u8]) -> TaggedSignature {
+        match &self.0 {
+            SigningKeyInner::Ed25519(key) => key.sign(message).into(),
+            SigningKeyInner::Ecdsa {
+                algorithm, inner, ..
+            } => {
+                let rng = ring::rand::SystemRandom::new();
+                TaggedSignature {
+                    algorithm: *algorithm,
+                    bytes: inner.sign(&rng, message).unwrap().as_ref().to_vec(),
+                }
+            }
+        }
+    }
+
+    pub fn sign_hashable<T: Hashable>(&self, value: &T) -> TaggedSignature {
+        self.sign(value.hash(&HASH_TYPE).as_ref())
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("verifying_key", &self.verifying_key())
+            .finish()
+    }
+}
+
+impl From<KeyPair> for SigningKey {
+    fn from(key: KeyPair) -> SigningKey {
+        SigningKey(SigningKeyInner::Ed25519(key))
+    }
+}
+
+impl VerifyingKey {
+    /// False on a bad signature, including one made with a different algorithm.
+    pub fn verify(&self, message: &[u8], signature: &TaggedSignature) -> bool {
+        if signature.algorithm != self.algorithm {
+            return false;
+        }
+        signature::UnparsedPublicKey::new(self.algorithm.verification(), &self.bytes)
+            .verify(message, &signature.bytes)
+            .is_ok()
+    }
+
+    pub fn verify_hashable<T: Hashable>(&self, value: &T, signature: &TaggedSignature) -> bool {
+        self.verify(value.hash(&HASH_TYPE).as_ref(), signature)
+    }
+}
+
+impl From<PublicKey> for VerifyingKey {
+    fn from(key: PublicKey) -> VerifyingKey {
+        VerifyingKey {
+            algorithm: Algorithm::Ed25519,
+            bytes: key.0.to_vec(),
+        }
+    }
+}
+
+impl From<Signature> for TaggedSignature {
+    fn from(signature: Signature) -> TaggedSignature {
+        TaggedSignature {
+            algorithm: Algorithm::Ed25519,
+            bytes: signature.0.to_vec(),
+        }
+    }
+}
+
+// hex strings for human readable formats (JSON), raw bytes otherwise (bincode)
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&hex::encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        let hex_str = String::deserialize(deserializer)?;
+        hex::decode(hex_str).map_err(serde::de::Error::custom)
+    } else {
+        serde_bytes_vec(deserializer)
+    }
+}
+
+fn serde_bytes_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct BytesVisitor;
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "a byte array")
+        }
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+        fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut out = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                out.push(byte);
+            }
+            Ok(out)
+        }
+    }
+    deserializer.deserialize_bytes(BytesVisitor)
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PublicKey, D::Error> {
+        let bytes = deserialize_bytes(deserializer)?;
+        PublicKey::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Signature, D::Error> {
+        let bytes = deserialize_bytes(deserializer)?;
+        Signature::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::H256;
+
+    #[test]
+    fn sign_and_verify() {
+        let key = KeyPair::generate();
+        let public = key.public_key();
+        let signature = key.sign(b"checkpoint");
+        assert!(public.verify(b"checkpoint", &signature));
+        assert!(!public.verify(b"checkpoinT", &signature));
+        assert!(!KeyPair::generate()
+            .public_key()
+            .verify(b"checkpoint", &signature));
+    }
+
+    #[test]
+    fn rfc8032_test_vector_1() {
+        // RFC 8032 section 7.1, TEST 1 (empty message)
+        let seed = hex!("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+        let key = KeyPair::from_seed(&seed).unwrap();
+        assert_eq!(
+            key.public_key().to_hex(),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        let signature = key.sign(b"");
+        assert_eq!(
+            signature.to_hex(),
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+        assert!(key.public_key().verify(b"", &signature));
+    }
+
+    #[test]
+    fn pkcs8_round_trip_matches_ring() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key = KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        assert_eq!(*key.to_pkcs8(), pkcs8.as_ref().to_vec());
+        assert_eq!(
+            key.public_key().as_bytes()[..],
+            *Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+                .unwrap()
+                .public_key()
+                .as_ref()
+        );
+        assert_eq!(
+            KeyPair::from_pkcs8(b"not a key").unwrap_err(),
+            KeyError::InvalidKey
+        );
+    }
+
+    #[test]
+    fn bytes_and_hex_round_trip() {
+        let key = KeyPair::generate();
+        let public = key.public_key();
+        let signature = key.sign(b"x");
+        assert_eq!(PublicKey::from_bytes(public.as_bytes()).unwrap(), public);
+        assert_eq!(PublicKey::from_hex(&public.to_hex()).unwrap(), public);
+        assert_eq!(
+            Signature::from_bytes(signature.as_bytes()).unwrap(),
+            signature
+        );
+        assert_eq!(Signature::from_hex(&signature.to_hex()).unwrap(), signature);
+        assert_eq!(
+            PublicKey::from_bytes(&[0u8; 31]),
+            Err(KeyError::InvalidLength)
+        );
+        assert_eq!(Signature::from_hex("zz"), Err(KeyError::InvalidHex));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let key = KeyPair::generate();
+        let public = key.public_key();
+        let signature = key.sign(b"x");
+        let public_back: PublicKey =
+            bincode::deserialize(&bincode::serialize(&public).unwrap()).unwrap();
+        let signature_back: Signature =
+            bincode::deserialize(&bincode::serialize(&signature).unwrap()).unwrap();
+        assert_eq!(public_back, public);
+        assert_eq!(signature_back, signature);
+    }
+
+    #[test]
+    fn sign_hashable_values() {
+        let key = KeyPair::generate();
+        let value: H256 =
+            (hex!("0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d")).into();
+        let signature = key.sign_hashable(&value);
+        assert!(key.public_key().verify_hashable(&value, &signature));
+        assert!(!key
+            .public_key()
+            .verify_hashable(&H256::default(), &signature));
+    }
+
+    #[test]
+    fn mnemonic_keys_are_deterministic() {
+        let mnemonic = Mnemonic::parse(
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        )
+        .unwrap();
+        let path: DerivationPath = "m/44'/0'/0'".parse().unwrap();
+        let key = KeyPair::from_mnemonic(&mnemonic, "", &path);
+        assert_eq!(
+            KeyPair::from_mnemonic(&mnemonic, "", &path).public_key(),
+            key.public_key()
+        );
+        assert_ne!(
+            KeyPair::from_mnemonic(&mnemonic, "TREZOR", &path).public_key(),
+            key.public_key()
+        );
+        let seed = mnemonic.to_seed("");
+        let expected = ExtendedKey::master(&seed[..])
+            .unwrap()
+            .derive_path(&path)
+            .unwrap();
+        assert_eq!(key.to_pkcs8(), expected.key_pair().to_pkcs8());
+    }
+
+    #[test]
+    fn mixed_algorithms() {
+        let keys: Vec<SigningKey> = vec![
+            SigningKey::generate(Algorithm::Ed25519),
+            SigningKey::generate(Algorithm::EcdsaP256Sha256),
+            SigningKey::generate(Algorithm::EcdsaP384Sha384),
+        ];
+        let signatures: Vec<TaggedSignature> = keys.iter().map(|k| k.sign(b"partner")).collect();
+        assert_eq!(signatures[1].bytes.len(), 64);
+        assert_eq!(signatures[2].bytes.len(), 96);
+        for (i, key) in keys.iter().enumerate() {
+            let verifying = key.verifying_key();
+            assert_eq!(verifying.algorithm, key.algorithm());
+            assert!(verifying.verify(b"partner", &signatures[i]));
+            assert!(!verifying.verify(b"partneR", &signatures[i]));
+            for (j, signature) in signatures.iter().enumerate() {
+                if i != j {
+                    assert!(!verifying.verify(b"partner", signature));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn signing_key_pkcs8_and_serde() {
+        for &algorithm in [
+            Algorithm::Ed25519,
+            Algorithm::EcdsaP256Sha256,
+            Algorithm::EcdsaP384Sha384,
+        ]
+        .iter()
+        {
+            let key = SigningKey::generate(algorithm);
+            let reloaded = SigningKey::from_pkcs8(algorithm, &key.to_pkcs8()).unwrap();
+            assert_eq!(reloaded.verifying_key(), key.verifying_key());
+
+            let signature = key.sign_hashable(&H256::default());
+            let verifying: VerifyingKey =
+                bincode::deserialize(&bincode::serialize(&key.verifying_key()).unwrap()).unwrap();
+            let signature: TaggedSignature =
+                bincode::deserialize(&bincode::serialize(&signature).unwrap()).unwrap();
+            assert!(verifying.verify_hashable(&H256::default(), &signature));
+        }
+        let p256 = SigningKey::generate(Algorithm::EcdsaP256Sha256);
+        assert!(SigningKey::from_pkcs8(Algorithm::EcdsaP384Sha384, &p256.to_pkcs8()).is_err());
+        assert!(SigningKey::from_pkcs8(Algorithm::Ed25519, &p256.to_pkcs8()).is_err());
+    }
+
+    #[test]
+    fn ecdsa_keys_use_the_system_rng() {
+        use crate::rng::TestRng;
+        for &algorithm in [Algorithm::EcdsaP256Sha256, Algorithm::EcdsaP384Sha384].iter() {
+            assert_eq!(
+                SigningKey::generate_with_rng(algorithm, &TestRng::new(b"keys")).unwrap_err(),
+                KeyError::UnsupportedRng
+            );
+            // an ECDSA key can't be loaded under another algorithm's tag
+            let key = SigningKey::generate(algorithm);
+            assert!(SigningKey::from_pkcs8(Algorithm::Ed25519, &key.to_pkcs8()).is_err());
+            assert_ne!(
+                SigningKey::generate(algorithm).verifying_key(),
+                key.verifying_key()
+            );
+        }
+    }
+
+    #[test]
+    fn generation_from_injected_rng() {
+        use crate::rng::tests::FailingRng;
+        use crate::rng::TestRng;
+
+        let algorithm = Algorithm::Ed25519;
+        let a = SigningKey::generate_with_rng(algorithm, &TestRng::new(b"keys")).unwrap();
+        let b = SigningKey::generate_with_rng(algorithm, &TestRng::new(b"keys")).unwrap();
+        assert_eq!(a.to_pkcs8(), b.to_pkcs8());
+        let c = SigningKey::generate_with_rng(algorithm, &TestRng::new(b"other")).unwrap();
+        assert_ne!(a.verifying_key(), c.verifying_key());
+        assert_eq!(
+            SigningKey::generate_with_rng(algorithm, &FailingRng).unwrap_err(),
+            KeyError::Random(RandomError)
+        );
+
+        let rng = TestRng::new(b"keys");
+        let key = KeyPair::generate_with_rng(&rng).unwrap();
+        assert_eq!(
+            random_with_rng(&TestRng::new(b"keys"))
+                .unwrap()
+                .public_key()
+                .as_ref(),
+            key.public_key().as_bytes()
+        );
+        // the stream moves on between keys
+        assert_ne!(
+            KeyPair::generate_with_rng(&rng).unwrap().public_key(),
+            key.public_key()
+        );
+    }
+
+    #[test]
+    fn debug_never_prints_secret() {
+        let key = KeyPair::from_seed(&[0x42u8; 32]).unwrap();
+        let printed = format!("{:?}", key);
+        assert!(!printed.contains(&hex::encode([0x42u8; 32])));
+        assert!(printed.contains(&key.public_key().to_hex()));
+    }
 }
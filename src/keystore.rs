@@ -0,0 +1,399 @@
+//! Password-encrypted keystore files for Ed25519 keys.
+//!
+//! The PKCS#8 document of a `KeyPair` is sealed with AES-256-GCM under a key
+//! derived from the password with PBKDF2-HMAC-SHA256. Everything needed to
+//! re-derive the key is recorded next to the ciphertext:
+//!
+//! ```notrust
+//! {
+//!   "version": 1,
+//!   "public_key": "<hex>",
+//!   "crypto": {
+//!     "cipher": "aes-256-gcm",
+//!     "nonce": "<hex, 12 bytes>",
+//!     "ciphertext": "<hex, pkcs8 || tag>",
+//!     "kdf": "pbkdf2-hmac-sha256",
+//!     "kdf_params": { "iterations": 100000, "salt": "<hex, 16 bytes>" }
+//!   }
+//! }
+//! ```
+//!
+//! The header fields are fed to GCM as associated data, so editing any of them
+//! (or the ciphertext) makes decryption fail.
+use crate::key_pair::{KeyError, KeyPair, PublicKey};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{Zeroize, Zeroizing};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+pub const VERSION: u32 = 1;
+pub const CIPHER: &str = "aes-256-gcm";
+pub const KDF: &str = "pbkdf2-hmac-sha256";
+/// Default PBKDF2 iteration count for new keystores.
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+/// Largest PBKDF2 iteration count accepted. The count comes from the file, so it
+/// bounds the work a crafted keystore can demand before the tag is checked.
+pub const MAX_ITERATIONS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    /// wrong password, or the file was modified
+    DecryptionFailed,
+    UnsupportedVersion(u32),
+    UnsupportedCipher(String),
+    UnsupportedKdf(String),
+    /// the file is not a well formed keystore
+    InvalidFormat(String),
+    Key(KeyError),
+    Random(RandomError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeystoreError::DecryptionFailed => {
+                write!(f, "decryption failed: wrong password or tampered keystore")
+            }
+            KeystoreError::UnsupportedVersion(v) => write!(f, "unsupported keystore version {}", v),
+            KeystoreError::UnsupportedCipher(c) => write!(f, "unsupported cipher {}", c),
+            KeystoreError::UnsupportedKdf(k) => write!(f, "unsupported kdf {}", k),
+            KeystoreError::InvalidFormat(e) => write!(f, "invalid keystore: {}", e),
+            KeystoreError::Key(e) => write!(f, "invalid key: {}", e),
+            KeystoreError::Random(e) => write!(f, "{}", e),
+            KeystoreError::Io(e) => write!(f, "keystore io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<RandomError> for KeystoreError {
+    fn from(e: RandomError) -> Self {
+        KeystoreError::Random(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub iterations: u32,
+    /// hex encoded
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CryptoSection {
+    pub cipher: String,
+    /// hex encoded
+    pub nonce: String,
+    /// hex encoded, GCM tag appended
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+}
+
+/// An encrypted key as stored on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub public_key: PublicKey,
+    pub crypto: CryptoSection,
+}
+
+impl Keystore {
+    /// Encrypt `key` under `password` with the default iteration count.
+    pub fn encrypt(key: &KeyPair, password: &[u8]) -> Result<Keystore, KeystoreError> {
+        Keystore::encrypt_with_iterations(key, password, DEFAULT_ITERATIONS)
+    }
+
+    pub fn encrypt_with_iterations(
+        key: &KeyPair,
+        password: &[u8],
+        iterations: u32,
+    ) -> Result<Keystore, KeystoreError> {
+        Keystore::encrypt_with_rng(key, password, iterations, &SystemRandom::new())
+    }
+
+    /// `encrypt_with_iterations` with the salt and nonce drawn from `rng`.
+    pub fn encrypt_with_rng(
+        key: &KeyPair,
+        password: &[u8],
+        iterations: u32,
+        rng: &dyn SecureRandom,
+    ) -> Result<Keystore, KeystoreError> {
+        let iterations = check_iterations(iterations)?;
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut salt)?;
+        rng.fill(&mut nonce)?;
+
+        let mut keystore = Keystore {
+            version: VERSION,
+            public_key: key.public_key(),
+            crypto: CryptoSection {
+                cipher: CIPHER.to_string(),
+                nonce: hex::encode(nonce),
+                ciphertext: String::new(),
+                kdf: KDF.to_string(),
+                kdf_params: KdfParams {
+                    iterations: iterations.get(),
+                    salt: hex::encode(salt),
+                },
+            },
+        };
+        let sealing_key = derive_key(password, &salt, iterations);
+        let mut in_out = key.to_pkcs8();
+        sealing_key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(keystore.aad()),
+                &mut *in_out,
+            )
+            .unwrap();
+        keystore.crypto.ciphertext = hex::encode(&in_out[..]);
+        Ok(keystore)
+    }
+
+    /// Decrypt the stored key. Fails with `DecryptionFailed` on a wrong password or any tampering.
+    pub fn decrypt(&self, password: &[u8]) -> Result<KeyPair, KeystoreError> {
+        if self.version != VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+        if self.crypto.cipher != CIPHER {
+            return Err(KeystoreError::UnsupportedCipher(self.crypto.cipher.clone()));
+        }
+        if self.crypto.kdf != KDF {
+            return Err(KeystoreError::UnsupportedKdf(self.crypto.kdf.clone()));
+        }
+        let iterations = check_iterations(self.crypto.kdf_params.iterations)?;
+        let salt = decode_hex("salt", &self.crypto.kdf_params.salt)?;
+        let nonce = decode_hex("nonce", &self.crypto.nonce)?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce)
+            .map_err(|_| KeystoreError::InvalidFormat("bad nonce length".into()))?;
+        let mut in_out = Zeroizing::new(decode_hex("ciphertext", &self.crypto.ciphertext)?);
+
+        let opening_key = derive_key(password, &salt, iterations);
+        let pkcs8 = opening_key
+            .open_in_place(nonce, Aad::from(self.aad()), &mut in_out)
+            .map_err(|_| KeystoreError::DecryptionFailed)?;
+        let key = KeyPair::from_pkcs8(pkcs8).map_err(KeystoreError::Key)?;
+        // already covered by the aad, checked again so a bad writer can't produce a mismatched file
+        if key.public_key() != self.public_key {
+            return Err(KeystoreError::DecryptionFailed);
+        }
+        Ok(key)
+    }
+
+    /// Re-encrypt under a new password, with a fresh salt and nonce.
+    pub fn change_password(
+        &self,
+        old_password: &[u8],
+        new_password: &[u8],
+    ) -> Result<Keystore, KeystoreError> {
+        self.change_password_with_rng(old_password, new_password, &SystemRandom::new())
+    }
+
+    /// `change_password` with the new salt and nonce drawn from `rng`.
+    pub fn change_password_with_rng(
+        &self,
+        old_password: &[u8],
+        new_password: &[u8],
+        rng: &dyn SecureRandom,
+    ) -> Result<Keystore, KeystoreError> {
+        let key = self.decrypt(old_password)?;
+        Keystore::encrypt_with_rng(&key, new_password, self.crypto.kdf_params.iterations, rng)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Keystore, KeystoreError> {
+        serde_json::from_str(json).map_err(|e| KeystoreError::InvalidFormat(e.to_string()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keystore, KeystoreError> {
+        let json = std::fs::read_to_string(path).map_err(KeystoreError::Io)?;
+        Keystore::from_json(&json)
+    }
+
+    /// Write the keystore to `path`, replacing any existing file atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), KeystoreError> {
+        let path = path.as_ref();
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut file = std::fs::File::create(&tmp_path).map_err(KeystoreError::Io)?;
+            file.write_all(self.to_json().as_bytes())
+                .map_err(KeystoreError::Io)?;
+            file.sync_all().map_err(KeystoreError::Io)?;
+        }
+        std::fs::rename(&tmp_path, path).map_err(KeystoreError::Io)
+    }
+
+    // everything except the ciphertext itself is authenticated
+    fn aad(&self) -> Vec<u8> {
+        format!(
+            "crypto_lib/keystore/v{}|{}|{}|{}|{}|{}|{}",
+            self.version,
+            self.public_key.to_hex(),
+            self.crypto.cipher,
+            self.crypto.nonce,
+            self.crypto.kdf,
+            self.crypto.kdf_params.iterations,
+            self.crypto.kdf_params.salt
+        )
+        .into_bytes()
+    }
+}
+
+fn check_iterations(iterations: u32) -> Result<NonZeroU32, KeystoreError> {
+    if iterations > MAX_ITERATIONS {
+        return Err(KeystoreError::InvalidFormat(format!(
+            "iterations must be at most {}",
+            MAX_ITERATIONS
+        )));
+    }
+    NonZeroU32::new(iterations)
+        .ok_or_else(|| KeystoreError::InvalidFormat("iterations must be non-zero".into()))
+}
+
+fn derive_key(password: &[u8], salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key_bytes = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        password,
+        &mut key_bytes,
+    );
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap());
+    key_bytes.zeroize();
+    key
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(value).map_err(|_| KeystoreError::InvalidFormat(format!("{} is not hex", field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITERATIONS: u32 = 1_000;
+
+    fn sample() -> (KeyPair, Keystore) {
+        let key = KeyPair::generate();
+        let keystore = Keystore::encrypt_with_iterations(&key, b"hunter2", ITERATIONS).unwrap();
+        (key, keystore)
+    }
+
+    #[test]
+    fn json_round_trip_decrypts() {
+        let (key, keystore) = sample();
+        let parsed = Keystore::from_json(&keystore.to_json()).unwrap();
+        assert_eq!(parsed, keystore);
+        let decrypted = parsed.decrypt(b"hunter2").unwrap();
+        assert_eq!(decrypted.public_key(), key.public_key());
+        assert_eq!(decrypted.to_pkcs8(), key.to_pkcs8());
+    }
+
+    #[test]
+    fn wrong_password_fails() {
+        let (_, keystore) = sample();
+        match keystore.decrypt(b"hunter3") {
+            Err(KeystoreError::DecryptionFailed) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tampering_detected() {
+        let (_, keystore) = sample();
+
+        let mut flipped = keystore.clone();
+        let mut ciphertext = hex::decode(&flipped.crypto.ciphertext).unwrap();
+        ciphertext[3] ^= 1;
+        flipped.crypto.ciphertext = hex::encode(ciphertext);
+        assert!(flipped.decrypt(b"hunter2").is_err());
+
+        let mut swapped = keystore.clone();
+        swapped.public_key = KeyPair::generate().public_key();
+        assert!(swapped.decrypt(b"hunter2").is_err());
+
+        let mut weakened = keystore.clone();
+        weakened.crypto.kdf_params.iterations = 1;
+        assert!(weakened.decrypt(b"hunter2").is_err());
+
+        let mut future = keystore;
+        future.version = 2;
+        match future.decrypt(b"hunter2") {
+            Err(KeystoreError::UnsupportedVersion(2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_iterations_rejected() {
+        let (key, keystore) = sample();
+        // rejected before any PBKDF2 work is done
+        let mut expensive = keystore;
+        expensive.crypto.kdf_params.iterations = u32::MAX;
+        match expensive.decrypt(b"hunter2") {
+            Err(KeystoreError::InvalidFormat(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            Keystore::encrypt_with_iterations(&key, b"hunter2", MAX_ITERATIONS + 1),
+            Err(KeystoreError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn change_password() {
+        let (key, keystore) = sample();
+        let changed = keystore
+            .change_password(b"hunter2", b"correct horse")
+            .unwrap();
+        assert_ne!(
+            changed.crypto.kdf_params.salt,
+            keystore.crypto.kdf_params.salt
+        );
+        assert_ne!(changed.crypto.nonce, keystore.crypto.nonce);
+        assert!(changed.decrypt(b"hunter2").is_err());
+        assert_eq!(
+            changed.decrypt(b"correct horse").unwrap().public_key(),
+            key.public_key()
+        );
+        assert!(keystore.change_password(b"wrong", b"x").is_err());
+
+        let rng = crate::rng::tests::FixedRng(vec![9]);
+        let fixed = keystore
+            .change_password_with_rng(b"hunter2", b"x", &rng)
+            .unwrap();
+        assert_eq!(fixed.crypto.kdf_params.salt, "09".repeat(SALT_LEN));
+        assert!(matches!(
+            keystore.change_password_with_rng(b"hunter2", b"x", &crate::rng::tests::FailingRng),
+            Err(KeystoreError::Random(_))
+        ));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (key, keystore) = sample();
+        let path = std::env::temp_dir().join(format!("keystore_{}.json", std::process::id()));
+        keystore.save(&path).unwrap();
+        let loaded = Keystore::load(&path).unwrap();
+        assert_eq!(
+            loaded.decrypt(b"hunter2").unwrap().public_key(),
+            key.public_key()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -6,12 +6,35 @@ extern crate hex_literal;
 #[macro_use]
 extern crate time_test;
 
+pub mod aead;
+pub mod argon2;
+pub mod batch_signature;
+pub mod bip39;
 pub mod bit_vec;
 pub mod bloom_filter;
+pub mod certificate;
+pub mod commitment;
 pub mod hash;
+pub mod hash_chain;
+pub mod hpke;
+pub mod jws;
+pub mod kademlia;
 pub mod key_pair;
+pub mod keystore;
+pub mod lms;
 pub mod merkle_mountain_range;
 pub mod merkle_tree;
+pub mod minisign;
+pub mod multisig;
+pub mod openssh;
+pub mod rng;
+pub mod secret;
+pub mod shamir;
+pub mod slip10;
+pub mod sortition;
+pub mod timestamp;
+pub mod transparency_log;
+pub mod typed_data;
 
 // static HASH_TYPE: hash::HasherType = hash::HasherType::Blake3Hash;
 
@@ -0,0 +1,399 @@
+//! ``` notrust
+//! Leighton-Micali one-time signatures (LM-OTS), RFC 8554 section 4.
+//!
+//! A Winternitz scheme: the message digest is cut into w-bit digits a[i], and
+//! for each digit the signer reveals the private value hashed a[i] times.
+//! The verifier hashes the rest of the way (2^w - 1 - a[i] times) and must land
+//! on the public key. A checksum over the digits stops anyone from pushing a
+//! digit forward without pushing another one back.
+//!
+//!  x[i] --H--> ... --H--> y[i] (signature) --H--> ... --H--> public chain end
+//!       a[i] times                        2^w-1-a[i] times
+//! ```
+
+use crate::hash::{Hasher, HasherType};
+use crate::secret::Zeroize;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// Only the n = 32 (SHA-256) parameter sets are supported.
+pub const N: usize = 32;
+
+// domain separation constants from RFC 8554 section 7.1
+pub(crate) const D_PBLC: u16 = 0x8080;
+pub(crate) const D_MESG: u16 = 0x8181;
+pub(crate) const D_LEAF: u16 = 0x8282;
+pub(crate) const D_INTR: u16 = 0x8383;
+
+// used to derive the pseudorandom randomizer C (not part of the RFC wire format)
+const D_RAND: u16 = 0xfffd;
+
+/// LM-OTS parameter sets, RFC 8554 table 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LmotsType {
+    Sha256N32W1,
+    Sha256N32W2,
+    Sha256N32W4,
+    Sha256N32W8,
+}
+
+impl LmotsType {
+    /// typecode on the wire
+    pub fn code(&self) -> u32 {
+        match self {
+            LmotsType::Sha256N32W1 => 1,
+            LmotsType::Sha256N32W2 => 2,
+            LmotsType::Sha256N32W4 => 3,
+            LmotsType::Sha256N32W8 => 4,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<LmotsType> {
+        match code {
+            1 => Some(LmotsType::Sha256N32W1),
+            2 => Some(LmotsType::Sha256N32W2),
+            3 => Some(LmotsType::Sha256N32W4),
+            4 => Some(LmotsType::Sha256N32W8),
+            _ => None,
+        }
+    }
+
+    /// width in bits of each Winternitz digit
+    pub fn w(&self) -> usize {
+        match self {
+            LmotsType::Sha256N32W1 => 1,
+            LmotsType::Sha256N32W2 => 2,
+            LmotsType::Sha256N32W4 => 4,
+            LmotsType::Sha256N32W8 => 8,
+        }
+    }
+
+    /// number of hash chains (digits of the digest plus digits of the checksum)
+    pub fn p(&self) -> usize {
+        match self {
+            LmotsType::Sha256N32W1 => 265,
+            LmotsType::Sha256N32W2 => 133,
+            LmotsType::Sha256N32W4 => 67,
+            LmotsType::Sha256N32W8 => 34,
+        }
+    }
+
+    /// left shift applied to the checksum
+    pub fn ls(&self) -> usize {
+        match self {
+            LmotsType::Sha256N32W1 => 7,
+            LmotsType::Sha256N32W2 => 6,
+            LmotsType::Sha256N32W4 => 4,
+            LmotsType::Sha256N32W8 => 0,
+        }
+    }
+
+    /// length of an encoded LM-OTS signature: type || C || y[0..p]
+    pub fn signature_len(&self) -> usize {
+        4 + N + N * self.p()
+    }
+}
+
+/// A decoded LM-OTS signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LmotsSignature {
+    pub ots_type: LmotsType,
+    pub c: [u8; N],
+    pub y: Vec<[u8; N]>,
+}
+
+impl LmotsSignature {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ots_type.signature_len());
+        out.extend_from_slice(&self.ots_type.code().to_be_bytes());
+        out.extend_from_slice(&self.c);
+        for y in self.y.iter() {
+            out.extend_from_slice(y);
+        }
+        out
+    }
+
+    /// Parse from the front of `bytes`, returning the signature and the number of bytes used.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(LmotsSignature, usize)> {
+        if bytes.len() < 4 {
+            return None;
+        }
+        let ots_type = LmotsType::from_code(u32::from_be_bytes(bytes[0..4].try_into().unwrap()))?;
+        let len = ots_type.signature_len();
+        if bytes.len() < len {
+            return None;
+        }
+        let mut c = [0u8; N];
+        c.copy_from_slice(&bytes[4..4 + N]);
+        let y = bytes[4 + N..len]
+            .chunks(N)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Some((LmotsSignature { ots_type, c, y }, len))
+    }
+}
+
+/// SHA-256 through the crate hasher; the RFC fixes the hash function,
+/// so this never follows the crate wide `HASH_TYPE`.
+pub(crate) fn sha256(parts: &[&[u8]]) -> [u8; N] {
+    let mut ctx = Hasher::new(&HasherType::RingSHA256);
+    for part in parts.iter() {
+        ctx.update(part);
+    }
+    ctx.finish().into()
+}
+
+/// coef(S, i, w): the i-th w-bit digit of S
+fn coef(s: &[u8], i: usize, w: usize) -> usize {
+    let digits_per_byte = 8 / w;
+    let byte = s[i * w / 8] as usize;
+    let shift = 8 - (w * (i % digits_per_byte) + w);
+    ((1 << w) - 1) & (byte >> shift)
+}
+
+/// Q || Cksm(Q), the string whose digits select how far each chain is walked
+fn digits_with_checksum(q_digest: &[u8; N], ots_type: LmotsType) -> Vec<u8> {
+    let w = ots_type.w();
+    let u = 8 * N / w;
+    let max = (1 << w) - 1;
+    let mut sum: usize = 0;
+    for i in 0..u {
+        sum += max - coef(q_digest, i, w);
+    }
+    let checksum = ((sum << ots_type.ls()) & 0xffff) as u16;
+    let mut out = q_digest.to_vec();
+    out.extend_from_slice(&checksum.to_be_bytes());
+    out
+}
+
+fn message_digest(identifier: &[u8; 16], q: u32, c: &[u8; N], message: &[u8]) -> [u8; N] {
+    sha256(&[
+        identifier,
+        &q.to_be_bytes(),
+        &D_MESG.to_be_bytes(),
+        c,
+        message,
+    ])
+}
+
+/// walk chain `i` from step `from` (inclusive) to step `to` (exclusive)
+fn chain(
+    identifier: &[u8; 16],
+    q: u32,
+    i: u16,
+    start: &[u8; N],
+    from: usize,
+    to: usize,
+) -> [u8; N] {
+    let mut tmp = *start;
+    for j in from..to {
+        tmp = sha256(&[
+            identifier,
+            &q.to_be_bytes(),
+            &i.to_be_bytes(),
+            &[j as u8],
+            &tmp,
+        ]);
+    }
+    tmp
+}
+
+/// One LM-OTS private key, derived from the tree seed as in RFC 8554 appendix A.
+pub struct LmotsPrivateKey {
+    ots_type: LmotsType,
+    identifier: [u8; 16],
+    q: u32,
+    x: Vec<[u8; N]>,
+    // kept around only to derive C deterministically
+    seed: [u8; N],
+}
+
+impl LmotsPrivateKey {
+    /// x_q[i] = H(I || u32str(q) || u16str(i) || u8str(0xff) || SEED)
+    pub fn derive(ots_type: LmotsType, identifier: &[u8; 16], q: u32, seed: &[u8; N]) -> Self {
+        let x = (0..ots_type.p())
+            .map(|i| {
+                sha256(&[
+                    identifier,
+                    &q.to_be_bytes(),
+                    &(i as u16).to_be_bytes(),
+                    &[0xff],
+                    seed,
+                ])
+            })
+            .collect();
+        LmotsPrivateKey {
+            ots_type,
+            identifier: *identifier,
+            q,
+            x,
+            seed: *seed,
+        }
+    }
+
+    /// K = H(I || u32str(q) || u16str(D_PBLC) || y[0] || ... || y[p-1])
+    pub fn public_key(&self) -> [u8; N] {
+        let max = (1 << self.ots_type.w()) - 1;
+        let mut ctx = Hasher::new(&HasherType::RingSHA256);
+        ctx.update(&self.identifier);
+        ctx.update(&self.q.to_be_bytes());
+        ctx.update(&D_PBLC.to_be_bytes());
+        for (i, x) in self.x.iter().enumerate() {
+            ctx.update(&chain(&self.identifier, self.q, i as u16, x, 0, max));
+        }
+        ctx.finish().into()
+    }
+
+    /// Sign `message`. The key must never be used again afterwards;
+    /// that is enforced one level up by `LmsPrivateKey`.
+    pub fn sign(&self, message: &[u8]) -> LmotsSignature {
+        let c = sha256(&[
+            &self.identifier,
+            &self.q.to_be_bytes(),
+            &D_RAND.to_be_bytes(),
+            &[0xff],
+            &self.seed,
+        ]);
+        self.sign_with_randomizer(message, c)
+    }
+
+    /// `sign` with a given randomizer C, e.g. to reproduce a published signature.
+    pub(crate) fn sign_with_randomizer(&self, message: &[u8], c: [u8; N]) -> LmotsSignature {
+        let q_digest = message_digest(&self.identifier, self.q, &c, message);
+        let digits = digits_with_checksum(&q_digest, self.ots_type);
+        let y = self
+            .x
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let a = coef(&digits, i, self.ots_type.w());
+                chain(&self.identifier, self.q, i as u16, x, 0, a)
+            })
+            .collect();
+        LmotsSignature {
+            ots_type: self.ots_type,
+            c,
+            y,
+        }
+    }
+}
+
+impl Drop for LmotsPrivateKey {
+    fn drop(&mut self) {
+        for x in self.x.iter_mut() {
+            x.zeroize();
+        }
+        self.seed.zeroize();
+    }
+}
+
+/// Algorithm 4b: compute the public key candidate Kc from a signature.
+/// The signature is valid iff Kc equals the real one-time public key.
+pub fn candidate_public_key(
+    signature: &LmotsSignature,
+    identifier: &[u8; 16],
+    q: u32,
+    message: &[u8],
+) -> Option<[u8; N]> {
+    let ots_type = signature.ots_type;
+    if signature.y.len() != ots_type.p() {
+        return None;
+    }
+    let max = (1 << ots_type.w()) - 1;
+    let q_digest = message_digest(identifier, q, &signature.c, message);
+    let digits = digits_with_checksum(&q_digest, ots_type);
+    let mut ctx = Hasher::new(&HasherType::RingSHA256);
+    ctx.update(identifier);
+    ctx.update(&q.to_be_bytes());
+    ctx.update(&D_PBLC.to_be_bytes());
+    for (i, y) in signature.y.iter().enumerate() {
+        let a = coef(&digits, i, ots_type.w());
+        ctx.update(&chain(identifier, q, i as u16, y, a, max));
+    }
+    Some(ctx.finish().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_TYPES: [LmotsType; 4] = [
+        LmotsType::Sha256N32W1,
+        LmotsType::Sha256N32W2,
+        LmotsType::Sha256N32W4,
+        LmotsType::Sha256N32W8,
+    ];
+
+    #[test]
+    fn coef_matches_rfc_example() {
+        // RFC 8554 section 3.1.3: S = 0x1234
+        let s = [0x12u8, 0x34];
+        assert_eq!(coef(&s, 7, 1), 0);
+        assert_eq!(coef(&s, 0, 4), 1);
+        assert_eq!(coef(&s, 3, 4), 4);
+        assert_eq!(coef(&s, 0, 8), 0x12);
+    }
+
+    #[test]
+    fn parameters_match_rfc_table() {
+        // p = u + v with u = ceil(8n/w), v = ceil((floor(lg((2^w-1) * u)) + 1) / w)
+        for ots_type in ALL_TYPES.iter() {
+            let w = ots_type.w();
+            let u = 8 * N / w;
+            let max_sum = ((1usize << w) - 1) * u;
+            let bits = 64 - (max_sum as u64).leading_zeros() as usize;
+            let v = bits.div_ceil(w);
+            assert_eq!(ots_type.p(), u + v);
+            assert_eq!(ots_type.ls(), 16 - v * w);
+        }
+    }
+
+    #[test]
+    fn sign_and_verify_every_type() {
+        let identifier = [7u8; 16];
+        let seed = [9u8; N];
+        for ots_type in ALL_TYPES.iter() {
+            let key = LmotsPrivateKey::derive(*ots_type, &identifier, 3, &seed);
+            let public = key.public_key();
+            let signature = key.sign(b"firmware image");
+            let candidate = candidate_public_key(&signature, &identifier, 3, b"firmware image");
+            assert_eq!(candidate, Some(public));
+            let forged = candidate_public_key(&signature, &identifier, 3, b"firmware imagf");
+            assert_ne!(forged, Some(public));
+        }
+    }
+
+    #[test]
+    fn rfc8554_test_case_2_one_time_signature() {
+        // appendix F, test case 2: second level tree, leaf q = 4, randomizer C
+        let mut identifier = [0u8; 16];
+        identifier.copy_from_slice(&hex!("215f83b7ccb9acbcd08db97b0d04dc2b"));
+        let seed = hex!("a1c4696e2608035a886100d05cd99945eb3370731884a8235e2fb3d4d71f2547");
+        let c = hex!("0eb1ed54a2460d512388cad533138d240534e97b1e82d33bd927d201dfc24ebb");
+        let message: &[u8] = b"The enumeration in the Constitution, of certain rights, shall \
+            not be construed to deny or disparage others retained by the people.\n";
+
+        let key = LmotsPrivateKey::derive(LmotsType::Sha256N32W8, &identifier, 4, &seed);
+        let signature = key.sign_with_randomizer(message, c);
+        assert_eq!(
+            signature.y[0],
+            hex!("11b3649023696f85150b189e50c00e98850ac343a77b3638319c347d7310269d")
+        );
+        assert_eq!(
+            candidate_public_key(&signature, &identifier, 4, message),
+            Some(key.public_key())
+        );
+    }
+
+    #[test]
+    fn signature_bytes_round_trip() {
+        let key = LmotsPrivateKey::derive(LmotsType::Sha256N32W4, &[1u8; 16], 0, &[2u8; N]);
+        let signature = key.sign(b"hello");
+        let bytes = signature.to_bytes();
+        assert_eq!(bytes.len(), LmotsType::Sha256N32W4.signature_len());
+        let (parsed, used) = LmotsSignature::from_bytes(&bytes).unwrap();
+        assert_eq!(used, bytes.len());
+        assert_eq!(parsed, signature);
+        assert!(LmotsSignature::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
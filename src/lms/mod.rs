@@ -0,0 +1,2 @@
+pub mod lm_ots;
+pub mod tree;
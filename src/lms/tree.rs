@@ -0,0 +1,717 @@
+//! ``` notrust
+//! Leighton-Micali signatures (LMS), RFC 8554 section 5.
+//!
+//! 2^h one-time keys are hashed into a Merkle tree, the root is the long-lived
+//! public key. Signature q is made with one-time key q plus the authentication
+//! path from leaf q up to the root.
+//!
+//! Nodes are numbered like a heap: root is 1, children of r are 2r and 2r+1,
+//! leaf q is 2^h + q.
+//!
+//!               T[1]
+//!             /      \
+//!          T[2]      T[3]
+//!         /   \     /    \
+//!       T[4] T[5] T[6]  T[7]   <- leaves, h = 2
+//! ```
+//!
+//! The scheme is stateful: signing twice with the same leaf leaks enough of the
+//! one-time key to forge. `LmsPrivateKey::sign` therefore persists the advanced
+//! leaf counter through a `KeyStateStore` *before* it releases a signature.
+//!
+//! A private key keeps the whole tree in memory and rebuilds it from SEED on
+//! every load, so signing is limited to heights up to `MAX_SIGNING_HEIGHT`.
+//! Public keys of every height verify.
+
+use super::lm_ots::{self, LmotsPrivateKey, LmotsSignature, LmotsType, D_INTR, D_LEAF, N};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{constant_time_eq, Zeroizing};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryInto;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Tallest tree a private key is built for: 2^15 one-time keys and 2 MiB of
+/// nodes. H20 and H25 would take 2^21 and 2^26 nodes, rebuilt on every load.
+pub const MAX_SIGNING_HEIGHT: usize = 15;
+
+/// LMS parameter sets, RFC 8554 table 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LmsType {
+    Sha256M32H5,
+    Sha256M32H10,
+    Sha256M32H15,
+    Sha256M32H20,
+    Sha256M32H25,
+}
+
+impl LmsType {
+    pub fn code(&self) -> u32 {
+        match self {
+            LmsType::Sha256M32H5 => 5,
+            LmsType::Sha256M32H10 => 6,
+            LmsType::Sha256M32H15 => 7,
+            LmsType::Sha256M32H20 => 8,
+            LmsType::Sha256M32H25 => 9,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<LmsType> {
+        match code {
+            5 => Some(LmsType::Sha256M32H5),
+            6 => Some(LmsType::Sha256M32H10),
+            7 => Some(LmsType::Sha256M32H15),
+            8 => Some(LmsType::Sha256M32H20),
+            9 => Some(LmsType::Sha256M32H25),
+            _ => None,
+        }
+    }
+
+    /// height of the tree
+    pub fn height(&self) -> usize {
+        match self {
+            LmsType::Sha256M32H5 => 5,
+            LmsType::Sha256M32H10 => 10,
+            LmsType::Sha256M32H15 => 15,
+            LmsType::Sha256M32H20 => 20,
+            LmsType::Sha256M32H25 => 25,
+        }
+    }
+
+    /// number of signatures a key can make
+    pub fn leaves(&self) -> u32 {
+        1 << self.height()
+    }
+}
+
+#[derive(Debug)]
+pub enum LmsError {
+    /// every leaf has been used, the key must be retired
+    KeysExhausted,
+    /// the advanced state could not be saved, so no signature was released
+    Persist(std::io::Error),
+    /// stored state is malformed
+    InvalidState,
+    /// the tree is taller than `MAX_SIGNING_HEIGHT`
+    UnsupportedHeight,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for LmsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LmsError::KeysExhausted => write!(f, "all one-time keys have been used"),
+            LmsError::Persist(e) => write!(f, "failed to persist key state: {}", e),
+            LmsError::InvalidState => write!(f, "invalid LMS key state"),
+            LmsError::UnsupportedHeight => write!(
+                f,
+                "LMS private keys are limited to height {}",
+                MAX_SIGNING_HEIGHT
+            ),
+            LmsError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LmsError {}
+
+impl From<RandomError> for LmsError {
+    fn from(e: RandomError) -> Self {
+        LmsError::Random(e)
+    }
+}
+
+/// An LMS public key: u32str(type) || u32str(otstype) || I || T[1]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LmsPublicKey {
+    pub lms_type: LmsType,
+    pub ots_type: LmotsType,
+    pub identifier: [u8; 16],
+    pub root: [u8; N],
+}
+
+/// An LMS signature: u32str(q) || lmots_signature || u32str(type) || path[0..h]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LmsSignature {
+    pub q: u32,
+    pub ots_signature: LmotsSignature,
+    pub lms_type: LmsType,
+    pub path: Vec<[u8; N]>,
+}
+
+/// Everything that has to survive a restart. The tree itself is recomputed on load.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LmsKeyState {
+    pub lms_type: LmsType,
+    pub ots_type: LmotsType,
+    pub identifier: [u8; 16],
+    /// wiped when dropped, including the copies a `KeyStateStore` keeps
+    #[serde(
+        serialize_with = "serialize_seed",
+        deserialize_with = "deserialize_seed"
+    )]
+    pub seed: Zeroizing<[u8; N]>,
+    /// the next unused leaf, every leaf below it is spent
+    pub next_leaf: u32,
+}
+
+impl std::fmt::Debug for LmsKeyState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // never print the seed
+        f.debug_struct("LmsKeyState")
+            .field("lms_type", &self.lms_type)
+            .field("ots_type", &self.ots_type)
+            .field("identifier", &hex::encode(self.identifier))
+            .field("next_leaf", &self.next_leaf)
+            .finish()
+    }
+}
+
+fn serialize_seed<S: Serializer>(
+    seed: &Zeroizing<[u8; N]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    seed.serialize(serializer)
+}
+
+fn deserialize_seed<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Zeroizing<[u8; N]>, D::Error> {
+    <[u8; N]>::deserialize(deserializer).map(Zeroizing::new)
+}
+
+/// Somewhere durable to record the leaf counter.
+pub trait KeyStateStore {
+    /// Must only return `Ok` once `state` is durably stored.
+    fn persist(&mut self, state: &LmsKeyState) -> std::io::Result<()>;
+}
+
+/// Keeps the state in memory only, useful for tests and short lived keys.
+#[derive(Debug, Default)]
+pub struct MemoryKeyStore {
+    pub last: Option<LmsKeyState>,
+}
+
+impl KeyStateStore for MemoryKeyStore {
+    fn persist(&mut self, state: &LmsKeyState) -> std::io::Result<()> {
+        self.last = Some(state.clone());
+        Ok(())
+    }
+}
+
+/// Keeps the state in a file, replaced atomically on each update.
+#[derive(Debug, Clone)]
+pub struct FileKeyStore {
+    path: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileKeyStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn load(&self) -> Result<LmsPrivateKey, LmsError> {
+        let bytes = Zeroizing::new(std::fs::read(&self.path).map_err(LmsError::Persist)?);
+        let state: LmsKeyState =
+            bincode::deserialize(&bytes).map_err(|_| LmsError::InvalidState)?;
+        LmsPrivateKey::from_state(state)
+    }
+}
+
+impl KeyStateStore for FileKeyStore {
+    fn persist(&mut self, state: &LmsKeyState) -> std::io::Result<()> {
+        let bytes = Zeroizing::new(bincode::serialize(state).map_err(std::io::Error::other)?);
+        // write a sibling file, sync it, then rename over the old state
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// An LMS private key together with its precomputed tree.
+pub struct LmsPrivateKey {
+    state: LmsKeyState,
+    // heap ordered nodes, tree[r] = T[r], tree[0] is unused
+    tree: Vec<[u8; N]>,
+}
+
+impl std::fmt::Debug for LmsPrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LmsPrivateKey")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl LmsPrivateKey {
+    /// Generate a fresh key with random I and SEED.
+    /// Builds the whole tree, i.e. 2^h one-time public keys, so large heights take a while.
+    pub fn generate(lms_type: LmsType, ots_type: LmotsType) -> Result<Self, LmsError> {
+        Self::generate_with_rng(lms_type, ots_type, &SystemRandom::new())
+    }
+
+    /// `generate` with I and SEED drawn from `rng`.
+    pub fn generate_with_rng(
+        lms_type: LmsType,
+        ots_type: LmotsType,
+        rng: &dyn SecureRandom,
+    ) -> Result<Self, LmsError> {
+        let mut identifier = [0u8; 16];
+        let mut seed = Zeroizing::new([0u8; N]);
+        rng.fill(&mut identifier)?;
+        rng.fill(&mut *seed)?;
+        let state = LmsKeyState {
+            lms_type,
+            ots_type,
+            identifier,
+            seed,
+            next_leaf: 0,
+        };
+        Self::from_state(state)
+    }
+
+    /// Rebuild a key from saved state.
+    pub fn from_state(state: LmsKeyState) -> Result<Self, LmsError> {
+        if state.lms_type.height() > MAX_SIGNING_HEIGHT {
+            return Err(LmsError::UnsupportedHeight);
+        }
+        if state.next_leaf > state.lms_type.leaves() {
+            return Err(LmsError::InvalidState);
+        }
+        let leaves = state.lms_type.leaves() as usize;
+        let mut tree: Vec<[u8; N]> = vec![[0u8; N]; 2 * leaves];
+        // leaves first: T[r] = H(I || u32str(r) || u16str(D_LEAF) || OTS_PUB_HASH[r - 2^h])
+        for q in 0..leaves {
+            let r = (leaves + q) as u32;
+            let ots_key =
+                LmotsPrivateKey::derive(state.ots_type, &state.identifier, q as u32, &state.seed);
+            tree[leaves + q] = lm_ots::sha256(&[
+                &state.identifier,
+                &r.to_be_bytes(),
+                &D_LEAF.to_be_bytes(),
+                &ots_key.public_key(),
+            ]);
+        }
+        // then every internal node: T[r] = H(I || u32str(r) || u16str(D_INTR) || T[2r] || T[2r+1])
+        for r in (1..leaves).rev() {
+            tree[r] = lm_ots::sha256(&[
+                &state.identifier,
+                &(r as u32).to_be_bytes(),
+                &D_INTR.to_be_bytes(),
+                &tree[2 * r],
+                &tree[2 * r + 1],
+            ]);
+        }
+        Ok(LmsPrivateKey { state, tree })
+    }
+
+    pub fn public_key(&self) -> LmsPublicKey {
+        LmsPublicKey {
+            lms_type: self.state.lms_type,
+            ots_type: self.state.ots_type,
+            identifier: self.state.identifier,
+            root: self.tree[1],
+        }
+    }
+
+    /// The state that would be persisted right now.
+    pub fn state(&self) -> &LmsKeyState {
+        &self.state
+    }
+
+    /// signatures left before the key is exhausted
+    pub fn remaining(&self) -> u32 {
+        self.state.lms_type.leaves() - self.state.next_leaf
+    }
+
+    /// Sign `message` with the next unused leaf.
+    ///
+    /// The leaf counter is advanced and handed to `store` before signing. If
+    /// persisting fails no signature is produced, and the leaf stays burned in
+    /// memory, so a retry can never reuse it either.
+    pub fn sign<S: KeyStateStore>(
+        &mut self,
+        message: &[u8],
+        store: &mut S,
+    ) -> Result<LmsSignature, LmsError> {
+        let q = self.state.next_leaf;
+        if q >= self.state.lms_type.leaves() {
+            return Err(LmsError::KeysExhausted);
+        }
+        self.state.next_leaf = q + 1;
+        store.persist(&self.state).map_err(LmsError::Persist)?;
+
+        let ots_key = LmotsPrivateKey::derive(
+            self.state.ots_type,
+            &self.state.identifier,
+            q,
+            &self.state.seed,
+        );
+        let ots_signature = ots_key.sign(message);
+
+        // authentication path: path[i] = T[(node_num >> i) ^ 1]
+        let height = self.state.lms_type.height();
+        let node_num = self.state.lms_type.leaves() + q;
+        let path = (0..height)
+            .map(|i| self.tree[((node_num >> i) ^ 1) as usize])
+            .collect();
+        Ok(LmsSignature {
+            q,
+            ots_signature,
+            lms_type: self.state.lms_type,
+            path,
+        })
+    }
+}
+
+impl LmsPublicKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 16 + N);
+        out.extend_from_slice(&self.lms_type.code().to_be_bytes());
+        out.extend_from_slice(&self.ots_type.code().to_be_bytes());
+        out.extend_from_slice(&self.identifier);
+        out.extend_from_slice(&self.root);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<LmsPublicKey> {
+        if bytes.len() != 8 + 16 + N {
+            return None;
+        }
+        let lms_type = LmsType::from_code(u32::from_be_bytes(bytes[0..4].try_into().unwrap()))?;
+        let ots_type = LmotsType::from_code(u32::from_be_bytes(bytes[4..8].try_into().unwrap()))?;
+        let mut identifier = [0u8; 16];
+        identifier.copy_from_slice(&bytes[8..24]);
+        let mut root = [0u8; N];
+        root.copy_from_slice(&bytes[24..]);
+        Some(LmsPublicKey {
+            lms_type,
+            ots_type,
+            identifier,
+            root,
+        })
+    }
+
+    /// Algorithm 6: verify `signature` over `message`.
+    pub fn verify(&self, message: &[u8], signature: &LmsSignature) -> bool {
+        if signature.lms_type != self.lms_type
+            || signature.ots_signature.ots_type != self.ots_type
+            || signature.q >= self.lms_type.leaves()
+            || signature.path.len() != self.lms_type.height()
+        {
+            return false;
+        }
+        let candidate = match lm_ots::candidate_public_key(
+            &signature.ots_signature,
+            &self.identifier,
+            signature.q,
+            message,
+        ) {
+            Some(candidate) => candidate,
+            None => return false,
+        };
+
+        let mut node_num = self.lms_type.leaves() + signature.q;
+        let mut tmp = lm_ots::sha256(&[
+            &self.identifier,
+            &node_num.to_be_bytes(),
+            &D_LEAF.to_be_bytes(),
+            &candidate,
+        ]);
+        for sibling in signature.path.iter() {
+            let parent = (node_num / 2).to_be_bytes();
+            // odd nodes are right children, so the sibling goes on the left
+            tmp = if node_num % 2 == 1 {
+                lm_ots::sha256(&[
+                    &self.identifier,
+                    &parent,
+                    &D_INTR.to_be_bytes(),
+                    sibling,
+                    &tmp,
+                ])
+            } else {
+                lm_ots::sha256(&[
+                    &self.identifier,
+                    &parent,
+                    &D_INTR.to_be_bytes(),
+                    &tmp,
+                    sibling,
+                ])
+            };
+            node_num /= 2;
+        }
+        constant_time_eq(&tmp, &self.root)
+    }
+}
+
+impl LmsSignature {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.q.to_be_bytes());
+        out.extend_from_slice(&self.ots_signature.to_bytes());
+        out.extend_from_slice(&self.lms_type.code().to_be_bytes());
+        for node in self.path.iter() {
+            out.extend_from_slice(node);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<LmsSignature> {
+        if bytes.len() < 4 {
+            return None;
+        }
+        let q = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let (ots_signature, used) = LmotsSignature::from_bytes(&bytes[4..])?;
+        let rest = &bytes[4 + used..];
+        if rest.len() < 4 {
+            return None;
+        }
+        let lms_type = LmsType::from_code(u32::from_be_bytes(rest[0..4].try_into().unwrap()))?;
+        let rest = &rest[4..];
+        if rest.len() != N * lms_type.height() {
+            return None;
+        }
+        let path = rest
+            .chunks(N)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Some(LmsSignature {
+            q,
+            ots_signature,
+            lms_type,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingStore;
+
+    impl KeyStateStore for FailingStore {
+        fn persist(&mut self, _state: &LmsKeyState) -> std::io::Result<()> {
+            Err(std::io::Error::other("disk full"))
+        }
+    }
+
+    fn small_key() -> LmsPrivateKey {
+        LmsPrivateKey::generate(LmsType::Sha256M32H5, LmotsType::Sha256N32W8).unwrap()
+    }
+
+    // RFC 8554 appendix F, test case 2
+    const TC2_TOP_PUBLIC: [u8; 56] = hex!("00000006 00000003 d08fabd4a2091ff0a8cb4ed834e74534 32a58885cd9ba0431235466bff9651c6c92124404d45fa53cf161c28f1ad5a8e");
+    const TC2_SECOND_PUBLIC: [u8; 56] = hex!("00000005 00000004 215f83b7ccb9acbcd08db97b0d04dc2b a1cd035833e0e90059603f26e07ad2aad152338e7a5e5984bcd5f7bb4eba40b7");
+    const TC2_MESSAGE: &[u8] = b"The enumeration in the Constitution, of certain rights, shall \
+        not be construed to deny or disparage others retained by the people.\n";
+
+    fn rfc_key(
+        lms_type: LmsType,
+        ots_type: LmotsType,
+        identifier: [u8; 16],
+        seed: [u8; N],
+    ) -> LmsPrivateKey {
+        LmsPrivateKey::from_state(LmsKeyState {
+            lms_type,
+            ots_type,
+            identifier,
+            seed: Zeroizing::new(seed),
+            next_leaf: 0,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rfc8554_test_case_2_key_generation() {
+        let top = rfc_key(
+            LmsType::Sha256M32H10,
+            LmotsType::Sha256N32W4,
+            hex!("d08fabd4a2091ff0a8cb4ed834e74534"),
+            hex!("558b8966c48ae9cb898b423c83443aae014a72f1b1ab5cc85cf1d892903b5439"),
+        );
+        assert_eq!(top.public_key().to_bytes(), TC2_TOP_PUBLIC.to_vec());
+
+        let second = rfc_key(
+            LmsType::Sha256M32H5,
+            LmotsType::Sha256N32W8,
+            hex!("215f83b7ccb9acbcd08db97b0d04dc2b"),
+            hex!("a1c4696e2608035a886100d05cd99945eb3370731884a8235e2fb3d4d71f2547"),
+        );
+        assert_eq!(second.public_key().to_bytes(), TC2_SECOND_PUBLIC.to_vec());
+    }
+
+    #[test]
+    fn rfc8554_test_case_2_signature() {
+        // the final LMS signature of the HSS signature: leaf q = 4 with the published C
+        let key = rfc_key(
+            LmsType::Sha256M32H5,
+            LmotsType::Sha256N32W8,
+            hex!("215f83b7ccb9acbcd08db97b0d04dc2b"),
+            hex!("a1c4696e2608035a886100d05cd99945eb3370731884a8235e2fb3d4d71f2547"),
+        );
+        let ots_key = LmotsPrivateKey::derive(
+            LmotsType::Sha256N32W8,
+            &key.state.identifier,
+            4,
+            &key.state.seed,
+        );
+        let c = hex!("0eb1ed54a2460d512388cad533138d240534e97b1e82d33bd927d201dfc24ebb");
+        let node_num = key.state.lms_type.leaves() + 4;
+        let signature = LmsSignature {
+            q: 4,
+            ots_signature: ots_key.sign_with_randomizer(TC2_MESSAGE, c),
+            lms_type: LmsType::Sha256M32H5,
+            path: (0..5)
+                .map(|i| key.tree[((node_num >> i) ^ 1) as usize])
+                .collect(),
+        };
+
+        let bytes = signature.to_bytes();
+        assert_eq!(
+            bytes[..72].to_vec(),
+            hex!("00000004 00000004 0eb1ed54a2460d512388cad533138d240534e97b1e82d33bd927d201dfc24ebb 11b3649023696f85150b189e50c00e98850ac343a77b3638319c347d7310269d")
+                .to_vec()
+        );
+        // LMS type, then path[0]
+        let path_start = 4 + LmotsType::Sha256N32W8.signature_len();
+        assert_eq!(
+            bytes[path_start..path_start + 4 + N].to_vec(),
+            hex!("00000005 4de1f6965bdabc676c5a4dc7c35f97f82cb0e31c68d04f1dad96314ff09e6b3d")
+                .to_vec()
+        );
+
+        let public = LmsPublicKey::from_bytes(&TC2_SECOND_PUBLIC).unwrap();
+        let parsed = LmsSignature::from_bytes(&bytes).unwrap();
+        assert!(public.verify(TC2_MESSAGE, &parsed));
+        assert!(!public.verify(&TC2_MESSAGE[1..], &parsed));
+    }
+
+    #[test]
+    fn signing_height_is_bounded() {
+        for lms_type in [LmsType::Sha256M32H20, LmsType::Sha256M32H25].iter() {
+            match LmsPrivateKey::generate(*lms_type, LmotsType::Sha256N32W8) {
+                Err(LmsError::UnsupportedHeight) => {}
+                other => panic!("expected unsupported height, got {:?}", other),
+            }
+        }
+        match LmsPrivateKey::generate_with_rng(
+            LmsType::Sha256M32H5,
+            LmotsType::Sha256N32W8,
+            &crate::rng::tests::FailingRng,
+        ) {
+            Err(LmsError::Random(_)) => {}
+            other => panic!("expected a random source error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut key = small_key();
+        let public = key.public_key();
+        let mut store = MemoryKeyStore::default();
+        let signature = key.sign(b"firmware root v1", &mut store).unwrap();
+        assert!(public.verify(b"firmware root v1", &signature));
+        assert!(!public.verify(b"firmware root v2", &signature));
+    }
+
+    #[test]
+    fn every_leaf_verifies_then_exhausted() {
+        let mut key = small_key();
+        let public = key.public_key();
+        let mut store = MemoryKeyStore::default();
+        for q in 0..32u32 {
+            let message = q.to_be_bytes();
+            let signature = key.sign(&message, &mut store).unwrap();
+            assert_eq!(signature.q, q);
+            assert!(public.verify(&message, &signature));
+            assert_eq!(store.last.as_ref().unwrap().next_leaf, q + 1);
+        }
+        assert_eq!(key.remaining(), 0);
+        match key.sign(b"one too many", &mut store) {
+            Err(LmsError::KeysExhausted) => {}
+            other => panic!("expected exhaustion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn failed_persist_burns_leaf() {
+        let mut key = small_key();
+        assert!(key.sign(b"lost", &mut FailingStore).is_err());
+        let mut store = MemoryKeyStore::default();
+        let signature = key.sign(b"kept", &mut store).unwrap();
+        assert_eq!(signature.q, 1);
+    }
+
+    #[test]
+    fn file_store_survives_reload() {
+        let path = std::env::temp_dir().join(format!("lms_state_{}", std::process::id()));
+        let mut store = FileKeyStore::new(&path);
+        let mut key = small_key();
+        let public = key.public_key();
+        key.sign(b"first", &mut store).unwrap();
+        key.sign(b"second", &mut store).unwrap();
+        drop(key);
+
+        let mut reloaded = store.load().unwrap();
+        assert_eq!(reloaded.public_key(), public);
+        let signature = reloaded.sign(b"third", &mut store).unwrap();
+        assert_eq!(signature.q, 2);
+        assert!(public.verify(b"third", &signature));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tampered_signature_rejected() {
+        let mut key = small_key();
+        let public = key.public_key();
+        let signature = key.sign(b"msg", &mut MemoryKeyStore::default()).unwrap();
+
+        let mut bad_path = signature.clone();
+        bad_path.path[2][0] ^= 1;
+        assert!(!public.verify(b"msg", &bad_path));
+
+        let mut bad_q = signature.clone();
+        bad_q.q = 1;
+        assert!(!public.verify(b"msg", &bad_q));
+
+        let mut bad_ots = signature.clone();
+        bad_ots.ots_signature.y[0][0] ^= 1;
+        assert!(!public.verify(b"msg", &bad_ots));
+    }
+
+    #[test]
+    fn wire_format_round_trip() {
+        let mut key =
+            LmsPrivateKey::generate(LmsType::Sha256M32H5, LmotsType::Sha256N32W4).unwrap();
+        let public = key.public_key();
+        let signature = key.sign(b"wire", &mut MemoryKeyStore::default()).unwrap();
+
+        let pk_bytes = public.to_bytes();
+        assert_eq!(pk_bytes.len(), 56);
+        assert_eq!(LmsPublicKey::from_bytes(&pk_bytes).unwrap(), public);
+
+        let sig_bytes = signature.to_bytes();
+        // RFC 8554: 4 + (4 + n + n*p) + 4 + m*h
+        assert_eq!(sig_bytes.len(), 4 + (4 + 32 + 32 * 67) + 4 + 32 * 5);
+        let parsed = LmsSignature::from_bytes(&sig_bytes).unwrap();
+        assert_eq!(parsed, signature);
+        assert!(public.verify(b"wire", &parsed));
+        assert!(LmsSignature::from_bytes(&sig_bytes[..sig_bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn debug_hides_seed() {
+        let key = small_key();
+        let seed_hex = hex::encode(*key.state().seed);
+        assert!(!format!("{:?}", key).contains(&seed_hex));
+    }
+}
@@ -48,6 +48,45 @@ pub struct MMRProof {
     mmr_root: H256,
 }
 
+impl MMRProof {
+    /// Rebuild a proof received from elsewhere, e.g. to check it with `MMR::verify_proof`.
+    pub fn new(
+        target_node: H256,
+        target_idx: usize,
+        proofs: Vec<H256>,
+        mmr_size: usize,
+        mmr_root: H256,
+    ) -> Self {
+        MMRProof {
+            target_node,
+            target_idx,
+            proofs,
+            mmr_size,
+            mmr_root,
+        }
+    }
+
+    pub fn target_node(&self) -> H256 {
+        self.target_node
+    }
+
+    pub fn target_idx(&self) -> usize {
+        self.target_idx
+    }
+
+    pub fn proofs(&self) -> &[H256] {
+        &self.proofs
+    }
+
+    pub fn mmr_size(&self) -> usize {
+        self.mmr_size
+    }
+
+    pub fn mmr_root(&self) -> H256 {
+        self.mmr_root
+    }
+}
+
 impl<T> MMR<T> {
     pub fn new(data: &[T]) -> Self
     where
@@ -371,7 +410,7 @@ impl<T> MMR<T> {
             // and right neighbor is lower than us
             if (right_neigh_idx >= self.current_len - 1
                 || self.heights[right_neigh_idx] < current_height)
-                && (left_neigh_idx == 0 || self.heights[left_neigh_idx] > current_height)
+                && (offset > current_idx || self.heights[left_neigh_idx] > current_height)
             {
                 // we are reaching at current tree peak
                 break;
@@ -535,13 +574,22 @@ impl<T> MMR<T> {
     pub fn verify(&self, mmr_proof: &MMRProof) -> bool {
         debug_println!("MMR::verify the proof: {:?}", mmr_proof);
         // small boundary checks
-        if mmr_proof.target_idx >= mmr_proof.mmr_size {
+        if mmr_proof.target_idx >= mmr_proof.mmr_size || mmr_proof.target_idx >= self.current_len {
             return false;
         }
         // the hash value passed in is diff than our record :(
         if mmr_proof.target_node != self.nodes[mmr_proof.target_idx] {
             return false;
         }
+        Self::verify_proof(mmr_proof)
+    }
+
+    /// verify a proof against the root it carries, without the MMR itself,
+    /// e.g. on a client that only knows a published root
+    pub fn verify_proof(mmr_proof: &MMRProof) -> bool {
+        if mmr_proof.target_idx >= mmr_proof.mmr_size {
+            return false;
+        }
         // let heights = Self::_get_heights(mmr_proof.mmr_size);
         let mut current_idx = mmr_proof.target_idx;
         // let mut current_height = heights[current_idx];
@@ -560,8 +608,22 @@ impl<T> MMR<T> {
             // prepare a context for this round
             // let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
             ctx.reset();
-            let offset = (2 << current_height) - 1;
-            let right_neigh_idx = current_idx + offset;
+            // a well formed path never leaves the MMR; a forged one could
+            // otherwise overflow the index arithmetic below
+            if current_idx >= mmr_proof.mmr_size {
+                return false;
+            }
+            let offset = match 2usize
+                .checked_shl(current_height as u32)
+                .and_then(|size| size.checked_sub(1))
+            {
+                Some(offset) => offset,
+                None => return false,
+            };
+            let right_neigh_idx = match current_idx.checked_add(offset) {
+                Some(idx) => idx,
+                None => return false,
+            };
             let left_neigh_idx = if offset > current_idx {
                 0
             } else {
@@ -572,10 +634,10 @@ impl<T> MMR<T> {
             // we are at peak if: we are on the side already (cross the boundaries)
             //                    or the heights are different than our current height
             // if (right_neigh_idx >= mmr_proof.mmr_size || heights[right_neigh_idx] < current_height)
-            //     && (left_neigh_idx == 0 || heights[left_neigh_idx] > current_height)
+            //     && (offset > current_idx || heights[left_neigh_idx] > current_height)
             if (right_neigh_idx >= mmr_proof.mmr_size
                 || Self::height_at(right_neigh_idx) < current_height)
-                && (left_neigh_idx == 0 || Self::height_at(left_neigh_idx) > current_height)
+                && (offset > current_idx || Self::height_at(left_neigh_idx) > current_height)
             {
                 debug_println!(
                     "Reached peak at idx={:?}, height={:?}, right_nei={:?}, left_nei={:?}",
@@ -875,6 +937,24 @@ impl<T> MMR<T> {
         return peaks;
     }
 
+    /// bag peak hashes (left to right) into a root the same way `get_root` does,
+    /// root = hash(p1, hash(p2, p3))
+    pub fn bag_peaks(peaks: &[H256]) -> H256 {
+        let mut iter = peaks.iter().rev();
+        let mut right_hash = match iter.next() {
+            Some(last) => *last,
+            None => return [0u8; 32].into(),
+        };
+        let mut ctx = Hasher::new(&HASH_TYPE);
+        for left in iter {
+            ctx.reset();
+            ctx.update(left.as_ref());
+            ctx.update(right_hash.as_ref());
+            right_hash = ctx.finish();
+        }
+        right_hash
+    }
+
     pub fn get_hash_at(&self, idx: usize) -> H256 {
         if idx > self.current_len - 1 {
             return [0u8; 32].into();
@@ -1288,6 +1368,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn proof_two_node() {
+        // the second leaf's left neighbour is node 0, which must not be
+        // mistaken for "no left neighbour"
+        let input: Vec<H256> = vec![
+            hex!("0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d").into(),
+            (hex!("0101010101010101010101010101010101010101010101010101010101010202")).into(),
+        ];
+        let mmr: MMR<H256> = MMR::new(&input);
+        let mmr_size = mmr.get_size();
+        for i in 0..(mmr_size + 10) {
+            assert_eq!(mmr.verify(&mmr.proof(i)), i < mmr_size);
+        }
+        assert_eq!(mmr.proof(1).proofs, vec![mmr.get_hash_at(0)]);
+    }
+
+    #[test]
+    fn verify_proof_for_larger_mmr() {
+        let input: Vec<H256> = vec![
+            hex!("0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d").into(),
+            (hex!("0101010101010101010101010101010101010101010101010101010101010202")).into(),
+            (hex!("0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f")).into(),
+        ];
+        let small: MMR<H256> = MMR::new(&input[..1]);
+        let large: MMR<H256> = MMR::new(&input);
+        // a proof for a node the small MMR doesn't have is rejected, not indexed
+        let proof = large.proof(3);
+        assert_eq!(large.verify(&proof), true);
+        assert_eq!(small.verify(&proof), false);
+    }
+
     #[test]
     fn proof_three_node() {
         time_test!();
@@ -170,6 +170,19 @@ impl<T> MerkleTree<T> {
     }
 }
 
+/// Number of proof hashes `MerkleTree::proof` returns for a tree with `leaf_count` leaves.
+/// `verify` doesn't check this itself, callers that know the leaf count should.
+pub fn proof_len(leaf_count: usize) -> usize {
+    let mut len = 0;
+    let mut width = leaf_count;
+    while width > 1 {
+        // each level halves the width, rounding up
+        width -= width / 2;
+        len += 1;
+    }
+    len
+}
+
 /// Verify that the datum hash with a vector of proofs will produce the Merkle root. Also need the
 /// index of datum and `leaf_size`, the total number of leaves.
 pub fn verify(root: &H256, datum: &H256, proof: &[H256], index: usize, _leaf_size: usize) -> bool {
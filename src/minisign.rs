@@ -0,0 +1,778 @@
+//! minisign compatible keys and detached signatures.
+//!
+//! Files are two lines, an untrusted comment and a base64 payload; signatures
+//! add a trusted comment and a global signature covering it:
+//!
+//! ```notrust
+//!   public key   "Ed" || key id (8) || ed25519 public key (32)
+//!   signature    "Ed" | "ED" || key id (8) || ed25519 signature (64)
+//!   global sig   ed25519 signature over (signature || trusted comment)
+//!   secret key   "Ed" || "Sc" | "\0\0" || "B2" || salt (32) || opslimit (8, le)
+//!                || memlimit (8, le) || (key id || seed || public key || checksum)
+//! ```
+//!
+//! `ED` signatures sign BLAKE2b-512 of the file, so files are hashed while
+//! streaming; `Ed` (legacy) signatures sign the file itself. Secret keys are
+//! encrypted by xoring with scrypt output, and the checksum is BLAKE2b-256 of
+//! `"Ed" || key id || seed || public key`.
+use crate::key_pair::{KeyError, KeyPair, PublicKey, Signature};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{constant_time_eq, Zeroizing};
+use blake2::digest::{Input, VariableOutput};
+use blake2::VarBlake2b;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// scrypt limits `minisign -G` uses (libsodium's "sensitive" preset).
+pub const DEFAULT_OPSLIMIT: u64 = 33_554_432;
+pub const DEFAULT_MEMLIMIT: u64 = 1_073_741_824;
+
+const ALG_ED25519: &[u8; 2] = b"Ed";
+const ALG_PREHASHED: &[u8; 2] = b"ED";
+const KDF_SCRYPT: &[u8; 2] = b"Sc";
+const KDF_NONE: &[u8; 2] = b"\0\0";
+const CHK_BLAKE2B: &[u8; 2] = b"B2";
+const COMMENT_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+const KEY_ID_LEN: usize = 8;
+const SALT_LEN: usize = 32;
+// key id || seed || public key || checksum
+const KEYNUM_SK_LEN: usize = KEY_ID_LEN + 64 + 32;
+const SECRET_KEY_LEN: usize = 6 + SALT_LEN + 16 + KEYNUM_SK_LEN;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum MinisignError {
+    /// not a well formed minisign file
+    InvalidFormat(String),
+    UnsupportedAlgorithm(String),
+    /// the signature was made by a different key
+    KeyIdMismatch,
+    /// wrong password, or a corrupted secret key
+    ChecksumMismatch,
+    InvalidSignature,
+    /// the trusted comment was modified
+    InvalidGlobalSignature,
+    Key(KeyError),
+    Random(RandomError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MinisignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MinisignError::InvalidFormat(e) => write!(f, "invalid minisign data: {}", e),
+            MinisignError::UnsupportedAlgorithm(a) => write!(f, "unsupported algorithm {}", a),
+            MinisignError::KeyIdMismatch => write!(f, "signature key id does not match"),
+            MinisignError::ChecksumMismatch => {
+                write!(f, "secret key checksum mismatch: wrong password?")
+            }
+            MinisignError::InvalidSignature => write!(f, "signature verification failed"),
+            MinisignError::InvalidGlobalSignature => {
+                write!(f, "trusted comment signature verification failed")
+            }
+            MinisignError::Key(e) => write!(f, "invalid key: {}", e),
+            MinisignError::Random(e) => write!(f, "{}", e),
+            MinisignError::Io(e) => write!(f, "minisign io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MinisignError {}
+
+impl From<KeyError> for MinisignError {
+    fn from(e: KeyError) -> MinisignError {
+        MinisignError::Key(e)
+    }
+}
+
+impl From<RandomError> for MinisignError {
+    fn from(e: RandomError) -> MinisignError {
+        MinisignError::Random(e)
+    }
+}
+
+impl From<std::io::Error> for MinisignError {
+    fn from(e: std::io::Error) -> MinisignError {
+        MinisignError::Io(e)
+    }
+}
+
+fn invalid(e: &str) -> MinisignError {
+    MinisignError::InvalidFormat(e.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinisignPublicKey {
+    key_id: [u8; KEY_ID_LEN],
+    key: PublicKey,
+}
+
+pub struct MinisignSecretKey {
+    key_id: [u8; KEY_ID_LEN],
+    key: KeyPair,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinisignSignature {
+    /// `ED` signatures sign the BLAKE2b-512 digest of the data
+    pub prehashed: bool,
+    pub key_id: [u8; KEY_ID_LEN],
+    pub signature: Signature,
+    pub untrusted_comment: String,
+    pub trusted_comment: String,
+    pub global_signature: Signature,
+}
+
+impl MinisignPublicKey {
+    pub fn key_id(&self) -> [u8; KEY_ID_LEN] {
+        self.key_id
+    }
+
+    /// The key id the way minisign prints it: a little endian u64 in upper case hex.
+    pub fn key_id_hex(&self) -> String {
+        key_id_hex(&self.key_id)
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// The bare base64 form, as passed to `minisign -P`.
+    pub fn to_base64(&self) -> String {
+        let mut bytes = ALG_ED25519.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.key.as_bytes());
+        base64::encode(&bytes)
+    }
+
+    pub fn from_base64(line: &str) -> Result<MinisignPublicKey, MinisignError> {
+        let bytes = base64::decode(line.trim()).map_err(|_| invalid("bad base64"))?;
+        if bytes.len() != 2 + KEY_ID_LEN + 32 {
+            return Err(invalid("bad public key length"));
+        }
+        if &bytes[..2] != ALG_ED25519 {
+            return Err(unsupported(&bytes[..2]));
+        }
+        let mut key_id = [0u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&bytes[2..10]);
+        Ok(MinisignPublicKey {
+            key_id,
+            key: PublicKey::from_bytes(&bytes[10..])?,
+        })
+    }
+
+    /// The contents of a `minisign.pub` file.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}minisign public key {}\n{}\n",
+            COMMENT_PREFIX,
+            self.key_id_hex(),
+            self.to_base64()
+        )
+    }
+
+    /// Parse a `minisign.pub` file, or a bare base64 key.
+    pub fn decode(text: &str) -> Result<MinisignPublicKey, MinisignError> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let mut line = lines.next().ok_or_else(|| invalid("empty public key"))?;
+        if line.starts_with(COMMENT_PREFIX) {
+            line = lines.next().ok_or_else(|| invalid("missing public key"))?;
+        }
+        MinisignPublicKey::from_base64(line)
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &MinisignSignature) -> Result<(), MinisignError> {
+        let digest;
+        let signed = if signature.prehashed {
+            digest = blake2b_512(data);
+            &digest[..]
+        } else {
+            data
+        };
+        self.verify_signed(signed, signature)
+    }
+
+    /// Verify `reader`'s contents. Prehashed signatures are checked while streaming,
+    /// legacy ones need the whole input in memory.
+    pub fn verify_reader<R: Read>(
+        &self,
+        mut reader: R,
+        signature: &MinisignSignature,
+    ) -> Result<(), MinisignError> {
+        if signature.prehashed {
+            let digest = blake2b_512_reader(&mut reader)?;
+            self.verify_signed(&digest, signature)
+        } else {
+            let mut data = vec![];
+            reader.read_to_end(&mut data)?;
+            self.verify_signed(&data, signature)
+        }
+    }
+
+    pub fn verify_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        signature: &MinisignSignature,
+    ) -> Result<(), MinisignError> {
+        self.verify_reader(File::open(path)?, signature)
+    }
+
+    fn verify_signed(
+        &self,
+        signed: &[u8],
+        signature: &MinisignSignature,
+    ) -> Result<(), MinisignError> {
+        if signature.key_id != self.key_id {
+            return Err(MinisignError::KeyIdMismatch);
+        }
+        if !self.key.verify(signed, &signature.signature) {
+            return Err(MinisignError::InvalidSignature);
+        }
+        let payload = global_payload(&signature.signature, &signature.trusted_comment);
+        if !self.key.verify(&payload, &signature.global_signature) {
+            return Err(MinisignError::InvalidGlobalSignature);
+        }
+        Ok(())
+    }
+}
+
+impl MinisignSecretKey {
+    /// A fresh key with a random key id.
+    pub fn generate() -> MinisignSecretKey {
+        MinisignSecretKey::generate_with_rng(&SystemRandom::new()).unwrap()
+    }
+
+    /// A key whose seed and key id are drawn from `rng`.
+    pub fn generate_with_rng(rng: &dyn SecureRandom) -> Result<MinisignSecretKey, MinisignError> {
+        let key = KeyPair::generate_with_rng(rng)?;
+        MinisignSecretKey::from_key_pair_with_rng(key, rng)
+    }
+
+    /// Use an existing key pair under a new random key id.
+    pub fn from_key_pair(key: KeyPair) -> MinisignSecretKey {
+        MinisignSecretKey::from_key_pair_with_rng(key, &SystemRandom::new()).unwrap()
+    }
+
+    pub fn from_key_pair_with_rng(
+        key: KeyPair,
+        rng: &dyn SecureRandom,
+    ) -> Result<MinisignSecretKey, MinisignError> {
+        let mut key_id = [0u8; KEY_ID_LEN];
+        rng.fill(&mut key_id)?;
+        Ok(MinisignSecretKey { key_id, key })
+    }
+
+    pub fn key_id(&self) -> [u8; KEY_ID_LEN] {
+        self.key_id
+    }
+
+    pub fn key_pair(&self) -> &KeyPair {
+        &self.key
+    }
+
+    pub fn public_key(&self) -> MinisignPublicKey {
+        MinisignPublicKey {
+            key_id: self.key_id,
+            key: self.key.public_key(),
+        }
+    }
+
+    /// Sign `data` the way current minisign does (prehashed).
+    pub fn sign(&self, data: &[u8], trusted_comment: &str) -> MinisignSignature {
+        self.sign_signed(&blake2b_512(data), true, trusted_comment)
+    }
+
+    /// Sign `data` itself, for verifiers older than minisign 0.8.
+    pub fn sign_legacy(&self, data: &[u8], trusted_comment: &str) -> MinisignSignature {
+        self.sign_signed(data, false, trusted_comment)
+    }
+
+    pub fn sign_reader<R: Read>(
+        &self,
+        mut reader: R,
+        trusted_comment: &str,
+    ) -> Result<MinisignSignature, MinisignError> {
+        let digest = blake2b_512_reader(&mut reader)?;
+        Ok(self.sign_signed(&digest, true, trusted_comment))
+    }
+
+    /// Sign a file. Without a trusted comment, uses minisign's default of
+    /// `timestamp:<unix time>\tfile:<file name>\thashed`.
+    pub fn sign_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        trusted_comment: Option<&str>,
+    ) -> Result<MinisignSignature, MinisignError> {
+        let path = path.as_ref();
+        let trusted_comment = match trusted_comment {
+            Some(comment) => comment.to_string(),
+            None => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                format!("timestamp:{}\tfile:{}\thashed", timestamp, name)
+            }
+        };
+        self.sign_reader(File::open(path)?, &trusted_comment)
+    }
+
+    fn sign_signed(
+        &self,
+        signed: &[u8],
+        prehashed: bool,
+        trusted_comment: &str,
+    ) -> MinisignSignature {
+        let signature = self.key.sign(signed);
+        let global_signature = self.key.sign(&global_payload(&signature, trusted_comment));
+        MinisignSignature {
+            prehashed,
+            key_id: self.key_id,
+            signature,
+            untrusted_comment: "signature from minisign secret key".to_string(),
+            trusted_comment: trusted_comment.to_string(),
+            global_signature,
+        }
+    }
+
+    /// The contents of a `minisign.key` file, encrypted with the default scrypt limits
+    /// unless `password` is `None`.
+    pub fn encode(&self, password: Option<&str>) -> Result<Zeroizing<String>, MinisignError> {
+        self.encode_with_limits(password, DEFAULT_OPSLIMIT, DEFAULT_MEMLIMIT)
+    }
+
+    /// Limits above `DEFAULT_OPSLIMIT` and `DEFAULT_MEMLIMIT` are rejected, since `decode`
+    /// would refuse to open the result.
+    pub fn encode_with_limits(
+        &self,
+        password: Option<&str>,
+        opslimit: u64,
+        memlimit: u64,
+    ) -> Result<Zeroizing<String>, MinisignError> {
+        self.encode_with_rng(password, opslimit, memlimit, &SystemRandom::new())
+    }
+
+    /// `encode_with_limits` with the salt drawn from `rng`.
+    pub fn encode_with_rng(
+        &self,
+        password: Option<&str>,
+        opslimit: u64,
+        memlimit: u64,
+        rng: &dyn SecureRandom,
+    ) -> Result<Zeroizing<String>, MinisignError> {
+        check_limits(opslimit, memlimit)?;
+        let public = self.key.public_key();
+        let mut keynum_sk = Zeroizing::new(Vec::with_capacity(KEYNUM_SK_LEN));
+        keynum_sk.extend_from_slice(&self.key_id);
+        keynum_sk.extend_from_slice(self.key.seed());
+        keynum_sk.extend_from_slice(public.as_bytes());
+        let checksum = secret_key_checksum(&self.key_id, self.key.seed(), &public);
+        keynum_sk.extend_from_slice(&checksum);
+
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt)?;
+        let mut bytes = Zeroizing::new(ALG_ED25519.to_vec());
+        match password {
+            Some(password) => {
+                bytes.extend_from_slice(KDF_SCRYPT);
+                let stream = scrypt_stream(password, &salt, opslimit, memlimit)
+                    .ok_or_else(|| invalid("bad scrypt limits"))?;
+                for (b, s) in keynum_sk.iter_mut().zip(stream.iter()) {
+                    *b ^= s;
+                }
+            }
+            None => bytes.extend_from_slice(KDF_NONE),
+        }
+        bytes.extend_from_slice(CHK_BLAKE2B);
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&opslimit.to_le_bytes());
+        bytes.extend_from_slice(&memlimit.to_le_bytes());
+        bytes.extend_from_slice(&keynum_sk);
+
+        let comment = if password.is_some() {
+            "minisign encrypted secret key"
+        } else {
+            "minisign secret key"
+        };
+        let mut out = Zeroizing::new(format!("{}{}\n", COMMENT_PREFIX, comment));
+        out.push_str(&Zeroizing::new(base64::encode(&bytes[..])));
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// Read a `minisign.key` file. `password` is required for encrypted keys.
+    pub fn decode(text: &str, password: Option<&str>) -> Result<MinisignSecretKey, MinisignError> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let mut line = lines.next().ok_or_else(|| invalid("empty secret key"))?;
+        if line.starts_with(COMMENT_PREFIX) {
+            line = lines.next().ok_or_else(|| invalid("missing secret key"))?;
+        }
+        let bytes = Zeroizing::new(base64::decode(line.trim()).map_err(|_| invalid("bad base64"))?);
+        if bytes.len() != SECRET_KEY_LEN {
+            return Err(invalid("bad secret key length"));
+        }
+        if &bytes[..2] != ALG_ED25519 {
+            return Err(unsupported(&bytes[..2]));
+        }
+        if &bytes[4..6] != CHK_BLAKE2B {
+            return Err(unsupported(&bytes[4..6]));
+        }
+        let salt = &bytes[6..6 + SALT_LEN];
+        let mut limit = [0u8; 8];
+        limit.copy_from_slice(&bytes[38..46]);
+        let opslimit = u64::from_le_bytes(limit);
+        limit.copy_from_slice(&bytes[46..54]);
+        let memlimit = u64::from_le_bytes(limit);
+        let mut keynum_sk = Zeroizing::new(bytes[54..].to_vec());
+
+        if &bytes[2..4] == KDF_SCRYPT {
+            let password = password.ok_or_else(|| invalid("secret key is encrypted"))?;
+            check_limits(opslimit, memlimit)?;
+            let stream = scrypt_stream(password, salt, opslimit, memlimit)
+                .ok_or_else(|| invalid("bad scrypt limits"))?;
+            for (b, s) in keynum_sk.iter_mut().zip(stream.iter()) {
+                *b ^= s;
+            }
+        } else if &bytes[2..4] != KDF_NONE {
+            return Err(unsupported(&bytes[2..4]));
+        }
+
+        let mut key_id = [0u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&keynum_sk[..KEY_ID_LEN]);
+        let seed = &keynum_sk[KEY_ID_LEN..KEY_ID_LEN + 32];
+        let public = PublicKey::from_bytes(&keynum_sk[KEY_ID_LEN + 32..KEY_ID_LEN + 64])?;
+        let checksum = secret_key_checksum(&key_id, seed, &public);
+        if !constant_time_eq(&checksum, &keynum_sk[KEY_ID_LEN + 64..]) {
+            return Err(MinisignError::ChecksumMismatch);
+        }
+        let key = KeyPair::from_seed(seed)?;
+        if key.public_key() != public {
+            return Err(invalid("public key does not match seed"));
+        }
+        Ok(MinisignSecretKey { key_id, key })
+    }
+}
+
+impl std::fmt::Debug for MinisignSecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MinisignSecretKey")
+            .field("key_id", &key_id_hex(&self.key_id))
+            .field("public_key", &self.key.public_key())
+            .finish()
+    }
+}
+
+impl MinisignSignature {
+    /// The contents of a `.minisig` file.
+    pub fn encode(&self) -> String {
+        let mut bytes = if self.prehashed {
+            ALG_PREHASHED.to_vec()
+        } else {
+            ALG_ED25519.to_vec()
+        };
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.signature.as_bytes());
+        format!(
+            "{}{}\n{}\n{}{}\n{}\n",
+            COMMENT_PREFIX,
+            self.untrusted_comment,
+            base64::encode(&bytes),
+            TRUSTED_PREFIX,
+            self.trusted_comment,
+            base64::encode(&self.global_signature.as_bytes()[..])
+        )
+    }
+
+    pub fn decode(text: &str) -> Result<MinisignSignature, MinisignError> {
+        let mut lines = text.lines();
+        let mut next = |what: &str| lines.next().ok_or_else(|| invalid(what));
+        let untrusted_comment = next("missing untrusted comment")?
+            .strip_prefix(COMMENT_PREFIX)
+            .ok_or_else(|| invalid("missing untrusted comment"))?
+            .to_string();
+        let bytes =
+            base64::decode(next("missing signature")?.trim()).map_err(|_| invalid("bad base64"))?;
+        let trusted_comment = next("missing trusted comment")?
+            .strip_prefix(TRUSTED_PREFIX)
+            .ok_or_else(|| invalid("missing trusted comment"))?
+            .to_string();
+        let global = base64::decode(next("missing global signature")?.trim())
+            .map_err(|_| invalid("bad base64"))?;
+
+        if bytes.len() != 2 + KEY_ID_LEN + 64 {
+            return Err(invalid("bad signature length"));
+        }
+        let prehashed = match &bytes[..2] {
+            alg if alg == ALG_PREHASHED => true,
+            alg if alg == ALG_ED25519 => false,
+            alg => return Err(unsupported(alg)),
+        };
+        let mut key_id = [0u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&bytes[2..10]);
+        Ok(MinisignSignature {
+            prehashed,
+            key_id,
+            signature: Signature::from_bytes(&bytes[10..])?,
+            untrusted_comment,
+            trusted_comment,
+            global_signature: Signature::from_bytes(&global)?,
+        })
+    }
+}
+
+// the global signature binds the trusted comment to the signature
+fn global_payload(signature: &Signature, trusted_comment: &str) -> Vec<u8> {
+    let mut payload = signature.as_bytes().to_vec();
+    payload.extend_from_slice(trusted_comment.as_bytes());
+    payload
+}
+
+fn unsupported(alg: &[u8]) -> MinisignError {
+    MinisignError::UnsupportedAlgorithm(String::from_utf8_lossy(alg).into_owned())
+}
+
+fn key_id_hex(key_id: &[u8; KEY_ID_LEN]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+fn blake2b_512(data: &[u8]) -> Vec<u8> {
+    let mut ctx = VarBlake2b::new(64).unwrap();
+    ctx.input(data);
+    let mut out = Vec::with_capacity(64);
+    ctx.variable_result(|res| out.extend_from_slice(res));
+    out
+}
+
+fn blake2b_512_reader<R: Read>(reader: &mut R) -> Result<Vec<u8>, MinisignError> {
+    let mut ctx = VarBlake2b::new(64).unwrap();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        ctx.input(&buffer[..n]);
+    }
+    let mut out = Vec::with_capacity(64);
+    ctx.variable_result(|res| out.extend_from_slice(res));
+    Ok(out)
+}
+
+fn secret_key_checksum(key_id: &[u8], seed: &[u8], public: &PublicKey) -> Vec<u8> {
+    let mut ctx = VarBlake2b::new(32).unwrap();
+    ctx.input(ALG_ED25519);
+    ctx.input(key_id);
+    ctx.input(seed);
+    ctx.input(public.as_bytes());
+    let mut out = Vec::with_capacity(32);
+    ctx.variable_result(|res| out.extend_from_slice(res));
+    out
+}
+
+// minisign only ever writes the defaults, refuse to allocate more than they need
+fn check_limits(opslimit: u64, memlimit: u64) -> Result<(), MinisignError> {
+    if opslimit > DEFAULT_OPSLIMIT || memlimit > DEFAULT_MEMLIMIT {
+        return Err(invalid("scrypt limits too large"));
+    }
+    Ok(())
+}
+
+// libsodium's crypto_pwhash_scryptsalsa208sha256 mapping of limits to (log2 N, r, p)
+fn scrypt_params(opslimit: u64, memlimit: u64) -> (u8, u32, u32) {
+    let opslimit = opslimit.max(32768);
+    let r = 8u64;
+    let log_n = |max_n: u64| (1..63u8).find(|&n| (1u64 << n) > max_n / 2).unwrap_or(63);
+    if opslimit < memlimit / 32 {
+        (log_n(opslimit / (r * 4)), r as u32, 1)
+    } else {
+        let n = log_n(memlimit / (r * 128));
+        let max_rp = ((opslimit / 4) >> n).min(0x3fff_ffff);
+        (n, r as u32, (max_rp / r) as u32)
+    }
+}
+
+fn scrypt_stream(
+    password: &str,
+    salt: &[u8],
+    opslimit: u64,
+    memlimit: u64,
+) -> Option<Zeroizing<Vec<u8>>> {
+    let (log_n, r, p) = scrypt_params(opslimit, memlimit);
+    let params = scrypt::Params::new(log_n, r, p, 32).ok()?;
+    let mut stream = Zeroizing::new(vec![0u8; KEYNUM_SK_LEN]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut stream).ok()?;
+    Some(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // from the minisign-verify test suite, made with the minisign tool
+    const MINISIGN_PUB: &str = "untrusted comment: minisign public key E7620F1842B4E81F
+RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3
+";
+    const MINISIGN_LEGACY_SIG: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+    const MINISIGN_PREHASHED_SIG: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+
+    // seed 00..1f, key id 0102030405060708, password "release", salt 5a.., opslimit 32768,
+    // memlimit 2^24 (scrypt N = 2^10); built independently with python hashlib
+    const SECRET_KEY: &str = "untrusted comment: minisign encrypted secret key
+RWRTY0IyWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWloAgAAAAAAAAAAAAAEAAAAASjM4lCxUJ4nEjAz2fuLRWdcyePKJzhWZEX7p0wX2fEbetWdJFVwt3pAV6E4sxdGqqDHzNK23ycWS0iWuz64MfAjQfBZ0A+OY8rkbTqodmJfzWzV6Qb+89qxdSnCIG6pZgRCIdty6z48=
+";
+    const SECRET_KEY_PUB: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const SECRET_KEY_SIG: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCEgYDs2Ej+d9AB8N7BBoZzSe5GpWXKSD/eCa6cJqgoTeQNHMRTnYJYAWp6aCZREqHwd9n1Qfz9VuR0sNOhnYBgs=
+trusted comment: timestamp:1700000000\tfile:artifact.tar.gz\thashed
+Ndf817sGrLJwO8MSFrrGF6affk7Dl56v2FOrjqC7QcEHsmrPOEb4y9xBCIkoqRsEGmQU0d0GwQrOhHvoj5IgBg==
+";
+
+    #[test]
+    fn verifies_minisign_signatures() {
+        let public = MinisignPublicKey::decode(MINISIGN_PUB).unwrap();
+        assert_eq!(public.key_id_hex(), "E7620F1842B4E81F");
+        assert_eq!(public.encode(), MINISIGN_PUB);
+
+        for (sig, prehashed) in
+            [(MINISIGN_LEGACY_SIG, false), (MINISIGN_PREHASHED_SIG, true)].iter()
+        {
+            let signature = MinisignSignature::decode(sig).unwrap();
+            assert_eq!(signature.prehashed, *prehashed);
+            assert_eq!(signature.encode(), *sig);
+            assert!(public.verify(b"test", &signature).is_ok());
+            assert!(public.verify_reader(&b"test"[..], &signature).is_ok());
+            assert!(matches!(
+                public.verify(b"Test", &signature),
+                Err(MinisignError::InvalidSignature)
+            ));
+
+            let mut edited = signature.clone();
+            edited.trusted_comment.push_str("\tfile:other");
+            assert!(matches!(
+                public.verify(b"test", &edited),
+                Err(MinisignError::InvalidGlobalSignature)
+            ));
+        }
+    }
+
+    #[test]
+    fn decodes_encrypted_secret_key() {
+        let secret = MinisignSecretKey::decode(SECRET_KEY, Some("release")).unwrap();
+        assert_eq!(
+            secret.key_pair().to_pkcs8(),
+            KeyPair::from_seed(&(0..32).collect::<Vec<u8>>())
+                .unwrap()
+                .to_pkcs8()
+        );
+        let public = secret.public_key();
+        assert_eq!(public.to_base64(), SECRET_KEY_PUB);
+        assert_eq!(public.key_id_hex(), "0807060504030201");
+
+        // ed25519 is deterministic, so the signature matches the fixture exactly
+        let signature = secret.sign(
+            b"release artifact",
+            "timestamp:1700000000\tfile:artifact.tar.gz\thashed",
+        );
+        assert_eq!(signature.encode(), SECRET_KEY_SIG);
+        assert!(public.verify(b"release artifact", &signature).is_ok());
+
+        assert!(matches!(
+            MinisignSecretKey::decode(SECRET_KEY, Some("wrong")),
+            Err(MinisignError::ChecksumMismatch)
+        ));
+        assert!(MinisignSecretKey::decode(SECRET_KEY, None).is_err());
+    }
+
+    #[test]
+    fn secret_key_round_trip() {
+        let secret = MinisignSecretKey::generate();
+        let text = secret
+            .encode_with_limits(Some("pw"), 32768, 1 << 24)
+            .unwrap();
+        let back = MinisignSecretKey::decode(&text, Some("pw")).unwrap();
+        assert_eq!(back.public_key(), secret.public_key());
+
+        let text = secret.encode(None).unwrap();
+        let back = MinisignSecretKey::decode(&text, None).unwrap();
+        assert_eq!(back.public_key(), secret.public_key());
+        assert!(!format!("{:?}", back).contains(&hex::encode(back.key_pair().seed())));
+    }
+
+    // scrypt with 1 GiB of memory takes minutes in a debug build
+    #[test]
+    #[ignore = "slow, run with --ignored"]
+    fn secret_key_round_trip_at_max_limits() {
+        let secret = MinisignSecretKey::generate();
+        let text = secret.encode(Some("pw")).unwrap();
+        let back = MinisignSecretKey::decode(&text, Some("pw")).unwrap();
+        assert_eq!(back.public_key(), secret.public_key());
+    }
+
+    #[test]
+    fn secret_key_limits_are_bounded() {
+        let secret = MinisignSecretKey::generate();
+        for (opslimit, memlimit) in &[
+            (DEFAULT_OPSLIMIT + 1, DEFAULT_MEMLIMIT),
+            (DEFAULT_OPSLIMIT, DEFAULT_MEMLIMIT + 1),
+        ] {
+            assert!(matches!(
+                secret.encode_with_limits(Some("pw"), *opslimit, *memlimit),
+                Err(MinisignError::InvalidFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn scrypt_limits_match_libsodium() {
+        assert_eq!(
+            scrypt_params(DEFAULT_OPSLIMIT, DEFAULT_MEMLIMIT),
+            (20, 8, 1)
+        );
+        assert_eq!(scrypt_params(32768, 1 << 24), (10, 8, 1));
+        assert_eq!(scrypt_params(1 << 20, 1 << 20), (10, 8, 32));
+    }
+
+    #[test]
+    fn sign_and_verify_files() {
+        let dir = std::env::temp_dir().join(format!("minisign-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("artifact.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let secret = MinisignSecretKey::generate();
+        let public = MinisignPublicKey::decode(&secret.public_key().encode()).unwrap();
+        let signature = secret.sign_file(&path, None).unwrap();
+        assert!(signature
+            .trusted_comment
+            .contains("\tfile:artifact.bin\thashed"));
+        let signature = MinisignSignature::decode(&signature.encode()).unwrap();
+        assert!(public.verify_file(&path, &signature).is_ok());
+        assert!(public.verify(&data, &signature).is_ok());
+
+        let legacy = secret.sign_legacy(&data, "legacy");
+        assert!(public.verify_file(&path, &legacy).is_ok());
+
+        let other = MinisignSecretKey::generate().public_key();
+        assert!(matches!(
+            other.verify_file(&path, &signature),
+            Err(MinisignError::KeyIdMismatch)
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
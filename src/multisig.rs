@@ -0,0 +1,295 @@
+//! M-of-N Ed25519 multisignatures.
+//!
+//! A `MultisigPolicy` is a threshold plus an ordered list of public keys and
+//! is identified by its policy hash. Signers sign
+//!
+//! ```notrust
+//!   payload = HASH_TYPE("crypto_lib/multisig/v1" || policy hash || message)
+//! ```
+//!
+//! so a signature collected for one policy can't be replayed under another.
+//! A `MultisigBundle` carries the message and the collected signatures.
+use crate::hash::{Hashable, Hasher, HasherType, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use crate::HASH_TYPE;
+use serde::{Deserialize, Serialize};
+
+const DOMAIN: &[u8] = b"crypto_lib/multisig/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultisigError {
+    /// threshold must be in 1..=n
+    InvalidThreshold,
+    EmptyPolicy,
+    DuplicateKey(PublicKey),
+    /// the bundle was built for a different policy
+    PolicyMismatch,
+    UnknownSigner(PublicKey),
+    DuplicateSigner(PublicKey),
+    InvalidSignature(PublicKey),
+    InsufficientSignatures {
+        have: usize,
+        need: usize,
+    },
+}
+
+impl std::fmt::Display for MultisigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MultisigError::InvalidThreshold => write!(f, "threshold must be between 1 and n"),
+            MultisigError::EmptyPolicy => write!(f, "policy has no keys"),
+            MultisigError::DuplicateKey(k) => write!(f, "key {} appears twice in policy", k),
+            MultisigError::PolicyMismatch => write!(f, "bundle is for a different policy"),
+            MultisigError::UnknownSigner(k) => write!(f, "{} is not part of the policy", k),
+            MultisigError::DuplicateSigner(k) => write!(f, "{} signed more than once", k),
+            MultisigError::InvalidSignature(k) => write!(f, "invalid signature from {}", k),
+            MultisigError::InsufficientSignatures { have, need } => {
+                write!(f, "{} of {} required signatures", have, need)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MultisigError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    threshold: u32,
+    keys: Vec<PublicKey>,
+}
+
+impl MultisigPolicy {
+    /// A `threshold` of `keys.len()` policy. Key order is part of the policy hash.
+    pub fn new(threshold: u32, keys: Vec<PublicKey>) -> Result<MultisigPolicy, MultisigError> {
+        let policy = MultisigPolicy { threshold, keys };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn keys(&self) -> &[PublicKey] {
+        &self.keys
+    }
+
+    pub fn contains(&self, key: &PublicKey) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// The policy hash under the crate wide `HASH_TYPE`.
+    pub fn policy_hash(&self) -> H256 {
+        self.hash(&HASH_TYPE)
+    }
+
+    /// The bytes each signer signs for `message` under this policy.
+    pub fn signing_payload(&self, message: &[u8]) -> H256 {
+        let mut hasher = Hasher::new(&HASH_TYPE);
+        hasher.update(DOMAIN);
+        hasher.update(self.policy_hash().as_ref());
+        hasher.update(message);
+        hasher.finish()
+    }
+
+    // deserialized policies skip `new`, so verification re-checks them
+    fn validate(&self) -> Result<(), MultisigError> {
+        if self.keys.is_empty() {
+            return Err(MultisigError::EmptyPolicy);
+        }
+        if self.threshold == 0 || self.threshold as usize > self.keys.len() {
+            return Err(MultisigError::InvalidThreshold);
+        }
+        for (i, key) in self.keys.iter().enumerate() {
+            if self.keys[..i].contains(key) {
+                return Err(MultisigError::DuplicateKey(*key));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Hashable for MultisigPolicy {
+    fn hash(&self, h_type: &HasherType) -> H256 {
+        let mut hasher = Hasher::new(h_type);
+        hasher.update(DOMAIN);
+        hasher.update(&self.threshold.to_be_bytes());
+        hasher.update(&(self.keys.len() as u32).to_be_bytes());
+        for key in self.keys.iter() {
+            hasher.update(key.as_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerEntry {
+    pub signer: PublicKey,
+    pub signature: Signature,
+}
+
+/// Signatures over one message, collected for one policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigBundle {
+    pub policy_hash: H256,
+    pub message: Vec<u8>,
+    pub signatures: Vec<SignerEntry>,
+}
+
+impl MultisigBundle {
+    pub fn new(policy: &MultisigPolicy, message: &[u8]) -> MultisigBundle {
+        MultisigBundle {
+            policy_hash: policy.policy_hash(),
+            message: message.to_vec(),
+            signatures: vec![],
+        }
+    }
+
+    /// Sign with `key` and add the signature. Only checks that the key is in the policy;
+    /// everything else is left to `verify`.
+    pub fn sign(&mut self, policy: &MultisigPolicy, key: &KeyPair) -> Result<(), MultisigError> {
+        let signer = key.public_key();
+        if !policy.contains(&signer) {
+            return Err(MultisigError::UnknownSigner(signer));
+        }
+        let signature = key.sign(policy.signing_payload(&self.message).as_ref());
+        self.add_signature(signer, signature);
+        Ok(())
+    }
+
+    /// Add a signature produced elsewhere, e.g. on an offline signer.
+    pub fn add_signature(&mut self, signer: PublicKey, signature: Signature) {
+        self.signatures.push(SignerEntry { signer, signature });
+    }
+
+    /// Check the bundle against `policy`: every entry must come from a distinct policy key
+    /// and verify, and there must be at least `threshold` of them.
+    pub fn verify(&self, policy: &MultisigPolicy) -> Result<(), MultisigError> {
+        policy.validate()?;
+        if self.policy_hash != policy.policy_hash() {
+            return Err(MultisigError::PolicyMismatch);
+        }
+        let payload = policy.signing_payload(&self.message);
+        let mut seen: Vec<&PublicKey> = Vec::with_capacity(self.signatures.len());
+        for entry in self.signatures.iter() {
+            if !policy.contains(&entry.signer) {
+                return Err(MultisigError::UnknownSigner(entry.signer));
+            }
+            if seen.contains(&&entry.signer) {
+                return Err(MultisigError::DuplicateSigner(entry.signer));
+            }
+            if !entry.signer.verify(payload.as_ref(), &entry.signature) {
+                return Err(MultisigError::InvalidSignature(entry.signer));
+            }
+            seen.push(&entry.signer);
+        }
+        let need = policy.threshold as usize;
+        if seen.len() < need {
+            return Err(MultisigError::InsufficientSignatures {
+                have: seen.len(),
+                need,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(threshold: u32, n: usize) -> (Vec<KeyPair>, MultisigPolicy) {
+        let keys: Vec<KeyPair> = (0..n).map(|_| KeyPair::generate()).collect();
+        let policy =
+            MultisigPolicy::new(threshold, keys.iter().map(|k| k.public_key()).collect()).unwrap();
+        (keys, policy)
+    }
+
+    #[test]
+    fn threshold_reached() {
+        let (keys, policy) = setup(2, 3);
+        let mut bundle = MultisigBundle::new(&policy, b"pay 10 to alice");
+        bundle.sign(&policy, &keys[2]).unwrap();
+        assert_eq!(
+            bundle.verify(&policy),
+            Err(MultisigError::InsufficientSignatures { have: 1, need: 2 })
+        );
+        bundle.sign(&policy, &keys[0]).unwrap();
+        assert_eq!(bundle.verify(&policy), Ok(()));
+    }
+
+    #[test]
+    fn rejects_duplicates_and_strangers() {
+        let (keys, policy) = setup(2, 3);
+        let mut bundle = MultisigBundle::new(&policy, b"m");
+        bundle.sign(&policy, &keys[0]).unwrap();
+        bundle.sign(&policy, &keys[0]).unwrap();
+        assert_eq!(
+            bundle.verify(&policy),
+            Err(MultisigError::DuplicateSigner(keys[0].public_key()))
+        );
+
+        let stranger = KeyPair::generate();
+        let mut bundle = MultisigBundle::new(&policy, b"m");
+        assert!(bundle.sign(&policy, &stranger).is_err());
+        bundle.add_signature(stranger.public_key(), stranger.sign(b"m"));
+        assert_eq!(
+            bundle.verify(&policy),
+            Err(MultisigError::UnknownSigner(stranger.public_key()))
+        );
+    }
+
+    #[test]
+    fn signatures_bound_to_message_and_policy() {
+        let (keys, policy) = setup(1, 2);
+        let mut bundle = MultisigBundle::new(&policy, b"original");
+        bundle.sign(&policy, &keys[1]).unwrap();
+        bundle.message = b"forged".to_vec();
+        assert_eq!(
+            bundle.verify(&policy),
+            Err(MultisigError::InvalidSignature(keys[1].public_key()))
+        );
+
+        // same keys, different threshold -> different policy
+        let other = MultisigPolicy::new(2, policy.keys().to_vec()).unwrap();
+        assert_ne!(other.policy_hash(), policy.policy_hash());
+        let mut bundle = MultisigBundle::new(&policy, b"original");
+        bundle.sign(&policy, &keys[1]).unwrap();
+        assert_eq!(bundle.verify(&other), Err(MultisigError::PolicyMismatch));
+    }
+
+    #[test]
+    fn invalid_policies() {
+        let key = KeyPair::generate().public_key();
+        assert_eq!(
+            MultisigPolicy::new(1, vec![]),
+            Err(MultisigError::EmptyPolicy)
+        );
+        assert_eq!(
+            MultisigPolicy::new(0, vec![key]),
+            Err(MultisigError::InvalidThreshold)
+        );
+        assert_eq!(
+            MultisigPolicy::new(2, vec![key]),
+            Err(MultisigError::InvalidThreshold)
+        );
+        assert_eq!(
+            MultisigPolicy::new(1, vec![key, key]),
+            Err(MultisigError::DuplicateKey(key))
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let (keys, policy) = setup(2, 2);
+        let mut bundle = MultisigBundle::new(&policy, b"transport me");
+        bundle.sign(&policy, &keys[0]).unwrap();
+        bundle.sign(&policy, &keys[1]).unwrap();
+        let policy_back: MultisigPolicy =
+            bincode::deserialize(&bincode::serialize(&policy).unwrap()).unwrap();
+        let bundle_back: MultisigBundle =
+            bincode::deserialize(&bincode::serialize(&bundle).unwrap()).unwrap();
+        assert_eq!(policy_back, policy);
+        assert_eq!(bundle_back.verify(&policy_back), Ok(()));
+    }
+}
@@ -0,0 +1,46 @@
+//! Handling of secret material.
+//!
+//! Comparisons that decide whether a MAC, tag, root or preimage is accepted go
+//! through `constant_time_eq`, so their running time doesn't reveal how many
+//! leading bytes matched. `H256`, `H160` and `key_pair::Signature` compare this
+//! way in their `PartialEq`.
+//!
+//! Seeds, PKCS#8 documents and derived keys are kept in `Zeroizing` buffers
+//! (or wiped in `Drop`) so they are overwritten when released.
+pub use zeroize::{Zeroize, Zeroizing};
+
+/// Whether `a == b`, in time that depends only on the lengths.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    ring::constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::H256;
+
+    #[test]
+    fn constant_time_comparisons() {
+        assert!(constant_time_eq(b"tag", b"tag"));
+        assert!(!constant_time_eq(b"tag", b"tab"));
+        assert!(!constant_time_eq(b"tag", b"tags"));
+        assert!(constant_time_eq(b"", b""));
+
+        let a: H256 = [1u8; 32].into();
+        let mut b: H256 = [1u8; 32].into();
+        assert_eq!(a, b);
+        b.zeroize();
+        assert_ne!(a, b);
+        assert_eq!(b, H256::default());
+    }
+
+    #[test]
+    fn zeroizing_buffers_wipe() {
+        let mut buffer = Zeroizing::new(vec![0xaau8; 16]);
+        buffer.zeroize();
+        assert!(buffer.is_empty());
+        let mut array = Zeroizing::new([0x55u8; 32]);
+        array.zeroize();
+        assert_eq!(*array, [0u8; 32]);
+    }
+}
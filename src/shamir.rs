@@ -0,0 +1,442 @@
+//! Shamir secret sharing over GF(256), for k-of-n backups of seeds.
+//!
+//! Every byte of the secret is the constant term of its own random polynomial
+//! of degree k - 1; share `x` holds the evaluations at `x` (1..=255).
+//!
+//! Encoded share layout:
+//!
+//! ```notrust
+//!   version(1) | set id(16) | threshold(1) | x(1) | len(4, BE) | data(len) | checksum(32)
+//!   checksum = Hasher(RingSHA256)("crypto_lib/shamir/v1" || everything before it)
+//! ```
+//!
+//! The checksum catches corrupted or mistyped shares, the set id stops shares
+//! from different splits being mixed. Neither says anything about the secret.
+use crate::hash::{Hasher, HasherType, H256};
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::secret::{Zeroize, Zeroizing};
+
+const VERSION: u8 = 1;
+const DOMAIN: &[u8] = b"crypto_lib/shamir/v1";
+const HEADER_LEN: usize = 1 + 16 + 1 + 1 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShamirError {
+    /// need 2 <= threshold <= shares <= 255
+    InvalidParameters,
+    EmptySecret,
+    NotEnoughShares {
+        have: usize,
+        need: usize,
+    },
+    /// checksum mismatch, the share is corrupted
+    CorruptShare(u8),
+    DuplicateShare(u8),
+    /// shares come from different splits
+    MismatchedShares,
+    InvalidEncoding,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShamirError::InvalidParameters => write!(f, "need 2 <= threshold <= shares <= 255"),
+            ShamirError::EmptySecret => write!(f, "secret is empty"),
+            ShamirError::NotEnoughShares { have, need } => {
+                write!(f, "{} shares given, {} needed", have, need)
+            }
+            ShamirError::CorruptShare(x) => write!(f, "share {} failed its integrity check", x),
+            ShamirError::DuplicateShare(x) => write!(f, "share {} given twice", x),
+            ShamirError::MismatchedShares => write!(f, "shares belong to different secrets"),
+            ShamirError::InvalidEncoding => write!(f, "invalid share encoding"),
+            ShamirError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShamirError {}
+
+impl From<RandomError> for ShamirError {
+    fn from(e: RandomError) -> Self {
+        ShamirError::Random(e)
+    }
+}
+
+/// One share of a split secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    set_id: [u8; 16],
+    threshold: u8,
+    x: u8,
+    data: Vec<u8>,
+    checksum: H256,
+}
+
+impl Share {
+    /// The share identifier, i.e. the x coordinate.
+    pub fn id(&self) -> u8 {
+        self.x
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn set_id(&self) -> &[u8; 16] {
+        &self.set_id
+    }
+
+    /// Whether the checksum still matches the contents.
+    pub fn is_intact(&self) -> bool {
+        self.checksum == checksum(&self.header_and_data())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header_and_data();
+        out.extend_from_slice(self.checksum.as_ref());
+        out
+    }
+
+    /// Decode a share, rejecting it if the checksum doesn't match.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Share, ShamirError> {
+        if bytes.len() < HEADER_LEN + 32 || bytes[0] != VERSION {
+            return Err(ShamirError::InvalidEncoding);
+        }
+        let mut set_id = [0u8; 16];
+        set_id.copy_from_slice(&bytes[1..17]);
+        let threshold = bytes[17];
+        let x = bytes[18];
+        let mut len_bytes = [0u8; 4];
+        len_bytes.copy_from_slice(&bytes[19..23]);
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        if bytes.len() != HEADER_LEN + len + 32 || x == 0 || threshold < 2 {
+            return Err(ShamirError::InvalidEncoding);
+        }
+        let mut checksum_bytes = [0u8; 32];
+        checksum_bytes.copy_from_slice(&bytes[HEADER_LEN + len..]);
+        let share = Share {
+            set_id,
+            threshold,
+            x,
+            data: bytes[HEADER_LEN..HEADER_LEN + len].to_vec(),
+            checksum: checksum_bytes.into(),
+        };
+        if !share.is_intact() {
+            return Err(ShamirError::CorruptShare(x));
+        }
+        Ok(share)
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(hex_str: &str) -> Result<Share, ShamirError> {
+        let bytes = hex::decode(hex_str.trim()).map_err(|_| ShamirError::InvalidEncoding)?;
+        Share::from_bytes(&bytes)
+    }
+
+    fn new(set_id: [u8; 16], threshold: u8, x: u8, data: Vec<u8>) -> Share {
+        let mut share = Share {
+            set_id,
+            threshold,
+            x,
+            data,
+            checksum: H256::default(),
+        };
+        share.checksum = checksum(&share.header_and_data());
+        share
+    }
+
+    fn header_and_data(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.data.len());
+        out.push(VERSION);
+        out.extend_from_slice(&self.set_id);
+        out.push(self.threshold);
+        out.push(self.x);
+        out.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Share {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // share data is secret material, leave it out
+        f.debug_struct("Share")
+            .field("set_id", &hex::encode(self.set_id))
+            .field("threshold", &self.threshold)
+            .field("x", &self.x)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+/// Split `secret` into `shares` shares, any `threshold` of which recover it.
+pub fn split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>, ShamirError> {
+    split_with_rng(secret, threshold, shares, &SystemRandom::new())
+}
+
+/// `split` with the set id and polynomial coefficients drawn from `rng`.
+pub fn split_with_rng(
+    secret: &[u8],
+    threshold: u8,
+    shares: u8,
+    rng: &dyn SecureRandom,
+) -> Result<Vec<Share>, ShamirError> {
+    if threshold < 2 || threshold > shares {
+        return Err(ShamirError::InvalidParameters);
+    }
+    if secret.is_empty() {
+        return Err(ShamirError::EmptySecret);
+    }
+    let mut set_id = [0u8; 16];
+    rng.fill(&mut set_id)?;
+
+    let mut data = vec![Vec::with_capacity(secret.len()); shares as usize];
+    // coefficients[0] is the secret byte, the rest are random
+    let mut coefficients = vec![0u8; threshold as usize];
+    for &byte in secret.iter() {
+        coefficients[0] = byte;
+        if let Err(e) = rng.fill(&mut coefficients[1..]) {
+            coefficients.zeroize();
+            return Err(e.into());
+        }
+        for (i, share_data) in data.iter_mut().enumerate() {
+            share_data.push(evaluate(&coefficients, i as u8 + 1));
+        }
+    }
+    coefficients.zeroize();
+    Ok(data
+        .into_iter()
+        .enumerate()
+        .map(|(i, d)| Share::new(set_id, threshold, i as u8 + 1, d))
+        .collect())
+}
+
+/// Recover the secret from at least `threshold` shares of the same split.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>, ShamirError> {
+    let first = shares
+        .first()
+        .ok_or(ShamirError::NotEnoughShares { have: 0, need: 2 })?;
+    for (i, share) in shares.iter().enumerate() {
+        if !share.is_intact() {
+            return Err(ShamirError::CorruptShare(share.x));
+        }
+        if share.set_id != first.set_id
+            || share.threshold != first.threshold
+            || share.data.len() != first.data.len()
+        {
+            return Err(ShamirError::MismatchedShares);
+        }
+        if shares[..i].iter().any(|s| s.x == share.x) {
+            return Err(ShamirError::DuplicateShare(share.x));
+        }
+    }
+    let need = first.threshold as usize;
+    if shares.len() < need {
+        return Err(ShamirError::NotEnoughShares {
+            have: shares.len(),
+            need,
+        });
+    }
+    let shares = &shares[..need];
+    let xs: Vec<u8> = shares.iter().map(|s| s.x).collect();
+    let weights: Vec<u8> = (0..need).map(|i| lagrange_at_zero(&xs, i)).collect();
+    Ok(Zeroizing::new(
+        (0..first.data.len())
+            .map(|b| {
+                shares
+                    .iter()
+                    .zip(weights.iter())
+                    .fold(0u8, |acc, (share, &w)| acc ^ gf_mul(share.data[b], w))
+            })
+            .collect(),
+    ))
+}
+
+fn checksum(bytes: &[u8]) -> H256 {
+    let mut hasher = Hasher::new(&HasherType::RingSHA256);
+    hasher.update(DOMAIN);
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+// Horner's rule, coefficients in increasing degree
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |acc, &c| gf_mul(acc, x) ^ c)
+}
+
+// l_i(0) = prod_{j != i} x_j / (x_j - x_i); subtraction is xor in GF(2^8)
+fn lagrange_at_zero(xs: &[u8], i: usize) -> u8 {
+    let mut numerator = 1u8;
+    let mut denominator = 1u8;
+    for (j, &xj) in xs.iter().enumerate() {
+        if j != i {
+            numerator = gf_mul(numerator, xj);
+            denominator = gf_mul(denominator, xj ^ xs[i]);
+        }
+    }
+    gf_mul(numerator, gf_inv(denominator))
+}
+
+// multiplication modulo x^8 + x^4 + x^3 + x + 1 without data dependent branches or tables
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 for a != 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_arithmetic() {
+        // FIPS-197 section 4.2: {57} * {83} = {c1}
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn any_k_shares_reconstruct() {
+        let secret = b"correct horse battery staple, but longer than one block".to_vec();
+        let shares = split(&secret, 3, 5).unwrap();
+        for a in 0..5 {
+            for b in 0..5 {
+                for c in 0..5 {
+                    if a == b || b == c || a == c {
+                        continue;
+                    }
+                    let subset = vec![shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    assert_eq!(*combine(&subset).unwrap(), secret);
+                }
+            }
+        }
+        assert_eq!(*combine(&shares).unwrap(), secret);
+    }
+
+    #[test]
+    fn k_minus_one_shares_fit_any_secret() {
+        // with k - 1 shares every candidate secret is equally consistent: for any guess
+        // there is a k-th share, at an unused x, that completes it to that guess
+        let shares = split(b"\x00\x01\x02\x03", 3, 5).unwrap();
+        let known = &shares[..2];
+        for guess in [b"\xff\xfe\xfd\xfc", b"\x00\x01\x02\x03", b"zzzz"].iter() {
+            let forged_x = 5u8;
+            let xs = [0u8, known[0].x, known[1].x];
+            let data: Vec<u8> = (0..4)
+                .map(|b| {
+                    let ys = [guess[b], known[0].data[b], known[1].data[b]];
+                    // interpolate through (0, guess), (x1, y1), (x2, y2) and evaluate at forged_x
+                    (0..3).fold(0u8, |acc, i| {
+                        let mut weight = 1u8;
+                        for j in 0..3 {
+                            if j != i {
+                                weight =
+                                    gf_mul(weight, gf_mul(forged_x ^ xs[j], gf_inv(xs[i] ^ xs[j])));
+                            }
+                        }
+                        acc ^ gf_mul(ys[i], weight)
+                    })
+                })
+                .collect();
+            let forged = Share::new(known[0].set_id, 3, forged_x, data);
+            let subset = vec![known[0].clone(), known[1].clone(), forged];
+            assert_eq!(*combine(&subset).unwrap(), guess.to_vec());
+        }
+        assert_eq!(
+            combine(known),
+            Err(ShamirError::NotEnoughShares { have: 2, need: 3 })
+        );
+    }
+
+    #[test]
+    fn encoding_and_integrity() {
+        let secret = [7u8; 32];
+        let shares = split(&secret, 2, 3).unwrap();
+        let decoded: Vec<Share> = shares
+            .iter()
+            .map(|s| Share::from_hex(&s.to_hex()).unwrap())
+            .collect();
+        assert_eq!(decoded, shares);
+        assert_eq!(*combine(&decoded[1..]).unwrap(), secret.to_vec());
+
+        let mut bytes = shares[0].to_bytes();
+        bytes[HEADER_LEN + 3] ^= 0x40;
+        assert_eq!(Share::from_bytes(&bytes), Err(ShamirError::CorruptShare(1)));
+        assert_eq!(
+            Share::from_bytes(&bytes[..10]),
+            Err(ShamirError::InvalidEncoding)
+        );
+        assert!(!format!("{:?}", shares[0]).contains(&hex::encode(&shares[0].data)));
+    }
+
+    #[test]
+    fn rejects_bad_combinations() {
+        let a = split(b"secret", 2, 3).unwrap();
+        let b = split(b"secret", 2, 3).unwrap();
+        assert_eq!(
+            combine(&[a[0].clone(), b[1].clone()]),
+            Err(ShamirError::MismatchedShares)
+        );
+        assert_eq!(
+            combine(&[a[0].clone(), a[0].clone()]),
+            Err(ShamirError::DuplicateShare(1))
+        );
+        let mut corrupted = a[1].clone();
+        corrupted.data[0] ^= 1;
+        assert_eq!(
+            combine(&[a[0].clone(), corrupted]),
+            Err(ShamirError::CorruptShare(2))
+        );
+        assert_eq!(split(b"s", 1, 3), Err(ShamirError::InvalidParameters));
+        assert_eq!(split(b"s", 4, 3), Err(ShamirError::InvalidParameters));
+        assert_eq!(split(b"", 2, 3), Err(ShamirError::EmptySecret));
+    }
+
+    #[test]
+    fn injected_rng() {
+        use crate::rng::{tests::FailingRng, RandomError, TestRng};
+        let a = split_with_rng(b"secret", 2, 3, &TestRng::new(b"shamir")).unwrap();
+        let b = split_with_rng(b"secret", 2, 3, &TestRng::new(b"shamir")).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(&combine(&a[1..]).unwrap()[..], b"secret");
+        assert_eq!(
+            split_with_rng(b"secret", 2, 3, &FailingRng),
+            Err(ShamirError::Random(RandomError))
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! SLIP-0010 hierarchical deterministic Ed25519 keys.
+//!
+//! ```notrust
+//!   seed --HMAC-SHA512("ed25519 seed")--> IL || IR = key || chain code     (m)
+//!   (key, chain) --HMAC-SHA512(chain, 0x00 || key || ser32(i))--> child   (m/i')
+//! ```
+//!
+//! Ed25519 only supports hardened children, so every path component must be
+//! hardened (`m/44'/0'/1'`). Indexes given without the `'` are rejected rather
+//! than silently hardened.
+use crate::key_pair::KeyPair;
+use crate::secret::Zeroize;
+use ring::hmac;
+use ring::signature::Ed25519KeyPair;
+use std::str::FromStr;
+
+/// Offset added to hardened indexes, `i' = i + HARDENED`.
+pub const HARDENED: u32 = 0x8000_0000;
+const MASTER_HMAC_KEY: &[u8] = b"ed25519 seed";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slip10Error {
+    /// seeds must be 16 to 64 bytes
+    InvalidSeedLength,
+    /// ed25519 has no public derivation
+    NonHardenedIndex(u32),
+    InvalidPath(String),
+}
+
+impl std::fmt::Display for Slip10Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Slip10Error::InvalidSeedLength => write!(f, "seed must be 16 to 64 bytes"),
+            Slip10Error::NonHardenedIndex(i) => {
+                write!(
+                    f,
+                    "index {} is not hardened, ed25519 only supports hardened derivation",
+                    i
+                )
+            }
+            Slip10Error::InvalidPath(p) => write!(f, "invalid derivation path {}", p),
+        }
+    }
+}
+
+impl std::error::Error for Slip10Error {}
+
+/// A derivation path such as `m/44'/0'/1'`. Indexes are stored with the hardened bit set.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Build a path from unhardened index numbers, hardening each one.
+    pub fn hardened(indexes: &[u32]) -> Result<DerivationPath, Slip10Error> {
+        indexes
+            .iter()
+            .map(|&i| {
+                if i >= HARDENED {
+                    Err(Slip10Error::InvalidPath(format!(
+                        "index {} out of range",
+                        i
+                    )))
+                } else {
+                    Ok(i + HARDENED)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(DerivationPath)
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Slip10Error;
+
+    fn from_str(path: &str) -> Result<DerivationPath, Slip10Error> {
+        let invalid = || Slip10Error::InvalidPath(path.to_string());
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+        let mut indexes = vec![];
+        for part in parts {
+            let number = part
+                .strip_suffix('\'')
+                .or_else(|| part.strip_suffix('h'))
+                .ok_or_else(|| match part.parse::<u32>() {
+                    Ok(i) => Slip10Error::NonHardenedIndex(i),
+                    Err(_) => invalid(),
+                })?;
+            let index: u32 = number.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid());
+            }
+            indexes.push(index + HARDENED);
+        }
+        Ok(DerivationPath(indexes))
+    }
+}
+
+impl std::fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "m")?;
+        for index in self.0.iter() {
+            write!(f, "/{}'", index - HARDENED)?;
+        }
+        Ok(())
+    }
+}
+
+/// A node in the derivation tree: a private key plus its chain code.
+#[derive(Clone)]
+pub struct ExtendedKey {
+    pub depth: u8,
+    /// index this key was derived with, hardened bit included; 0 for the master key
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    key: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// The master key for `seed` (16 to 64 bytes, e.g. a BIP-39 seed).
+    pub fn master(seed: &[u8]) -> Result<ExtendedKey, Slip10Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Slip10Error::InvalidSeedLength);
+        }
+        let hmac_key = hmac::Key::new(hmac::HMAC_SHA512, MASTER_HMAC_KEY);
+        Ok(ExtendedKey::from_hmac(
+            hmac::sign(&hmac_key, seed).as_ref(),
+            0,
+            0,
+        ))
+    }
+
+    /// Derive the hardened child `index`, which must already include `HARDENED`.
+    pub fn derive_child(&self, index: u32) -> Result<ExtendedKey, Slip10Error> {
+        if index < HARDENED {
+            return Err(Slip10Error::NonHardenedIndex(index));
+        }
+        let hmac_key = hmac::Key::new(hmac::HMAC_SHA512, &self.chain_code);
+        let mut ctx = hmac::Context::with_key(&hmac_key);
+        ctx.update(&[0]);
+        ctx.update(&self.key);
+        ctx.update(&index.to_be_bytes());
+        Ok(ExtendedKey::from_hmac(
+            ctx.sign().as_ref(),
+            self.depth.wrapping_add(1),
+            index,
+        ))
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<ExtendedKey, Slip10Error> {
+        let mut node = self.clone();
+        for &index in path.indexes() {
+            node = node.derive_child(index)?;
+        }
+        Ok(node)
+    }
+
+    /// The 32-byte Ed25519 private key (seed) of this node.
+    pub fn private_key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    pub fn ed25519_key_pair(&self) -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&self.key).unwrap()
+    }
+
+    pub fn key_pair(&self) -> KeyPair {
+        KeyPair::from_seed(&self.key).unwrap()
+    }
+
+    fn from_hmac(output: &[u8], depth: u8, child_number: u32) -> ExtendedKey {
+        let mut key = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        key.copy_from_slice(&output[..32]);
+        chain_code.copy_from_slice(&output[32..]);
+        ExtendedKey {
+            depth,
+            child_number,
+            chain_code,
+            key,
+        }
+    }
+}
+
+impl std::fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ExtendedKey")
+            .field("depth", &self.depth)
+            .field("child_number", &self.child_number)
+            .field("public_key", &self.key_pair().public_key())
+            .finish()
+    }
+}
+
+impl Drop for ExtendedKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.chain_code.zeroize();
+    }
+}
+
+/// Derive the key pair at `path` (e.g. `"m/44'/0'/1'"`) from `seed`.
+pub fn derive_key_pair(seed: &[u8], path: &str) -> Result<Ed25519KeyPair, Slip10Error> {
+    let path: DerivationPath = path.parse()?;
+    Ok(ExtendedKey::master(seed)?
+        .derive_path(&path)?
+        .ed25519_key_pair())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::KeyPair as _;
+
+    // (path, chain code, private key, public key) from the SLIP-0010 ed25519 test vectors
+    fn check(seed: &[u8], vectors: &[(&str, &str, &str, &str)]) {
+        let master = ExtendedKey::master(seed).unwrap();
+        for (path, chain_code, private, public) in vectors {
+            let path: DerivationPath = path.parse().unwrap();
+            let node = master.derive_path(&path).unwrap();
+            assert_eq!(node.depth as usize, path.len());
+            assert_eq!(hex::encode(node.chain_code), *chain_code, "{}", path);
+            assert_eq!(hex::encode(node.private_key()), *private, "{}", path);
+            assert_eq!(
+                hex::encode(node.ed25519_key_pair().public_key().as_ref()),
+                *public,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_vector_1() {
+        check(
+            &hex!("000102030405060708090a0b0c0d0e0f"),
+            &[
+                (
+                    "m",
+                    "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+                    "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+                    "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed",
+                ),
+                (
+                    "m/0'",
+                    "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+                    "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+                    "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c",
+                ),
+                (
+                    "m/0'/1'",
+                    "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14",
+                    "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+                    "1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187",
+                ),
+                (
+                    "m/0'/1'/2'",
+                    "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c",
+                    "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+                    "ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1",
+                ),
+                (
+                    "m/0'/1'/2'/2'",
+                    "8f6d87f93d750e0efccda017d662a1b31a266e4a6f5993b15f5c1f07f74dd5cc",
+                    "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662",
+                    "8abae2d66361c879b900d204ad2cc4984fa2aa344dd7ddc46007329ac76c429c",
+                ),
+                (
+                    "m/0'/1'/2'/2'/1000000000'",
+                    "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230",
+                    "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+                    "3c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_vector_2() {
+        check(
+            &hex::decode(
+                "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a2\
+                 9f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+            )
+            .unwrap(),
+            &[
+                (
+                    "m",
+                    "ef70a74db9c3a5af931b5fe73ed8e1a53464133654fd55e7a66f8570b8e33c3b",
+                    "171cb88b1b3c1db25add599712e36245d75bc65a1a5c9e18d76f9f2b1eab4012",
+                    "8fe9693f8fa62a4305a140b9764c5ee01e455963744fe18204b4fb948249308a",
+                ),
+                (
+                    "m/0'",
+                    "0b78a3226f915c082bf118f83618a618ab6dec793752624cbeb622acb562862d",
+                    "1559eb2bbec5790b0c65d8693e4d0875b1747f4970ae8b650486ed7470845635",
+                    "86fab68dcb57aa196c77c5f264f215a112c22a912c10d123b0d03c3c28ef1037",
+                ),
+                (
+                    "m/0'/2147483647'",
+                    "138f0b2551bcafeca6ff2aa88ba8ed0ed8de070841f0c4ef0165df8181eaad7f",
+                    "ea4f5bfe8694d8bb74b7b59404632fd5968b774ed545e810de9c32a4fb4192f4",
+                    "5ba3b9ac6e90e83effcd25ac4e58a1365a9e35a3d3ae5eb07b9e4d90bcf7506d",
+                ),
+                (
+                    "m/0'/2147483647'/1'",
+                    "73bd9fff1cfbde33a1b846c27085f711c0fe2d66fd32e139d3ebc28e5a4a6b90",
+                    "3757c7577170179c7868353ada796c839135b3d30554bbb74a4b1e4a5a58505c",
+                    "2e66aa57069c86cc18249aecf5cb5a9cebbfd6fadeab056254763874a9352b45",
+                ),
+                (
+                    "m/0'/2147483647'/1'/2147483646'",
+                    "0902fe8a29f9140480a00ef244bd183e8a13288e4412d8389d140aac1794825a",
+                    "5837736c89570de861ebc173b1086da4f505d4adb387c6a1b1342d5e4ac9ec72",
+                    "e33c0f7d81d843c572275f287498e8d408654fdf0d1e065b84e2e6f157aab09b",
+                ),
+                (
+                    "m/0'/2147483647'/1'/2147483646'/2'",
+                    "5d70af781f3a37b829f0d060924d5e960bdc02e85423494afc0b1a41bbe196d4",
+                    "551d333177df541ad876a60ea71f00447931c0a9da16f227c11ea080d7391b8d",
+                    "47150c75db263559a70d5778bf36abbab30fb061ad69f69ece61a72b0cfa4fc0",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn path_parsing() {
+        let path: DerivationPath = "m/44'/0'/1'".parse().unwrap();
+        assert_eq!(path, DerivationPath::hardened(&[44, 0, 1]).unwrap());
+        assert_eq!(path.to_string(), "m/44'/0'/1'");
+        assert_eq!("m/44h/0h".parse::<DerivationPath>().unwrap().len(), 2);
+        assert!("m".parse::<DerivationPath>().unwrap().is_empty());
+        assert_eq!(
+            "m/44'/0".parse::<DerivationPath>(),
+            Err(Slip10Error::NonHardenedIndex(0))
+        );
+        assert!("44'/0'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648'".parse::<DerivationPath>().is_err());
+        assert!("m/x'".parse::<DerivationPath>().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_inputs() {
+        assert_eq!(
+            ExtendedKey::master(&[0u8; 15]).unwrap_err(),
+            Slip10Error::InvalidSeedLength
+        );
+        let master = ExtendedKey::master(&[7u8; 32]).unwrap();
+        assert_eq!(
+            master.derive_child(5).unwrap_err(),
+            Slip10Error::NonHardenedIndex(5)
+        );
+    }
+
+    #[test]
+    fn derived_keys_sign() {
+        let seed = [1u8; 64];
+        let key = derive_key_pair(&seed, "m/44'/0'/1'").unwrap();
+        let again = derive_key_pair(&seed, "m/44'/0'/1'").unwrap();
+        let other = derive_key_pair(&seed, "m/44'/0'/2'").unwrap();
+        assert_eq!(key.public_key().as_ref(), again.public_key().as_ref());
+        assert_ne!(key.public_key().as_ref(), other.public_key().as_ref());
+
+        let node = ExtendedKey::master(&seed)
+            .unwrap()
+            .derive_path(&"m/44'/0'/1'".parse().unwrap())
+            .unwrap();
+        let signature = node.key_pair().sign(b"device");
+        assert!(node.key_pair().public_key().verify(b"device", &signature));
+        assert!(!format!("{:?}", node).contains(&hex::encode(node.private_key())));
+    }
+}
@@ -0,0 +1,276 @@
+//! Stake-weighted cryptographic sortition from Ed25519 signatures.
+//!
+//! For a round a node signs
+//!
+//! ```notrust
+//!   message = "crypto_lib/sortition/v1" || seed (32) || round (8) || role
+//!   value   = SHA-256(signature)
+//! ```
+//!
+//! and is selected when `value < 2^256 * expected_size * stake / total_stake`,
+//! so each node is picked with probability proportional to its stake and the
+//! committee has `expected_size` members on average. The signature is the
+//! proof; anyone with the node's public key and stake can check it. The value
+//! is SHA-256, independent of the build's `HASH_TYPE`, so debug and release
+//! nodes agree on the committee.
+//!
+//! Caveat: RFC 8032 signing is deterministic, but verification can't tell how
+//! the nonce was chosen. A node willing to sign with other nonces can try many
+//! valid signatures and publish one that selects it. Use this where nodes are
+//! not expected to grind, or switch to a VRF.
+use crate::hash::{Hasher, HasherType, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+const DOMAIN: &[u8] = b"crypto_lib/sortition/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortitionError {
+    ZeroTotalStake,
+    StakeExceedsTotal,
+    InvalidSignature,
+    /// the proof is valid but its value is above the threshold
+    NotSelected,
+}
+
+impl std::fmt::Display for SortitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SortitionError::ZeroTotalStake => write!(f, "total stake is zero"),
+            SortitionError::StakeExceedsTotal => write!(f, "stake exceeds total stake"),
+            SortitionError::InvalidSignature => write!(f, "invalid sortition signature"),
+            SortitionError::NotSelected => write!(f, "node was not selected"),
+        }
+    }
+}
+
+impl std::error::Error for SortitionError {}
+
+/// A node's claim that it was selected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectionProof {
+    pub signer: PublicKey,
+    pub signature: Signature,
+}
+
+impl SelectionProof {
+    /// The pseudo random value compared against the threshold; lowest values
+    /// make a natural ordering of the committee.
+    pub fn value(&self) -> H256 {
+        let mut hasher = Hasher::new(&HasherType::RingSHA256);
+        hasher.update(self.signature.as_bytes());
+        hasher.finish()
+    }
+}
+
+/// One selection: which round, for which role, and how big the committee should be.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sortition {
+    pub seed: H256,
+    pub round: u64,
+    pub role: String,
+    pub expected_size: u64,
+    pub total_stake: u64,
+}
+
+impl Sortition {
+    pub fn new(
+        seed: H256,
+        round: u64,
+        role: &str,
+        expected_size: u64,
+        total_stake: u64,
+    ) -> Sortition {
+        Sortition {
+            seed,
+            round,
+            role: role.to_string(),
+            expected_size,
+            total_stake,
+        }
+    }
+
+    /// The bytes every node signs for this selection.
+    pub fn message(&self) -> Vec<u8> {
+        let mut message = DOMAIN.to_vec();
+        message.extend_from_slice(self.seed.as_ref());
+        message.extend_from_slice(&self.round.to_be_bytes());
+        message.extend_from_slice(self.role.as_bytes());
+        message
+    }
+
+    /// `floor(2^256 * expected_size * stake / total_stake)`, or `None` when that
+    /// reaches 2^256 and the node is always selected.
+    pub fn threshold(&self, stake: u64) -> Result<Option<H256>, SortitionError> {
+        if self.total_stake == 0 {
+            return Err(SortitionError::ZeroTotalStake);
+        }
+        if stake > self.total_stake {
+            return Err(SortitionError::StakeExceedsTotal);
+        }
+        let numerator = self.expected_size as u128 * stake as u128;
+        let denominator = self.total_stake as u128;
+        if numerator >= denominator {
+            return Ok(None);
+        }
+        // binary long division of numerator / denominator, one bit at a time;
+        // remainder < denominator < 2^64 so doubling it can't overflow
+        let mut threshold = [0u8; 32];
+        let mut remainder = numerator;
+        for bit in 0..256 {
+            remainder <<= 1;
+            if remainder >= denominator {
+                remainder -= denominator;
+                threshold[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        Ok(Some(H256::from(threshold)))
+    }
+
+    /// Sign this round and return the proof if `key` is selected with `stake`.
+    pub fn try_select(
+        &self,
+        key: &KeyPair,
+        stake: u64,
+    ) -> Result<Option<SelectionProof>, SortitionError> {
+        let proof = SelectionProof {
+            signer: key.public_key(),
+            signature: key.sign(&self.message()),
+        };
+        match self.check(&proof, stake) {
+            Ok(()) => Ok(Some(proof)),
+            Err(SortitionError::NotSelected) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check another node's proof given its stake.
+    pub fn verify(&self, proof: &SelectionProof, stake: u64) -> Result<(), SortitionError> {
+        if !proof.signer.verify(&self.message(), &proof.signature) {
+            return Err(SortitionError::InvalidSignature);
+        }
+        self.check(proof, stake)
+    }
+
+    fn check(&self, proof: &SelectionProof, stake: u64) -> Result<(), SortitionError> {
+        match self.threshold(stake)? {
+            None => Ok(()),
+            Some(threshold) if proof.value() < threshold => Ok(()),
+            Some(_) => Err(SortitionError::NotSelected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed() -> H256 {
+        let mut hasher = Hasher::new(&HasherType::RingSHA256);
+        hasher.update(b"randomness beacon output");
+        hasher.finish()
+    }
+
+    #[test]
+    fn thresholds() {
+        let sortition = Sortition::new(seed(), 1, "proposer", 1, 4);
+        let mut quarter = [0u8; 32];
+        quarter[0] = 0x40;
+        assert_eq!(sortition.threshold(1), Ok(Some(H256::from(quarter))));
+        assert_eq!(sortition.threshold(0), Ok(Some(H256::default())));
+        assert_eq!(sortition.threshold(4), Ok(None));
+        assert_eq!(
+            sortition.threshold(5),
+            Err(SortitionError::StakeExceedsTotal)
+        );
+
+        // 1/3 = 0.010101... in binary
+        let third = Sortition::new(seed(), 1, "proposer", 1, 3)
+            .threshold(1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(third, H256::from([0x55u8; 32]));
+        assert_eq!(
+            Sortition::new(seed(), 1, "x", 1, 0).threshold(0),
+            Err(SortitionError::ZeroTotalStake)
+        );
+    }
+
+    #[test]
+    fn value_vector() {
+        let bytes: Vec<u8> = (0..64).collect();
+        let proof = SelectionProof {
+            signer: KeyPair::generate().public_key(),
+            signature: Signature::from_bytes(&bytes).unwrap(),
+        };
+        // SHA-256 of the bytes 0x00..0x3f, the same in every build profile
+        assert_eq!(
+            proof.value(),
+            H256::from(hex!(
+                "fdeab9acf3710362bd2658cdc9a29e8f9c757fcf9811603a8c447cd1d9151108"
+            ))
+        );
+    }
+
+    #[test]
+    fn proofs_verify() {
+        let sortition = Sortition::new(seed(), 7, "committee", 1, 1);
+        let key = KeyPair::generate();
+        // full stake with expected size 1 always selects
+        let proof = sortition.try_select(&key, 1).unwrap().unwrap();
+        assert_eq!(sortition.verify(&proof, 1), Ok(()));
+        // deterministic signatures give the same proof every time
+        assert_eq!(sortition.try_select(&key, 1).unwrap(), Some(proof.clone()));
+
+        let next_round = Sortition::new(seed(), 8, "committee", 1, 1);
+        assert_eq!(
+            next_round.verify(&proof, 1),
+            Err(SortitionError::InvalidSignature)
+        );
+        let other_role = Sortition::new(seed(), 7, "proposer", 1, 1);
+        assert_eq!(
+            other_role.verify(&proof, 1),
+            Err(SortitionError::InvalidSignature)
+        );
+
+        let mut forged = proof;
+        forged.signer = KeyPair::generate().public_key();
+        assert_eq!(
+            sortition.verify(&forged, 1),
+            Err(SortitionError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn selection_follows_stake() {
+        let keys: Vec<KeyPair> = (0..200).map(|_| KeyPair::generate()).collect();
+        // 200 nodes with stake 1, committee of 20 expected
+        let sortition = Sortition::new(seed(), 3, "committee", 20, 200);
+        let mut selected = 0;
+        for key in keys.iter() {
+            match sortition.try_select(key, 1).unwrap() {
+                Some(proof) => {
+                    assert_eq!(sortition.verify(&proof, 1), Ok(()));
+                    selected += 1;
+                }
+                None => {
+                    let proof = SelectionProof {
+                        signer: key.public_key(),
+                        signature: key.sign(&sortition.message()),
+                    };
+                    assert_eq!(
+                        sortition.verify(&proof, 1),
+                        Err(SortitionError::NotSelected)
+                    );
+                    // zero stake is never selected
+                    assert_eq!(
+                        sortition.verify(&proof, 0),
+                        Err(SortitionError::NotSelected)
+                    );
+                }
+            }
+        }
+        // binomial(200, 0.1): mean 20, standard deviation ~4.2
+        assert!(selected > 3 && selected < 45, "selected {}", selected);
+    }
+}
@@ -0,0 +1,446 @@
+//! OpenTimestamps style aggregation of document digests under one attested root.
+//!
+//! ```notrust
+//!   leaf i    = HASH_TYPE(HASH_TYPE(digest_i || nonce_i))
+//!   root      = MerkleTree over the leaves
+//!   attest    = Attestor::attest(root), e.g. Ed25519("crypto_lib/timestamp/attest/v1"
+//!                                                   || root || time (8, BE))
+//!   stamp i   = digest_i, ops_i, attestation
+//!
+//!   ops_i     = append(nonce_i), hash, hash                (leaf)
+//!               append(sibling) | prepend(sibling), hash  (once per level)
+//! ```
+//!
+//! A stamp is verified by replaying its ops from the document digest: the
+//! result must be the 32 byte root that the attestation commits to. The fresh
+//! nonce per document keeps siblings in other proofs from revealing digests.
+//! How a root gets anchored is left to the `Attestor` and
+//! `AttestationVerifier` traits; `KeyAttestor` signs roots with a local key.
+//! The `hash` op and the tree follow the crate wide `HASH_TYPE`, so stamp and
+//! verify with the same build profile.
+use crate::hash::{Hasher, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use crate::merkle_tree::merkle_tree::MerkleTree;
+use crate::rng::{RandomError, SecureRandom, SystemRandom};
+use crate::HASH_TYPE;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ATTEST_TAG: &[u8] = b"crypto_lib/timestamp/attest/v1";
+const NONCE_LEN: usize = 16;
+// bounds replay work on untrusted stamps
+const MAX_OPS: usize = 256;
+const MAX_MESSAGE_LEN: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampError {
+    EmptyBatch,
+    /// the stamp has more ops than a verifier will replay
+    TooManyOps,
+    /// an op grew the message past the replay limit
+    MessageTooLong,
+    /// the stamp is for another document
+    DigestMismatch,
+    /// replaying the ops doesn't end in a 32 byte root
+    InvalidRoot,
+    /// the attestation doesn't commit to the replayed root
+    InvalidAttestation,
+    Random(RandomError),
+}
+
+impl std::fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimestampError::EmptyBatch => write!(f, "no digests to timestamp"),
+            TimestampError::TooManyOps => write!(f, "too many timestamp ops"),
+            TimestampError::MessageTooLong => write!(f, "timestamp message too long"),
+            TimestampError::DigestMismatch => write!(f, "timestamp is for another digest"),
+            TimestampError::InvalidRoot => write!(f, "timestamp ops don't end in a root"),
+            TimestampError::InvalidAttestation => write!(f, "invalid timestamp attestation"),
+            TimestampError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+impl From<RandomError> for TimestampError {
+    fn from(e: RandomError) -> Self {
+        TimestampError::Random(e)
+    }
+}
+
+/// The digest of a document to timestamp.
+pub fn hash_document(document: &[u8]) -> H256 {
+    let mut hasher = Hasher::new(&HASH_TYPE);
+    hasher.update(document);
+    hasher.finish()
+}
+
+/// One step from a document digest towards the attested root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    /// message || bytes
+    Append(Vec<u8>),
+    /// bytes || message
+    Prepend(Vec<u8>),
+    /// HASH_TYPE(message)
+    Hash,
+}
+
+/// Replay `ops` starting from `digest`, returning the final message.
+pub fn evaluate(digest: &H256, ops: &[Op]) -> Result<Vec<u8>, TimestampError> {
+    if ops.len() > MAX_OPS {
+        return Err(TimestampError::TooManyOps);
+    }
+    let mut message = digest.as_ref().to_vec();
+    for op in ops {
+        match op {
+            Op::Append(bytes) => {
+                if message.len() + bytes.len() > MAX_MESSAGE_LEN {
+                    return Err(TimestampError::MessageTooLong);
+                }
+                message.extend_from_slice(bytes);
+            }
+            Op::Prepend(bytes) => {
+                if message.len() + bytes.len() > MAX_MESSAGE_LEN {
+                    return Err(TimestampError::MessageTooLong);
+                }
+                let mut prefixed = bytes.clone();
+                prefixed.extend_from_slice(&message);
+                message = prefixed;
+            }
+            Op::Hash => message = hash_document(&message).as_ref().to_vec(),
+        }
+    }
+    Ok(message)
+}
+
+/// Evidence that a root existed at `time`, in an anchor specific format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// seconds since the Unix epoch
+    pub time: u64,
+    pub data: Vec<u8>,
+}
+
+/// Anchors an aggregated root, e.g. in a signed log or a blockchain.
+pub trait Attestor {
+    fn attest(&self, root: &H256) -> Result<Attestation, TimestampError>;
+}
+
+/// Checks an attestation made by the matching `Attestor`.
+pub trait AttestationVerifier {
+    fn check(&self, root: &H256, attestation: &Attestation) -> Result<(), TimestampError>;
+}
+
+fn attestation_payload(root: &H256, time: u64) -> Vec<u8> {
+    let mut payload = ATTEST_TAG.to_vec();
+    payload.extend_from_slice(root.as_ref());
+    payload.extend_from_slice(&time.to_be_bytes());
+    payload
+}
+
+/// A local attestor that signs roots with an Ed25519 key; its public key is
+/// the matching verifier.
+#[derive(Debug)]
+pub struct KeyAttestor {
+    key: KeyPair,
+}
+
+impl KeyAttestor {
+    pub fn new(key: KeyPair) -> KeyAttestor {
+        KeyAttestor { key }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+
+    /// Attest `root` at an explicit time instead of the system clock.
+    pub fn attest_at(&self, root: &H256, time: u64) -> Attestation {
+        let signature = self.key.sign(&attestation_payload(root, time));
+        Attestation {
+            time,
+            data: signature.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl Attestor for KeyAttestor {
+    fn attest(&self, root: &H256) -> Result<Attestation, TimestampError> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(self.attest_at(root, time))
+    }
+}
+
+impl AttestationVerifier for PublicKey {
+    fn check(&self, root: &H256, attestation: &Attestation) -> Result<(), TimestampError> {
+        let signature = Signature::from_bytes(&attestation.data)
+            .map_err(|_| TimestampError::InvalidAttestation)?;
+        if self.verify(&attestation_payload(root, attestation.time), &signature) {
+            Ok(())
+        } else {
+            Err(TimestampError::InvalidAttestation)
+        }
+    }
+}
+
+/// A timestamp proof for one document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timestamp {
+    pub digest: H256,
+    pub ops: Vec<Op>,
+    pub attestation: Attestation,
+}
+
+impl Timestamp {
+    /// The root the ops commit the digest to.
+    pub fn root(&self) -> Result<H256, TimestampError> {
+        let message = evaluate(&self.digest, &self.ops)?;
+        if message.len() != 32 {
+            return Err(TimestampError::InvalidRoot);
+        }
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&message);
+        Ok(H256::from(root))
+    }
+
+    /// Check that `digest` existed at the attested time, which is returned.
+    pub fn verify(
+        &self,
+        digest: &H256,
+        verifier: &dyn AttestationVerifier,
+    ) -> Result<u64, TimestampError> {
+        if self.digest != *digest {
+            return Err(TimestampError::DigestMismatch);
+        }
+        verifier.check(&self.root()?, &self.attestation)?;
+        Ok(self.attestation.time)
+    }
+}
+
+/// Collects digests and stamps them together under one attestation.
+#[derive(Debug, Default)]
+pub struct Aggregator {
+    pending: Vec<(H256, [u8; NONCE_LEN])>,
+}
+
+impl Aggregator {
+    pub fn new() -> Aggregator {
+        Aggregator::default()
+    }
+
+    /// Queue a digest, returning its position in the next batch.
+    pub fn submit(&mut self, digest: H256) -> Result<usize, TimestampError> {
+        self.submit_with_rng(digest, &SystemRandom::new())
+    }
+
+    pub fn submit_with_rng(
+        &mut self,
+        digest: H256,
+        rng: &dyn SecureRandom,
+    ) -> Result<usize, TimestampError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut nonce)?;
+        self.pending.push((digest, nonce));
+        Ok(self.pending.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Attest the root over every queued digest and return one stamp per
+    /// digest, in submission order. The queue is only cleared on success.
+    pub fn stamp(&mut self, attestor: &dyn Attestor) -> Result<Vec<Timestamp>, TimestampError> {
+        if self.pending.is_empty() {
+            return Err(TimestampError::EmptyBatch);
+        }
+        let leaves: Vec<H256> = self
+            .pending
+            .iter()
+            .map(|(digest, nonce)| {
+                let mut hasher = Hasher::new(&HASH_TYPE);
+                hasher.update(digest.as_ref());
+                hasher.update(nonce);
+                hasher.finish()
+            })
+            .collect();
+        let tree = MerkleTree::new(&leaves);
+        let attestation = attestor.attest(&tree.root())?;
+
+        let stamps = self
+            .pending
+            .drain(..)
+            .enumerate()
+            .map(|(index, (digest, nonce))| {
+                let mut ops = vec![Op::Append(nonce.to_vec()), Op::Hash, Op::Hash];
+                // the proof runs from the root down, ops from the leaf up
+                let mut position = index;
+                for sibling in tree.proof(index).iter().rev() {
+                    if position % 2 == 0 {
+                        ops.push(Op::Append(sibling.as_ref().to_vec()));
+                    } else {
+                        ops.push(Op::Prepend(sibling.as_ref().to_vec()));
+                    }
+                    ops.push(Op::Hash);
+                    position /= 2;
+                }
+                Timestamp {
+                    digest,
+                    ops,
+                    attestation: attestation.clone(),
+                }
+            })
+            .collect();
+        Ok(stamps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::{FailingRng, FixedRng};
+
+    fn attestor() -> KeyAttestor {
+        KeyAttestor::new(KeyPair::from_seed(&[7u8; 32]).unwrap())
+    }
+
+    #[test]
+    fn every_stamp_verifies() {
+        let attestor = attestor();
+        for count in 1..=9 {
+            let mut aggregator = Aggregator::new();
+            let digests: Vec<H256> = (0..count)
+                .map(|i| hash_document(format!("document {}", i).as_bytes()))
+                .collect();
+            for (i, digest) in digests.iter().enumerate() {
+                assert_eq!(aggregator.submit(*digest).unwrap(), i);
+            }
+            let stamps = aggregator.stamp(&attestor).unwrap();
+            assert!(aggregator.is_empty());
+            assert_eq!(stamps.len(), count);
+
+            let root = stamps[0].root().unwrap();
+            for (stamp, digest) in stamps.iter().zip(digests.iter()) {
+                assert_eq!(stamp.root().unwrap(), root);
+                assert_eq!(
+                    stamp.verify(digest, &attestor.public_key()).unwrap(),
+                    stamp.attestation.time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_stamps_are_rejected() {
+        let attestor = attestor();
+        let mut aggregator = Aggregator::new();
+        let digest = hash_document(b"contract.pdf");
+        aggregator.submit(digest).unwrap();
+        aggregator.submit(hash_document(b"invoice.pdf")).unwrap();
+        let stamp = aggregator.stamp(&attestor).unwrap().remove(0);
+        let key = attestor.public_key();
+
+        assert_eq!(
+            stamp.verify(&hash_document(b"forged.pdf"), &key),
+            Err(TimestampError::DigestMismatch)
+        );
+
+        let mut other_ops = stamp.clone();
+        other_ops.ops[0] = Op::Append(vec![0u8; NONCE_LEN]);
+        assert_eq!(
+            other_ops.verify(&digest, &key),
+            Err(TimestampError::InvalidAttestation)
+        );
+
+        let mut later = stamp.clone();
+        later.attestation.time += 1;
+        assert_eq!(
+            later.verify(&digest, &key),
+            Err(TimestampError::InvalidAttestation)
+        );
+
+        let mut unhashed = stamp.clone();
+        unhashed.ops.push(Op::Append(vec![1]));
+        assert_eq!(
+            unhashed.verify(&digest, &key),
+            Err(TimestampError::InvalidRoot)
+        );
+
+        let stranger = KeyPair::from_seed(&[8u8; 32]).unwrap().public_key();
+        assert_eq!(
+            stamp.verify(&digest, &stranger),
+            Err(TimestampError::InvalidAttestation)
+        );
+        assert!(stamp.verify(&digest, &key).is_ok());
+    }
+
+    #[test]
+    fn replay_limits() {
+        let digest = hash_document(b"doc");
+        assert_eq!(
+            evaluate(&digest, &vec![Op::Hash; MAX_OPS + 1]),
+            Err(TimestampError::TooManyOps)
+        );
+        assert_eq!(
+            evaluate(&digest, &[Op::Prepend(vec![0u8; MAX_MESSAGE_LEN])]),
+            Err(TimestampError::MessageTooLong)
+        );
+        assert_eq!(
+            evaluate(&digest, &[Op::Prepend(vec![1]), Op::Append(vec![2])]).unwrap(),
+            [&[1u8][..], digest.as_ref(), &[2u8][..]].concat()
+        );
+    }
+
+    #[test]
+    fn stamps_are_reproducible_with_injected_rng() {
+        let attestor = attestor();
+        let digest = hash_document(b"doc");
+        let stamp_with = |rng: &dyn SecureRandom| {
+            let mut aggregator = Aggregator::new();
+            aggregator.submit_with_rng(digest, rng).unwrap();
+            let root = aggregator.stamp(&attestor).unwrap().remove(0).root();
+            root.unwrap()
+        };
+        assert_eq!(
+            stamp_with(&FixedRng(vec![1])),
+            stamp_with(&FixedRng(vec![1]))
+        );
+        assert_ne!(
+            stamp_with(&FixedRng(vec![1])),
+            stamp_with(&FixedRng(vec![2]))
+        );
+
+        let mut aggregator = Aggregator::new();
+        assert_eq!(
+            aggregator.submit_with_rng(digest, &FailingRng),
+            Err(TimestampError::Random(RandomError))
+        );
+        assert_eq!(aggregator.stamp(&attestor), Err(TimestampError::EmptyBatch));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let attestor = attestor();
+        let mut aggregator = Aggregator::new();
+        aggregator.submit(hash_document(b"a")).unwrap();
+        aggregator.submit(hash_document(b"b")).unwrap();
+        aggregator.submit(hash_document(b"c")).unwrap();
+        let stamp = aggregator.stamp(&attestor).unwrap().remove(2);
+        let bytes = bincode::serialize(&stamp).unwrap();
+        let decoded: Timestamp = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, stamp);
+        assert!(decoded
+            .verify(&hash_document(b"c"), &attestor.public_key())
+            .is_ok());
+    }
+}
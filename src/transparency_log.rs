@@ -0,0 +1,619 @@
+//! An append-only transparency log on top of the `MMR`.
+//!
+//! ```notrust
+//!   entry hash   = HASH_TYPE("crypto_lib/tlog/entry/v1" || entry), the MMR leaf is its hash
+//!   tree head    = tree size (entries) || root || timestamp
+//!   signature    = Ed25519("crypto_lib/tlog/sth/v1" || tree size (8, BE) || root || timestamp (8, BE))
+//! ```
+//!
+//! The log serves inclusion proofs (an MMR path from an entry to the root) and
+//! consistency proofs between an older size and the current one. A consistency
+//! proof lists the peaks of the old MMR, which bag to the old root, each with
+//! an MMR path into the new root; since the MMR only appends, a log that
+//! rewrote history can't produce one.
+//!
+//! `LogVerifier` is the client side: it remembers every head it has seen and
+//! reports a split view when the log signs two different roots for the same
+//! size. Hashes follow the crate wide `HASH_TYPE`, so the log and its clients
+//! have to use the same build profile.
+use crate::hash::{Hasher, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use crate::merkle_mountain_range::merkle_mountain_range::{MMRProof, MMR};
+use crate::HASH_TYPE;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ENTRY_TAG: &[u8] = b"crypto_lib/tlog/entry/v1";
+const HEAD_TAG: &[u8] = b"crypto_lib/tlog/sth/v1";
+// sizes and paths come from untrusted proofs; beyond these the MMR index
+// arithmetic overflows. Below MAX_TREE_SIZE no path is longer than 63.
+const MAX_TREE_SIZE: u64 = 1 << 62;
+const MAX_PATH_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogError {
+    IndexOutOfRange,
+    /// a consistency proof from a larger size, or a head older than one already seen
+    InvalidTreeSize,
+    /// the proof was made for a different tree size than the head
+    SizeMismatch,
+    InvalidSignature,
+    InvalidInclusionProof,
+    InvalidConsistencyProof,
+    /// the log signed two different roots for the same size; both heads are evidence
+    SplitView(Box<(SignedTreeHead, SignedTreeHead)>),
+}
+
+impl std::fmt::Display for LogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogError::IndexOutOfRange => write!(f, "entry index out of range"),
+            LogError::InvalidTreeSize => write!(f, "invalid tree size"),
+            LogError::SizeMismatch => write!(f, "proof and tree head sizes differ"),
+            LogError::InvalidSignature => write!(f, "invalid tree head signature"),
+            LogError::InvalidInclusionProof => write!(f, "invalid inclusion proof"),
+            LogError::InvalidConsistencyProof => write!(f, "invalid consistency proof"),
+            LogError::SplitView(heads) => write!(
+                f,
+                "split view: two different roots for tree size {}",
+                heads.0.tree_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
+
+/// The tagged hash of a log entry.
+pub fn entry_hash(entry: &[u8]) -> H256 {
+    let mut hasher = Hasher::new(&HASH_TYPE);
+    hasher.update(ENTRY_TAG);
+    hasher.update(entry);
+    hasher.finish()
+}
+
+// MMR leaves are the hash of what is inserted
+fn leaf_node(entry_hash: &H256) -> H256 {
+    let mut hasher = Hasher::new(&HASH_TYPE);
+    hasher.update(entry_hash.as_ref());
+    hasher.finish()
+}
+
+// number of MMR nodes holding `leaves` leaves, None above MAX_TREE_SIZE
+fn mmr_size(leaves: u64) -> Option<usize> {
+    if leaves > MAX_TREE_SIZE {
+        return None;
+    }
+    let size = leaves
+        .checked_mul(2)?
+        .checked_sub(leaves.count_ones() as u64)?;
+    std::convert::TryFrom::try_from(size).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A tree head signed by the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    pub tree_size: u64,
+    pub root: H256,
+    /// seconds since the Unix epoch
+    pub timestamp: u64,
+    pub signature: Signature,
+}
+
+impl SignedTreeHead {
+    fn payload(tree_size: u64, root: &H256, timestamp: u64) -> Vec<u8> {
+        let mut payload = HEAD_TAG.to_vec();
+        payload.extend_from_slice(&tree_size.to_be_bytes());
+        payload.extend_from_slice(root.as_ref());
+        payload.extend_from_slice(&timestamp.to_be_bytes());
+        payload
+    }
+
+    pub fn verify(&self, log_key: &PublicKey) -> Result<(), LogError> {
+        let payload = SignedTreeHead::payload(self.tree_size, &self.root, self.timestamp);
+        if log_key.verify(&payload, &self.signature) {
+            Ok(())
+        } else {
+            Err(LogError::InvalidSignature)
+        }
+    }
+}
+
+/// Path from one entry to the root of a tree of `tree_size` entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub path: Vec<H256>,
+}
+
+impl InclusionProof {
+    /// Check that `entry` is at `leaf_index` in the tree `head` commits to.
+    /// Only checks the path; verify the head's signature separately.
+    pub fn verify(&self, head: &SignedTreeHead, entry: &[u8]) -> Result<(), LogError> {
+        if self.tree_size != head.tree_size {
+            return Err(LogError::SizeMismatch);
+        }
+        let size = mmr_size(self.tree_size).ok_or(LogError::InvalidTreeSize)?;
+        if self.leaf_index >= self.tree_size || self.path.len() > MAX_PATH_LEN {
+            return Err(LogError::InvalidInclusionProof);
+        }
+        let proof = MMRProof::new(
+            leaf_node(&entry_hash(entry)),
+            MMR::<H256>::convert_to_mmr_idx(self.leaf_index as usize),
+            self.path.clone(),
+            size,
+            head.root,
+        );
+        if MMR::<H256>::verify_proof(&proof) {
+            Ok(())
+        } else {
+            Err(LogError::InvalidInclusionProof)
+        }
+    }
+}
+
+/// Proof that the tree of `old_size` entries is a prefix of the tree of `new_size`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyProof {
+    pub old_size: u64,
+    pub new_size: u64,
+    /// the old tree's peaks, left to right
+    pub old_peaks: Vec<H256>,
+    /// one MMR path per old peak, into the new root
+    pub paths: Vec<Vec<H256>>,
+}
+
+impl ConsistencyProof {
+    /// Check that `new` extends `old`. Only checks the proof; verify both
+    /// heads' signatures separately.
+    pub fn verify(&self, old: &SignedTreeHead, new: &SignedTreeHead) -> Result<(), LogError> {
+        if self.old_size != old.tree_size || self.new_size != new.tree_size {
+            return Err(LogError::SizeMismatch);
+        }
+        if old.tree_size > new.tree_size {
+            return Err(LogError::InvalidTreeSize);
+        }
+        if old.tree_size == new.tree_size {
+            return if self.old_peaks.is_empty() && old.root == new.root {
+                Ok(())
+            } else {
+                Err(LogError::InvalidConsistencyProof)
+            };
+        }
+        let new_mmr_size = mmr_size(new.tree_size).ok_or(LogError::InvalidTreeSize)?;
+        let old_mmr_size = mmr_size(old.tree_size).ok_or(LogError::InvalidTreeSize)?;
+        let positions = MMR::<H256>::get_peaks(old_mmr_size);
+        if positions.len() != self.old_peaks.len()
+            || positions.len() != self.paths.len()
+            || MMR::<H256>::bag_peaks(&self.old_peaks) != old.root
+        {
+            return Err(LogError::InvalidConsistencyProof);
+        }
+        for (((position, _), peak), path) in positions
+            .iter()
+            .zip(self.old_peaks.iter())
+            .zip(self.paths.iter())
+        {
+            if path.len() > MAX_PATH_LEN {
+                return Err(LogError::InvalidConsistencyProof);
+            }
+            let proof = MMRProof::new(*peak, *position, path.clone(), new_mmr_size, new.root);
+            if !MMR::<H256>::verify_proof(&proof) {
+                return Err(LogError::InvalidConsistencyProof);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The log operator's side: entries, the MMR over them, and the signing key.
+pub struct TransparencyLog {
+    key: KeyPair,
+    mmr: MMR<H256>,
+    entries: Vec<Vec<u8>>,
+}
+
+impl TransparencyLog {
+    pub fn new(key: KeyPair) -> Self {
+        TransparencyLog {
+            key,
+            mmr: MMR::new(&[]),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+
+    /// Append `entry`, returning its index.
+    pub fn append(&mut self, entry: &[u8]) -> u64 {
+        self.mmr.insert(&[entry_hash(entry)]);
+        self.entries.push(entry.to_vec());
+        self.entries.len() as u64 - 1
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entry(&self, index: u64) -> Option<&[u8]> {
+        self.entries.get(index as usize).map(|e| &e[..])
+    }
+
+    pub fn root(&self) -> H256 {
+        self.mmr.get_root()
+    }
+
+    /// Sign the current tree head with the current time.
+    pub fn signed_tree_head(&self) -> SignedTreeHead {
+        self.signed_tree_head_at(now())
+    }
+
+    pub fn signed_tree_head_at(&self, timestamp: u64) -> SignedTreeHead {
+        let root = self.root();
+        let tree_size = self.len();
+        SignedTreeHead {
+            tree_size,
+            root,
+            timestamp,
+            signature: self
+                .key
+                .sign(&SignedTreeHead::payload(tree_size, &root, timestamp)),
+        }
+    }
+
+    /// Inclusion proof of entry `index` in the current tree.
+    pub fn inclusion_proof(&self, index: u64) -> Result<InclusionProof, LogError> {
+        if index >= self.len() {
+            return Err(LogError::IndexOutOfRange);
+        }
+        let position = MMR::<H256>::convert_to_mmr_idx(index as usize);
+        Ok(InclusionProof {
+            leaf_index: index,
+            tree_size: self.len(),
+            path: self.mmr.proof(position).proofs().to_vec(),
+        })
+    }
+
+    /// Consistency proof from the tree of `old_size` entries to the current tree.
+    pub fn consistency_proof(&self, old_size: u64) -> Result<ConsistencyProof, LogError> {
+        if old_size > self.len() {
+            return Err(LogError::InvalidTreeSize);
+        }
+        let mut proof = ConsistencyProof {
+            old_size,
+            new_size: self.len(),
+            old_peaks: vec![],
+            paths: vec![],
+        };
+        if old_size == self.len() {
+            return Ok(proof);
+        }
+        // nodes never change once written, so the old peaks are still in the MMR
+        let old_mmr_size = mmr_size(old_size).ok_or(LogError::InvalidTreeSize)?;
+        for (position, _) in MMR::<H256>::get_peaks(old_mmr_size) {
+            proof.old_peaks.push(self.mmr.get_hash_at(position));
+            proof.paths.push(self.mmr.proof(position).proofs().to_vec());
+        }
+        Ok(proof)
+    }
+}
+
+/// A client of one log: checks heads and proofs and watches for split views.
+#[derive(Debug, Clone)]
+pub struct LogVerifier {
+    log_key: PublicKey,
+    /// every verified head, by size
+    heads: BTreeMap<u64, SignedTreeHead>,
+    latest: Option<SignedTreeHead>,
+}
+
+impl LogVerifier {
+    pub fn new(log_key: PublicKey) -> Self {
+        LogVerifier {
+            log_key,
+            heads: BTreeMap::new(),
+            latest: None,
+        }
+    }
+
+    /// The newest head accepted through `update`.
+    pub fn latest(&self) -> Option<&SignedTreeHead> {
+        self.latest.as_ref()
+    }
+
+    /// Record a head seen anywhere, e.g. gossiped by another client. Fails with
+    /// `SplitView` if the log signed a different root for the same size.
+    pub fn observe(&mut self, head: &SignedTreeHead) -> Result<(), LogError> {
+        head.verify(&self.log_key)?;
+        if let Some(known) = self.heads.get(&head.tree_size) {
+            if known.root != head.root {
+                return Err(LogError::SplitView(Box::new((known.clone(), head.clone()))));
+            }
+            return Ok(());
+        }
+        self.heads.insert(head.tree_size, head.clone());
+        Ok(())
+    }
+
+    /// Move to `head`, which must extend the latest head according to `proof`.
+    /// The first head is accepted on its signature alone (trust on first use).
+    pub fn update(
+        &mut self,
+        head: &SignedTreeHead,
+        proof: &ConsistencyProof,
+    ) -> Result<(), LogError> {
+        head.verify(&self.log_key)?;
+        // record the head first, so a fork of a known size shows up as a split
+        // view and not just as a bad consistency proof
+        self.observe(head)?;
+        if let Some(latest) = &self.latest {
+            if head.tree_size < latest.tree_size {
+                return Err(LogError::InvalidTreeSize);
+            }
+            proof.verify(latest, head)?;
+        }
+        self.latest = Some(head.clone());
+        Ok(())
+    }
+
+    /// Check that `entry` is in the log as of `head`.
+    pub fn verify_inclusion(
+        &self,
+        head: &SignedTreeHead,
+        entry: &[u8],
+        proof: &InclusionProof,
+    ) -> Result<(), LogError> {
+        head.verify(&self.log_key)?;
+        proof.verify(head, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_with(count: usize) -> TransparencyLog {
+        let mut log = TransparencyLog::new(KeyPair::from_seed(&[3u8; 32]).unwrap());
+        for i in 0..count {
+            log.append(format!("entry {}", i).as_bytes());
+        }
+        log
+    }
+
+    #[test]
+    fn peaks_bag_to_root() {
+        let mut log = log_with(0);
+        assert_eq!(log.root(), MMR::<H256>::bag_peaks(&[]));
+        for i in 0..40u64 {
+            log.append(&i.to_be_bytes());
+            let peaks: Vec<H256> = MMR::<H256>::get_peaks(mmr_size(log.len()).unwrap())
+                .iter()
+                .map(|(position, _)| log.mmr.get_hash_at(*position))
+                .collect();
+            assert_eq!(MMR::<H256>::bag_peaks(&peaks), log.root());
+            assert_eq!(Some(log.mmr.get_size()), mmr_size(log.len()));
+        }
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        for count in 1..=20 {
+            let log = log_with(count);
+            let head = log.signed_tree_head_at(1_600_000_000);
+            assert_eq!(head.verify(&log.public_key()), Ok(()));
+            let verifier = LogVerifier::new(log.public_key());
+            for index in 0..count as u64 {
+                let proof = log.inclusion_proof(index).unwrap();
+                let entry = log.entry(index).unwrap();
+                assert_eq!(verifier.verify_inclusion(&head, entry, &proof), Ok(()));
+                assert_eq!(
+                    proof.verify(&head, b"not in the log"),
+                    Err(LogError::InvalidInclusionProof)
+                );
+            }
+            assert_eq!(
+                log.inclusion_proof(count as u64),
+                Err(LogError::IndexOutOfRange)
+            );
+        }
+
+        let log = log_with(6);
+        let head = log.signed_tree_head_at(1);
+        let proof = log.inclusion_proof(2).unwrap();
+        let mut moved = proof.clone();
+        moved.leaf_index = 3;
+        assert!(moved.verify(&head, b"entry 2").is_err());
+        let mut forged = head.clone();
+        forged.timestamp += 1;
+        assert_eq!(
+            LogVerifier::new(log.public_key()).verify_inclusion(&forged, b"entry 2", &proof),
+            Err(LogError::InvalidSignature)
+        );
+        let older = log_with(5).signed_tree_head_at(1);
+        assert_eq!(
+            proof.verify(&older, b"entry 2"),
+            Err(LogError::SizeMismatch)
+        );
+    }
+
+    #[test]
+    fn oversized_proofs_are_rejected() {
+        let log = log_with(3);
+        let mut head = log.signed_tree_head_at(1);
+        head.tree_size = u64::MAX;
+        let huge = InclusionProof {
+            leaf_index: 1,
+            tree_size: u64::MAX,
+            path: vec![H256::default(); 3],
+        };
+        assert_eq!(
+            huge.verify(&head, b"entry 1"),
+            Err(LogError::InvalidTreeSize)
+        );
+
+        // the largest accepted size with forged paths up to the cap
+        head.tree_size = MAX_TREE_SIZE;
+        for leaf_index in [0, 1, MAX_TREE_SIZE / 2, MAX_TREE_SIZE - 1].iter() {
+            for len in [1, 40, MAX_PATH_LEN].iter() {
+                let forged = InclusionProof {
+                    leaf_index: *leaf_index,
+                    tree_size: MAX_TREE_SIZE,
+                    path: vec![H256::default(); *len],
+                };
+                assert_eq!(
+                    forged.verify(&head, b"entry 1"),
+                    Err(LogError::InvalidInclusionProof)
+                );
+            }
+        }
+        let too_long = InclusionProof {
+            leaf_index: 0,
+            tree_size: MAX_TREE_SIZE,
+            path: vec![H256::default(); MAX_PATH_LEN + 1],
+        };
+        assert_eq!(
+            too_long.verify(&head, b"entry 1"),
+            Err(LogError::InvalidInclusionProof)
+        );
+
+        let old = log.signed_tree_head_at(1);
+        let consistency = ConsistencyProof {
+            old_size: 3,
+            new_size: u64::MAX,
+            old_peaks: vec![],
+            paths: vec![],
+        };
+        let mut new = old.clone();
+        new.tree_size = u64::MAX;
+        assert_eq!(
+            consistency.verify(&old, &new),
+            Err(LogError::InvalidTreeSize)
+        );
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        let mut log = log_with(0);
+        let mut heads = vec![log.signed_tree_head_at(0)];
+        for i in 0..24 {
+            log.append(format!("entry {}", i).as_bytes());
+            heads.push(log.signed_tree_head_at(i + 1));
+        }
+        let latest = heads.last().unwrap();
+        for old in heads.iter() {
+            let proof = log.consistency_proof(old.tree_size).unwrap();
+            assert_eq!(proof.verify(old, latest), Ok(()));
+        }
+        assert_eq!(log.consistency_proof(25), Err(LogError::InvalidTreeSize));
+
+        // a log that rewrote entry 3 can't prove consistency with its old head
+        let mut rewritten = log_with(3);
+        rewritten.append(b"rewritten");
+        for i in 4..24 {
+            rewritten.append(format!("entry {}", i).as_bytes());
+        }
+        let rewritten_head = rewritten.signed_tree_head_at(30);
+        for old in heads[4..].iter() {
+            let proof = rewritten.consistency_proof(old.tree_size).unwrap();
+            assert_eq!(
+                proof.verify(old, &rewritten_head),
+                Err(LogError::InvalidConsistencyProof)
+            );
+        }
+        let mut tampered = log.consistency_proof(5).unwrap();
+        tampered.paths[0].push(H256::default());
+        assert!(tampered.verify(&heads[5], latest).is_err());
+    }
+
+    #[test]
+    fn verifier_tracks_heads_and_detects_split_views() {
+        let mut log = log_with(3);
+        let mut verifier = LogVerifier::new(log.public_key());
+        let first = log.signed_tree_head_at(10);
+        let empty_proof = log.consistency_proof(3).unwrap();
+        assert_eq!(verifier.update(&first, &empty_proof), Ok(()));
+
+        log.append(b"entry 3");
+        let second = log.signed_tree_head_at(20);
+        let proof = log.consistency_proof(3).unwrap();
+        assert_eq!(verifier.update(&second, &proof), Ok(()));
+        assert_eq!(verifier.latest(), Some(&second));
+        // going back is refused
+        assert_eq!(
+            verifier.update(&first, &empty_proof),
+            Err(LogError::InvalidTreeSize)
+        );
+
+        // the same log key signing another history of the same size
+        let mut fork = log_with(3);
+        fork.append(b"something else");
+        let forked = fork.signed_tree_head_at(21);
+        match verifier.observe(&forked) {
+            Err(LogError::SplitView(heads)) => {
+                assert_eq!(heads.0, second);
+                assert_eq!(heads.1, forked);
+            }
+            other => panic!("expected a split view, got {:?}", other),
+        }
+        // a re-signed head with the same root is fine
+        assert_eq!(verifier.observe(&log.signed_tree_head_at(99)), Ok(()));
+
+        let stranger = TransparencyLog::new(KeyPair::from_seed(&[4u8; 32]).unwrap());
+        assert_eq!(
+            verifier.observe(&stranger.signed_tree_head()),
+            Err(LogError::InvalidSignature)
+        );
+        let bytes = bincode::serialize(&second).unwrap();
+        assert_eq!(
+            bincode::deserialize::<SignedTreeHead>(&bytes).unwrap(),
+            second
+        );
+    }
+
+    #[test]
+    fn update_detects_split_view_of_latest_size() {
+        let mut log = log_with(3);
+        let mut verifier = LogVerifier::new(log.public_key());
+        assert_eq!(
+            verifier.update(
+                &log.signed_tree_head_at(10),
+                &log.consistency_proof(3).unwrap()
+            ),
+            Ok(())
+        );
+        log.append(b"entry 3");
+        let latest = log.signed_tree_head_at(20);
+        assert_eq!(
+            verifier.update(&latest, &log.consistency_proof(3).unwrap()),
+            Ok(())
+        );
+
+        // the log hands this client a second head for its latest size
+        let mut fork = log_with(3);
+        fork.append(b"something else");
+        let forked = fork.signed_tree_head_at(21);
+        match verifier.update(&forked, &fork.consistency_proof(4).unwrap()) {
+            Err(LogError::SplitView(heads)) => {
+                assert_eq!(heads.0, latest);
+                assert_eq!(heads.1, forked);
+            }
+            other => panic!("expected a split view, got {:?}", other),
+        }
+        assert_eq!(verifier.latest(), Some(&latest));
+    }
+}
@@ -0,0 +1,609 @@
+//! Typed structured data signing in the style of EIP-712.
+//!
+//! Instead of signing raw bytes, a message is a value of a named struct type
+//! from a `Schema`, hashed together with a `Domain` naming the application,
+//! its version and chain id:
+//!
+//! ```notrust
+//!   encodeType(T)   = "T(type1 name1,type2 name2,...)" || encodeType(R) for every
+//!                     struct R reachable from T, sorted by name
+//!   hashStruct(s)   = H(H(encodeType(T)) || encodeData(s))
+//!   encodeData(s)   = one 32-byte word per field, in schema order:
+//!                       bool, uint64      big endian, zero padded on the left
+//!                       int64             big endian two's complement, sign extended
+//!                       bytes32, pubkey   the 32 bytes
+//!                       string, bytes     H(contents)
+//!                       T[]               H(word(e1) || word(e2) || ...)
+//!                       struct            hashStruct
+//!   domain          = hashStruct(Domain { name, version, chainId })
+//!   digest          = H(0x19 0x01 || domain || hashStruct(message))
+//! ```
+//!
+//! The Ed25519 signature covers `digest`. Two messages of different types, or
+//! the same message for another application, version or chain, never share a
+//! digest. `H` is SHA-256, independent of the build's `HASH_TYPE`, so debug and
+//! release builds agree on signatures.
+use crate::hash::{Hasher, HasherType, H256};
+use crate::key_pair::{KeyPair, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const DOMAIN_TYPE: &str = "Domain(string name,string version,uint64 chainId)";
+const DIGEST_PREFIX: [u8; 2] = [0x19, 0x01];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedDataError {
+    /// a type name or type expression that can't be parsed
+    InvalidType(String),
+    /// a struct type is not defined in the schema
+    UnknownType(String),
+    DuplicateType(String),
+    DuplicateField(String),
+    MissingField(String),
+    UnexpectedField(String),
+    /// the value doesn't have the type the schema gives the field
+    TypeMismatch(String),
+    InvalidSignature,
+}
+
+impl std::fmt::Display for TypedDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypedDataError::InvalidType(t) => write!(f, "invalid type {}", t),
+            TypedDataError::UnknownType(t) => write!(f, "unknown type {}", t),
+            TypedDataError::DuplicateType(t) => write!(f, "type {} defined twice", t),
+            TypedDataError::DuplicateField(n) => write!(f, "field {} defined twice", n),
+            TypedDataError::MissingField(n) => write!(f, "missing field {}", n),
+            TypedDataError::UnexpectedField(n) => write!(f, "unexpected field {}", n),
+            TypedDataError::TypeMismatch(n) => write!(f, "wrong value type for {}", n),
+            TypedDataError::InvalidSignature => write!(f, "typed data signature is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for TypedDataError {}
+
+/// The type of a struct field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FieldType {
+    Bool,
+    Uint64,
+    Int64,
+    Bytes32,
+    /// an Ed25519 public key
+    PublicKey,
+    Bytes,
+    String,
+    Array(Box<FieldType>),
+    Struct(String),
+}
+
+impl FieldType {
+    /// Parse a type expression like `uint64`, `string[]` or `Person`.
+    pub fn parse(expression: &str) -> Result<FieldType, TypedDataError> {
+        if let Some(element) = expression.strip_suffix("[]") {
+            return Ok(FieldType::Array(Box::new(FieldType::parse(element)?)));
+        }
+        Ok(match expression {
+            "bool" => FieldType::Bool,
+            "uint64" => FieldType::Uint64,
+            "int64" => FieldType::Int64,
+            "bytes32" => FieldType::Bytes32,
+            "pubkey" => FieldType::PublicKey,
+            "bytes" => FieldType::Bytes,
+            "string" => FieldType::String,
+            name if is_identifier(name) => FieldType::Struct(name.to_string()),
+            _ => return Err(TypedDataError::InvalidType(expression.to_string())),
+        })
+    }
+
+    fn collect_structs<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            FieldType::Array(element) => element.collect_structs(out),
+            FieldType::Struct(name) => out.push(name),
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FieldType::Bool => write!(f, "bool"),
+            FieldType::Uint64 => write!(f, "uint64"),
+            FieldType::Int64 => write!(f, "int64"),
+            FieldType::Bytes32 => write!(f, "bytes32"),
+            FieldType::PublicKey => write!(f, "pubkey"),
+            FieldType::Bytes => write!(f, "bytes"),
+            FieldType::String => write!(f, "string"),
+            FieldType::Array(element) => write!(f, "{}[]", element),
+            FieldType::Struct(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub field_type: FieldType,
+}
+
+/// The struct types messages can be built from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    types: BTreeMap<String, Vec<Field>>,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Define struct `name` with `(field name, type expression)` pairs, in order.
+    /// Referenced struct types may be added later.
+    pub fn add_type(&mut self, name: &str, fields: &[(&str, &str)]) -> Result<(), TypedDataError> {
+        if FieldType::parse(name) != Ok(FieldType::Struct(name.to_string())) {
+            return Err(TypedDataError::InvalidType(name.to_string()));
+        }
+        if self.types.contains_key(name) {
+            return Err(TypedDataError::DuplicateType(name.to_string()));
+        }
+        let mut parsed: Vec<Field> = Vec::with_capacity(fields.len());
+        for (field_name, expression) in fields.iter() {
+            if !is_identifier(field_name) {
+                return Err(TypedDataError::InvalidType(field_name.to_string()));
+            }
+            if parsed.iter().any(|f| f.name == *field_name) {
+                return Err(TypedDataError::DuplicateField(field_name.to_string()));
+            }
+            parsed.push(Field {
+                name: field_name.to_string(),
+                field_type: FieldType::parse(expression)?,
+            });
+        }
+        self.types.insert(name.to_string(), parsed);
+        Ok(())
+    }
+
+    pub fn with_type(
+        mut self,
+        name: &str,
+        fields: &[(&str, &str)],
+    ) -> Result<Schema, TypedDataError> {
+        self.add_type(name, fields)?;
+        Ok(self)
+    }
+
+    pub fn fields(&self, name: &str) -> Option<&[Field]> {
+        self.types.get(name).map(|f| &f[..])
+    }
+
+    fn lookup(&self, name: &str) -> Result<&[Field], TypedDataError> {
+        self.fields(name)
+            .ok_or_else(|| TypedDataError::UnknownType(name.to_string()))
+    }
+
+    /// `name` followed by every struct it references, e.g.
+    /// `Mail(Person from,Person to,string contents)Person(string name,pubkey key)`.
+    pub fn encode_type(&self, name: &str) -> Result<String, TypedDataError> {
+        let mut dependencies = BTreeSet::new();
+        let mut pending = vec![name];
+        while let Some(current) = pending.pop() {
+            for field in self.lookup(current)? {
+                let mut referenced = vec![];
+                field.field_type.collect_structs(&mut referenced);
+                for r in referenced {
+                    if r != name && dependencies.insert(r) {
+                        pending.push(r);
+                    }
+                }
+            }
+        }
+        let mut encoded = String::new();
+        for t in std::iter::once(name).chain(dependencies) {
+            let fields: Vec<String> = self
+                .lookup(t)?
+                .iter()
+                .map(|f| format!("{} {}", f.field_type, f.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", t, fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    pub fn type_hash(&self, name: &str) -> Result<H256, TypedDataError> {
+        Ok(hash(self.encode_type(name)?.as_bytes()))
+    }
+
+    /// `hashStruct` of `value` as an instance of struct `name`.
+    pub fn hash_struct(&self, name: &str, value: &Value) -> Result<H256, TypedDataError> {
+        let values = match value {
+            Value::Struct(values) => values,
+            _ => return Err(TypedDataError::TypeMismatch(name.to_string())),
+        };
+        let fields = self.lookup(name)?;
+        if let Some(extra) = values
+            .keys()
+            .find(|k| !fields.iter().any(|f| &f.name == *k))
+        {
+            return Err(TypedDataError::UnexpectedField(extra.clone()));
+        }
+        let mut hasher = Hasher::new(&HasherType::RingSHA256);
+        hasher.update(self.type_hash(name)?.as_ref());
+        for field in fields {
+            let value = values
+                .get(&field.name)
+                .ok_or_else(|| TypedDataError::MissingField(field.name.clone()))?;
+            hasher.update(&self.encode_value(&field.name, &field.field_type, value)?);
+        }
+        Ok(hasher.finish())
+    }
+
+    fn encode_value(
+        &self,
+        field: &str,
+        field_type: &FieldType,
+        value: &Value,
+    ) -> Result<[u8; 32], TypedDataError> {
+        let mut word = [0u8; 32];
+        match (field_type, value) {
+            (FieldType::Bool, Value::Bool(b)) => word[31] = *b as u8,
+            (FieldType::Uint64, Value::Uint(n)) => word[24..].copy_from_slice(&n.to_be_bytes()),
+            (FieldType::Int64, Value::Int(n)) => {
+                if *n < 0 {
+                    word = [0xff; 32];
+                }
+                word[24..].copy_from_slice(&n.to_be_bytes());
+            }
+            (FieldType::Bytes32, Value::Bytes32(h)) => word.copy_from_slice(h.as_ref()),
+            (FieldType::PublicKey, Value::PublicKey(key)) => word.copy_from_slice(key.as_bytes()),
+            (FieldType::Bytes, Value::Bytes(bytes)) => word = hash(bytes).into(),
+            (FieldType::String, Value::String(s)) => word = hash(s.as_bytes()).into(),
+            (FieldType::Array(element), Value::Array(items)) => {
+                let mut hasher = Hasher::new(&HasherType::RingSHA256);
+                for item in items {
+                    hasher.update(&self.encode_value(field, element, item)?);
+                }
+                word = hasher.finish().into();
+            }
+            (FieldType::Struct(name), Value::Struct(_)) => {
+                word = self.hash_struct(name, value)?.into()
+            }
+            _ => return Err(TypedDataError::TypeMismatch(field.to_string())),
+        }
+        Ok(word)
+    }
+}
+
+/// A field value. Structs map field names to values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+    Bool(bool),
+    Uint(u64),
+    Int(i64),
+    Bytes32(H256),
+    PublicKey(PublicKey),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<Value>),
+    Struct(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// A struct value from `(field name, value)` pairs.
+    pub fn from_fields(fields: Vec<(&str, Value)>) -> Value {
+        Value::Struct(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+/// Which application, version and chain a signature is meant for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Domain {
+    pub name: String,
+    pub version: String,
+    pub chain_id: u64,
+}
+
+impl Domain {
+    pub fn new(name: &str, version: &str, chain_id: u64) -> Domain {
+        Domain {
+            name: name.to_string(),
+            version: version.to_string(),
+            chain_id,
+        }
+    }
+
+    /// `hashStruct` of the domain.
+    pub fn separator(&self) -> H256 {
+        let mut chain_id = [0u8; 32];
+        chain_id[24..].copy_from_slice(&self.chain_id.to_be_bytes());
+        let mut hasher = Hasher::new(&HasherType::RingSHA256);
+        hasher.update(hash(DOMAIN_TYPE.as_bytes()).as_ref());
+        hasher.update(hash(self.name.as_bytes()).as_ref());
+        hasher.update(hash(self.version.as_bytes()).as_ref());
+        hasher.update(&chain_id);
+        hasher.finish()
+    }
+}
+
+/// A message checked against its schema, ready to be signed or verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedData {
+    domain: Domain,
+    primary_type: String,
+    message: Value,
+    digest: H256,
+}
+
+impl TypedData {
+    /// Fails if `message` is not a valid `primary_type` under `schema`.
+    pub fn new(
+        schema: &Schema,
+        domain: Domain,
+        primary_type: &str,
+        message: Value,
+    ) -> Result<TypedData, TypedDataError> {
+        let message_hash = schema.hash_struct(primary_type, &message)?;
+        let mut hasher = Hasher::new(&HasherType::RingSHA256);
+        hasher.update(&DIGEST_PREFIX);
+        hasher.update(domain.separator().as_ref());
+        hasher.update(message_hash.as_ref());
+        Ok(TypedData {
+            domain,
+            primary_type: primary_type.to_string(),
+            message,
+            digest: hasher.finish(),
+        })
+    }
+
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    pub fn primary_type(&self) -> &str {
+        &self.primary_type
+    }
+
+    pub fn message(&self) -> &Value {
+        &self.message
+    }
+
+    /// The 32 bytes that get signed.
+    pub fn digest(&self) -> H256 {
+        self.digest
+    }
+
+    pub fn sign(&self, key: &KeyPair) -> Signature {
+        key.sign(self.digest.as_ref())
+    }
+
+    pub fn verify(&self, key: &PublicKey, signature: &Signature) -> Result<(), TypedDataError> {
+        if key.verify(self.digest.as_ref(), signature) {
+            Ok(())
+        } else {
+            Err(TypedDataError::InvalidSignature)
+        }
+    }
+}
+
+fn hash(data: &[u8]) -> H256 {
+    let mut hasher = Hasher::new(&HasherType::RingSHA256);
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail_schema() -> Schema {
+        Schema::new()
+            .with_type(
+                "Mail",
+                &[
+                    ("from", "Person"),
+                    ("to", "Person[]"),
+                    ("contents", "string"),
+                ],
+            )
+            .unwrap()
+            .with_type("Person", &[("name", "string"), ("key", "pubkey")])
+            .unwrap()
+    }
+
+    fn person(name: &str, seed: u8) -> Value {
+        let key = KeyPair::from_seed(&[seed; 32]).unwrap().public_key();
+        Value::from_fields(vec![
+            ("name", Value::String(name.to_string())),
+            ("key", Value::PublicKey(key)),
+        ])
+    }
+
+    fn mail(contents: &str) -> Value {
+        Value::from_fields(vec![
+            ("from", person("Cow", 1)),
+            ("to", Value::Array(vec![person("Bob", 2)])),
+            ("contents", Value::String(contents.to_string())),
+        ])
+    }
+
+    #[test]
+    fn type_encoding() {
+        let schema = mail_schema();
+        assert_eq!(
+            schema.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person[] to,string contents)Person(string name,pubkey key)"
+        );
+        assert_eq!(
+            schema.encode_type("Person").unwrap(),
+            "Person(string name,pubkey key)"
+        );
+        assert_eq!(
+            schema.type_hash("Person").unwrap(),
+            hash(b"Person(string name,pubkey key)")
+        );
+        assert_eq!(
+            FieldType::parse("uint64[][]").unwrap().to_string(),
+            "uint64[][]"
+        );
+        // recursive types list themselves once
+        let tree = Schema::new()
+            .with_type("Node", &[("value", "int64"), ("children", "Node[]")])
+            .unwrap();
+        assert_eq!(
+            tree.encode_type("Node").unwrap(),
+            "Node(int64 value,Node[] children)"
+        );
+        let leaf = Value::from_fields(vec![
+            ("value", Value::Int(-1)),
+            ("children", Value::Array(vec![])),
+        ]);
+        let root = Value::from_fields(vec![
+            ("value", Value::Int(1)),
+            ("children", Value::Array(vec![leaf])),
+        ]);
+        assert!(tree.hash_struct("Node", &root).is_ok());
+    }
+
+    #[test]
+    fn known_digest() {
+        // computed independently with Python's hashlib
+        let data = TypedData::new(
+            &mail_schema(),
+            Domain::new("Ether Mail", "1", 1),
+            "Mail",
+            mail("Hello, Bob!"),
+        )
+        .unwrap();
+        assert_eq!(
+            Domain::new("Ether Mail", "1", 1).separator(),
+            H256::from(hex!(
+                "bf1a6d39bb31fc046daf0494b4e82458dec1f64d57b831245f8424e814018ea0"
+            ))
+        );
+        assert_eq!(
+            data.digest(),
+            H256::from(hex!(
+                "4e0619e661b36d45d466b35cb650cb0e0ac8d8bc7feeffe72e5dced0c705e592"
+            ))
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let schema = mail_schema();
+        let key = KeyPair::from_seed(&[1u8; 32]).unwrap();
+        let domain = Domain::new("Ether Mail", "1", 1);
+        let data = TypedData::new(&schema, domain.clone(), "Mail", mail("Hello, Bob!")).unwrap();
+        let signature = data.sign(&key);
+        assert_eq!(data.verify(&key.public_key(), &signature), Ok(()));
+
+        // the same signature doesn't carry over to another domain or message
+        let others = [
+            TypedData::new(
+                &schema,
+                Domain::new("Other Mail", "1", 1),
+                "Mail",
+                mail("Hello, Bob!"),
+            ),
+            TypedData::new(
+                &schema,
+                Domain::new("Ether Mail", "2", 1),
+                "Mail",
+                mail("Hello, Bob!"),
+            ),
+            TypedData::new(
+                &schema,
+                Domain::new("Ether Mail", "1", 5),
+                "Mail",
+                mail("Hello, Bob!"),
+            ),
+            TypedData::new(&schema, domain.clone(), "Mail", mail("Hello, Eve!")),
+            TypedData::new(&schema, domain, "Person", person("Cow", 1)),
+        ];
+        for other in others.iter() {
+            let other = other.as_ref().unwrap();
+            assert_ne!(other.digest(), data.digest());
+            assert_eq!(
+                other.verify(&key.public_key(), &signature),
+                Err(TypedDataError::InvalidSignature)
+            );
+        }
+        // nor is it a signature over the struct hash alone
+        let struct_hash = schema.hash_struct("Mail", data.message()).unwrap();
+        assert!(!key.public_key().verify(struct_hash.as_ref(), &signature));
+    }
+
+    #[test]
+    fn rejects_invalid_schemas_and_values() {
+        let mut schema = mail_schema();
+        assert_eq!(
+            schema.add_type("Person", &[("name", "string")]),
+            Err(TypedDataError::DuplicateType("Person".into()))
+        );
+        assert_eq!(
+            schema.add_type("Bad", &[("a", "string"), ("a", "bool")]),
+            Err(TypedDataError::DuplicateField("a".into()))
+        );
+        assert_eq!(
+            schema.add_type("Bad", &[("a", "map<string>")]),
+            Err(TypedDataError::InvalidType("map<string>".into()))
+        );
+        assert_eq!(
+            schema.add_type("uint64", &[("a", "bool")]),
+            Err(TypedDataError::InvalidType("uint64".into()))
+        );
+        schema.add_type("Memo", &[("author", "Unknown")]).unwrap();
+        assert_eq!(
+            schema.encode_type("Memo"),
+            Err(TypedDataError::UnknownType("Unknown".into()))
+        );
+
+        let domain = Domain::new("Ether Mail", "1", 1);
+        let check = |value: Value| TypedData::new(&schema, domain.clone(), "Person", value);
+        assert_eq!(
+            check(Value::from_fields(vec![(
+                "name",
+                Value::String("Cow".into())
+            )])),
+            Err(TypedDataError::MissingField("key".into()))
+        );
+        let mut extra = person("Cow", 1);
+        if let Value::Struct(fields) = &mut extra {
+            fields.insert("age".into(), Value::Uint(3));
+        }
+        assert_eq!(
+            check(extra),
+            Err(TypedDataError::UnexpectedField("age".into()))
+        );
+        assert_eq!(
+            check(Value::from_fields(vec![
+                ("name", Value::Bytes(b"Cow".to_vec())),
+                ("key", Value::PublicKey(PublicKey::default())),
+            ])),
+            Err(TypedDataError::TypeMismatch("name".into()))
+        );
+        assert_eq!(
+            check(Value::String("Cow".into())),
+            Err(TypedDataError::TypeMismatch("Person".into()))
+        );
+        assert_eq!(
+            TypedData::new(&schema, domain, "Letter", person("Cow", 1)),
+            Err(TypedDataError::UnknownType("Letter".into()))
+        );
+    }
+}